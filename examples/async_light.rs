@@ -0,0 +1,27 @@
+use tradfri_gateway::{AsyncDevice, AsyncTradfriGateway};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let session_key = "enter pre shared key generated from gateway code";
+    let identifier = "enter identifier generated along with the pre shared key";
+    let gateway =
+        AsyncTradfriGateway::from_identifier_and_session_key(identifier, session_key).await?;
+
+    // Toggle all your lights
+    for device in gateway.devices().await? {
+        match device {
+            Ok(AsyncDevice::RemoteControl) => (),
+            Ok(AsyncDevice::Light(mut light)) => {
+                if light.is_on() {
+                    light.off().await?;
+                } else {
+                    light.on().await?;
+                }
+                println!("light {:#?}", light);
+            }
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    Ok(())
+}
@@ -3,13 +3,13 @@ use tradfri_gateway::{Device, TradfriGateway};
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Connect with gateway code
     let gateway_code = "enter gateway code from the underside of your TRÅDFRI gateway";
-    let mut gateway = TradfriGateway::from_gateway_code(gateway_code)?;
+    let gateway = TradfriGateway::from_gateway_code(gateway_code)?;
     println!("{:#?}", gateway);
 
     // Connect with identifier and session key once created
     // let session_key = "enter pre shared key generated from gateway code";
     // let identifier = "enter identifier generated along with the pre shared key";
-    // let mut gateway =
+    // let gateway =
     //     TradfriGateway::from_identifier_and_session_key(identifier, session_key)?;
     // println!("{:#?}", gateway);
 
@@ -0,0 +1,19 @@
+use tradfri_gateway::{Device, TradfriGateway};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let session_key = "enter pre shared key generated from gateway code";
+    let identifier = "enter identifier generated along with the pre shared key";
+    let gateway = TradfriGateway::from_identifier_and_session_key(identifier, session_key)?;
+
+    // Print every light as soon as it is switched, by a remote, the app or
+    // another program
+    for device in gateway.observe_devices()? {
+        match device {
+            Ok(Device::RemoteControl) => (),
+            Ok(Device::Light(light)) => println!("light {:#?}", light),
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+
+    Ok(())
+}
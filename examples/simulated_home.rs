@@ -0,0 +1,29 @@
+use tradfri_gateway::{Device, Simulator, SimulatorConfig, TradfriGateway};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Start a simulated gateway with a few lights, it runs until dropped
+    let simulator = Simulator::bind("127.0.0.1:5684", SimulatorConfig::demo())?;
+
+    // Pair with the security code of the simulator, just like with a real gateway
+    let gateway = TradfriGateway::from_gateway_code_and_addr([127, 0, 0, 1], "SimulatorCode001")?;
+
+    for device in gateway.devices()? {
+        if let Device::Light(mut light) = device? {
+            light.on()?;
+            println!("light {:#?}", light);
+        }
+    }
+
+    for group in gateway.groups()? {
+        let mut group = group?;
+        group.off()?;
+        println!("group {:#?}", group);
+    }
+
+    println!(
+        "{:#}",
+        simulator.resource("15001/65537").unwrap_or_default()
+    );
+
+    Ok(())
+}
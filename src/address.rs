@@ -0,0 +1,295 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
+    str::FromStr,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Address of a gateway, an IPv4 or IPv6 address together with the scope id
+/// IPv6 link-local addresses need to pick the network interface.
+///
+/// Converts from the std address types, so anything that takes an
+/// `Into<GatewayAddr>` also takes an `IpAddr`, and parses from strings, with
+/// the scope id written after a `%`:
+///
+/// ```
+/// use tradfri_gateway::GatewayAddr;
+///
+/// let address: GatewayAddr = "fe80::1ff:fe23:4567:890a%3".parse().unwrap();
+/// assert_eq!(address.scope_id(), 3);
+///
+/// let address = GatewayAddr::from([192, 168, 1, 10]);
+/// assert_eq!(address.to_string(), "192.168.1.10");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GatewayAddr {
+    ip: IpAddr,
+    scope_id: u32,
+}
+
+impl GatewayAddr {
+    pub fn new<A: Into<IpAddr>>(ip: A) -> Self {
+        Self {
+            ip: ip.into(),
+            scope_id: 0,
+        }
+    }
+
+    /// An IPv6 address on the network interface with the given index, as
+    /// needed for link-local addresses.
+    pub fn with_scope_id(ip: Ipv6Addr, scope_id: u32) -> Self {
+        Self {
+            ip: IpAddr::V6(ip),
+            scope_id,
+        }
+    }
+
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    /// The index of the network interface of an IPv6 address, `0` if none
+    /// has been given.
+    pub fn scope_id(&self) -> u32 {
+        self.scope_id
+    }
+
+    /// Returns `true` for IPv6 link-local addresses, which can only be used
+    /// together with a scope id.
+    pub fn is_link_local(&self) -> bool {
+        match self.ip {
+            IpAddr::V4(_) => false,
+            IpAddr::V6(ip) => ip.segments()[0] & 0xffc0 == 0xfe80,
+        }
+    }
+
+    /// The address of the given port on the gateway.
+    pub(crate) fn socket_addr(&self, port: u16) -> SocketAddr {
+        match self.ip {
+            IpAddr::V4(ip) => SocketAddr::new(IpAddr::V4(ip), port),
+            IpAddr::V6(ip) => SocketAddr::V6(SocketAddrV6::new(ip, port, 0, self.scope_id)),
+        }
+    }
+
+    /// The unspecified local address of the same family, for binding the
+    /// socket that talks to the gateway.
+    pub(crate) fn bind_addr(&self) -> SocketAddr {
+        match self.ip {
+            IpAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            IpAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+        }
+    }
+}
+
+impl fmt::Display for GatewayAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.scope_id {
+            0 => write!(f, "{}", self.ip),
+            scope_id => write!(f, "{}%{}", self.ip, scope_id),
+        }
+    }
+}
+
+/// Parses an IPv4 or IPv6 address, the latter optionally in brackets and with
+/// a scope id after a `%`, either the index or the name of the interface.
+impl FromStr for GatewayAddr {
+    type Err = GatewayAddrParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || GatewayAddrParseError(s.to_string());
+        let unbracketed = match s.strip_prefix('[') {
+            Some(rest) => rest.strip_suffix(']').ok_or_else(error)?,
+            None => s,
+        };
+
+        match unbracketed.split_once('%') {
+            Some((ip, scope)) => Ok(Self::with_scope_id(
+                ip.parse().map_err(|_| error())?,
+                scope
+                    .parse()
+                    .ok()
+                    .or_else(|| interface_index(scope))
+                    .ok_or_else(error)?,
+            )),
+            None if unbracketed.len() < s.len() => Ok(Self::new(
+                unbracketed.parse::<Ipv6Addr>().map_err(|_| error())?,
+            )),
+            None => Ok(Self::new(s.parse::<IpAddr>().map_err(|_| error())?)),
+        }
+    }
+}
+
+impl From<IpAddr> for GatewayAddr {
+    fn from(ip: IpAddr) -> Self {
+        Self::new(ip)
+    }
+}
+
+impl From<Ipv4Addr> for GatewayAddr {
+    fn from(ip: Ipv4Addr) -> Self {
+        Self::new(ip)
+    }
+}
+
+impl From<Ipv6Addr> for GatewayAddr {
+    fn from(ip: Ipv6Addr) -> Self {
+        Self::new(ip)
+    }
+}
+
+impl From<[u8; 4]> for GatewayAddr {
+    fn from(octets: [u8; 4]) -> Self {
+        Self::new(octets)
+    }
+}
+
+impl From<[u16; 8]> for GatewayAddr {
+    fn from(segments: [u16; 8]) -> Self {
+        Self::new(segments)
+    }
+}
+
+/// Takes the address and the scope id, the port is ignored.
+impl From<SocketAddrV6> for GatewayAddr {
+    fn from(address: SocketAddrV6) -> Self {
+        Self::with_scope_id(*address.ip(), address.scope_id())
+    }
+}
+
+/// Takes the address and, for IPv6, the scope id, the port is ignored.
+impl From<SocketAddr> for GatewayAddr {
+    fn from(address: SocketAddr) -> Self {
+        match address {
+            SocketAddr::V4(address) => Self::new(*address.ip()),
+            SocketAddr::V6(address) => address.into(),
+        }
+    }
+}
+
+/// Serialized in its string form, such as `fe80::1ff:fe23:4567:890a%3`.
+impl Serialize for GatewayAddr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for GatewayAddr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// The index of the network interface with the given name.
+fn interface_index(name: &str) -> Option<u32> {
+    if_addrs::get_if_addrs()
+        .ok()?
+        .into_iter()
+        .find(|interface| interface.name == name)
+        .and_then(|interface| interface.index)
+}
+
+/// The indices of the network interfaces with an IPv6 link-local address,
+/// which are the interfaces a link-local gateway can be on.
+pub(crate) fn link_local_interfaces() -> Vec<u32> {
+    let mut indices: Vec<u32> = if_addrs::get_if_addrs()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|interface| {
+            matches!(interface.ip(), IpAddr::V6(ip) if GatewayAddr::new(ip).is_link_local())
+        })
+        .filter_map(|interface| interface.index)
+        .collect();
+    indices.sort_unstable();
+    indices.dedup();
+
+    indices
+}
+
+/// Error parsing a [`GatewayAddr`].
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid gateway address: {0}")]
+pub struct GatewayAddrParseError(String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINK_LOCAL: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0x1ff, 0xfe23, 0x4567, 0x890a);
+
+    #[test]
+    fn parses_ipv6_with_and_without_brackets() {
+        let expected = GatewayAddr::new(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+
+        assert_eq!("2001:db8::1".parse::<GatewayAddr>().unwrap(), expected);
+        assert_eq!("[2001:db8::1]".parse::<GatewayAddr>().unwrap(), expected);
+        assert_eq!(
+            "[fe80::1ff:fe23:4567:890a%3]"
+                .parse::<GatewayAddr>()
+                .unwrap(),
+            GatewayAddr::with_scope_id(LINK_LOCAL, 3)
+        );
+    }
+
+    #[test]
+    fn parses_scope_by_index_and_name() {
+        let address: GatewayAddr = "fe80::1ff:fe23:4567:890a%3".parse().unwrap();
+        assert_eq!(address.scope_id(), 3);
+        assert!(address.is_link_local());
+
+        let interface = if_addrs::get_if_addrs()
+            .unwrap()
+            .into_iter()
+            .find(|interface| interface.index.is_some())
+            .expect("no network interface");
+        let address: GatewayAddr = format!("fe80::1ff:fe23:4567:890a%{}", interface.name)
+            .parse()
+            .unwrap();
+        assert_eq!(Some(address.scope_id()), interface.index);
+
+        assert!("fe80::1ff:fe23:4567:890a%no-such-interface"
+            .parse::<GatewayAddr>()
+            .is_err());
+        assert!("fe80::1ff:fe23:4567:890a%".parse::<GatewayAddr>().is_err());
+    }
+
+    #[test]
+    fn rejects_stray_brackets_and_ipv4_scopes() {
+        for invalid in [
+            "[2001:db8::1",
+            "2001:db8::1]",
+            "[[2001:db8::1]]",
+            "[192.168.1.10]",
+            "192.168.1.10%3",
+            "gateway.local",
+        ] {
+            assert!(invalid.parse::<GatewayAddr>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn displays_in_parseable_form() {
+        for address in [
+            GatewayAddr::from([192, 168, 1, 10]),
+            GatewayAddr::new(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            GatewayAddr::with_scope_id(LINK_LOCAL, 3),
+        ] {
+            assert_eq!(address.to_string().parse::<GatewayAddr>().unwrap(), address);
+        }
+        assert_eq!(
+            GatewayAddr::with_scope_id(LINK_LOCAL, 3).to_string(),
+            "fe80::1ff:fe23:4567:890a%3"
+        );
+    }
+
+    #[test]
+    fn keeps_scope_of_socket_address() {
+        let socket = SocketAddr::V6(SocketAddrV6::new(LINK_LOCAL, 5684, 0, 3));
+        let address = GatewayAddr::from(socket);
+
+        assert_eq!(address, GatewayAddr::with_scope_id(LINK_LOCAL, 3));
+        assert_eq!(address.socket_addr(5684), socket);
+    }
+}
@@ -0,0 +1,5 @@
+use crate::{AsyncTradfriGateway, Device};
+
+/// Async counterpart of [`Device`], created by
+/// [`AsyncTradfriGateway::device`] and [`AsyncTradfriGateway::devices`].
+pub type AsyncDevice = Device<AsyncTradfriGateway>;
@@ -0,0 +1,57 @@
+use super::run_blocking;
+use crate::{DiscoveredGateway, DiscoveryCanceller, GatewayDiscovery, TradfriGatewayError};
+
+/// Async counterpart of [`GatewayDiscovery`].
+///
+/// Created by
+/// [`AsyncTradfriGateway::discover_gateways`](crate::AsyncTradfriGateway::discover_gateways).
+/// If a call to [`AsyncGatewayDiscovery::next`] is dropped before it
+/// finishes, the discovery ends.
+pub struct AsyncGatewayDiscovery {
+    discovery: Option<GatewayDiscovery>,
+    canceller: DiscoveryCanceller,
+}
+
+impl AsyncGatewayDiscovery {
+    pub(crate) fn new(discovery: GatewayDiscovery) -> Self {
+        Self {
+            canceller: discovery.canceller(),
+            discovery: Some(discovery),
+        }
+    }
+
+    /// Ends the discovery once the timeout, counted from now, has run out.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.discovery = self
+            .discovery
+            .map(|discovery| discovery.with_timeout(timeout));
+        self
+    }
+
+    /// Returns a handle that stops the discovery from another task.
+    pub fn canceller(&self) -> DiscoveryCanceller {
+        self.canceller.clone()
+    }
+
+    /// The gateways found so far, with their latest announcement.
+    pub fn gateways(&self) -> Vec<DiscoveredGateway> {
+        self.discovery
+            .as_ref()
+            .map(GatewayDiscovery::gateways)
+            .unwrap_or_default()
+    }
+
+    /// Waits for the next gateway, `None` once the discovery has ended.
+    pub async fn next(&mut self) -> Option<DiscoveredGateway> {
+        let mut discovery = self.discovery.take()?;
+        let (discovery, gateway) = run_blocking(move || {
+            let gateway = discovery.next();
+            Ok::<_, TradfriGatewayError>((discovery, gateway))
+        })
+        .await
+        .ok()?;
+
+        self.discovery = Some(discovery);
+        gateway
+    }
+}
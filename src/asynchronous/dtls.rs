@@ -0,0 +1,161 @@
+use std::time::{Duration, Instant};
+
+use coap::{message::packet::Packet, CoAPRequest, CoAPResponse};
+use futures::future::BoxFuture;
+use tokio::sync::Mutex;
+
+use super::{AsyncObserveChannel, AsyncTransport};
+use crate::{
+    tradfri_coap::{is_idempotent, AsyncTradfriConnection, TransmissionParameters},
+    transport::{request_error, SESSION_TIMEOUT},
+    GatewayAddr, Secret, TradfriGatewayError,
+};
+
+/// Async counterpart of [`DtlsTransport`](crate::DtlsTransport), talking to a
+/// real gateway over a non-blocking socket.
+///
+/// The session is set up on the first request and transparently
+/// re-established if the gateway drops it, and requests that fail on a lost
+/// session are sent again like they are by the blocking transport. Requests
+/// from different tasks take turns on the session, and like with the blocking
+/// transport, the others wait while one of them makes a new handshake. A
+/// request that is dropped before it finishes leaves the session usable for
+/// the next one.
+#[derive(Debug)]
+pub struct AsyncDtlsTransport {
+    address: GatewayAddr,
+    identifier: String,
+    session_key: Secret,
+    parameters: TransmissionParameters,
+    session: Mutex<Option<AsyncTradfriConnection>>,
+}
+
+impl AsyncDtlsTransport {
+    pub fn new<A: Into<GatewayAddr>, K: Into<Secret>>(
+        address: A,
+        identifier: &str,
+        session_key: K,
+    ) -> Self {
+        Self {
+            address: address.into(),
+            identifier: identifier.into(),
+            session_key: session_key.into(),
+            parameters: TransmissionParameters::default(),
+            session: Mutex::new(None),
+        }
+    }
+
+    /// Sets the overall time allowed for each request to the gateway, after
+    /// which it fails with [`TradfriGatewayError::Timeout`].
+    ///
+    /// Defaults to 30 seconds.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.parameters.request_timeout = timeout;
+        self
+    }
+
+    /// Sets the CoAP retransmission parameters used for requests.
+    pub fn with_transmission_parameters(mut self, parameters: TransmissionParameters) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    /// Sets up the session now rather than on the first request, to find out
+    /// whether the gateway answers at the address.
+    pub(crate) async fn connect(&self) -> Result<(), TradfriGatewayError> {
+        let mut session = self.session.lock().await;
+        if session.is_none() {
+            *session = Some(self.create_session().await?);
+        }
+
+        Ok(())
+    }
+
+    async fn create_session(&self) -> Result<AsyncTradfriConnection, TradfriGatewayError> {
+        let mut connection = AsyncTradfriConnection::new_with_timeout(
+            self.address,
+            self.identifier.as_bytes(),
+            self.session_key.expose(),
+            Some(SESSION_TIMEOUT),
+        )
+        .await?;
+        connection.set_transmission_parameters(self.parameters);
+
+        Ok(connection)
+    }
+
+    async fn send_request(&self, req: CoAPRequest) -> Result<CoAPResponse, TradfriGatewayError> {
+        let deadline = Instant::now() + self.parameters.request_timeout;
+        let mut session = self.session.lock().await;
+
+        if let Some(connection) = session.as_mut() {
+            let session_deadline =
+                deadline.min(Instant::now() + Duration::from_secs(SESSION_TIMEOUT));
+            match connection
+                .request_until(req.clone(), session_deadline)
+                .await
+            {
+                Ok(response) => return Ok(response),
+                Err(error) => {
+                    *session = None;
+                    if !is_idempotent(&req) {
+                        log::debug!("Gateway session lost, not resending: {}", error);
+                        return Err(request_error(error, &self.parameters));
+                    }
+                    log::debug!("Gateway session lost, reconnecting: {}", error);
+                }
+            }
+
+            if Instant::now() >= deadline {
+                return Err(TradfriGatewayError::Timeout(
+                    self.parameters.request_timeout,
+                ));
+            }
+        }
+
+        // Handshake while holding the lock, requests waiting for their turn
+        // need the new session as well.
+        let connection = session.insert(self.create_session().await?);
+        let result = connection.request_until(req, deadline).await;
+        if result.is_err() {
+            *session = None;
+        }
+
+        result.map_err(|error| request_error(error, &self.parameters))
+    }
+}
+
+impl AsyncTransport for AsyncDtlsTransport {
+    /// Sends a request over the shared session, handshaking a new session if
+    /// there is none yet or if the current one stopped working.
+    fn request(
+        &self,
+        req: CoAPRequest,
+    ) -> BoxFuture<'_, Result<CoAPResponse, TradfriGatewayError>> {
+        Box::pin(self.send_request(req))
+    }
+
+    fn open_observe_channel(
+        &self,
+    ) -> BoxFuture<'_, Result<Box<dyn AsyncObserveChannel>, TradfriGatewayError>> {
+        Box::pin(async move {
+            let channel: Box<dyn AsyncObserveChannel> = Box::new(self.create_session().await?);
+            Ok(channel)
+        })
+    }
+}
+
+impl AsyncObserveChannel for AsyncTradfriConnection {
+    fn send(&mut self, message: &Packet) -> Result<(), TradfriGatewayError> {
+        AsyncTradfriConnection::send(self, message)?;
+
+        Ok(())
+    }
+
+    fn receive(
+        &mut self,
+        timeout: Duration,
+    ) -> BoxFuture<'_, Result<Option<Packet>, TradfriGatewayError>> {
+        Box::pin(async move { Ok(self.receive_timeout(timeout).await?) })
+    }
+}
@@ -0,0 +1,685 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{FixedOffset, Utc};
+use coap::{CoAPRequest, CoAPResponse, Method};
+use serde_json::Value;
+
+use super::{run_blocking, AsyncDtlsTransport, AsyncObserveChannel, AsyncTransport};
+use crate::{
+    credentials::StoredConnection,
+    gateway::is_unreachable,
+    gateway_time,
+    pairing::{self, PairingAttempts},
+    raw::raw_request,
+    requests,
+    response_code::check_response,
+    scene,
+    tradfri_coap::TradfriAuthenticator,
+    AsyncDevice, AsyncDeviceObserver, AsyncGatewayDiscovery, AsyncGroup, AsyncGroupObserver,
+    AsyncNotificationObserver, AsyncOtaObserver, AsyncScene, CoapError, CommissioningWindow,
+    CredentialStore, Credentials, DeviceUpdate, DiscoveredGateway, DiscoveryOptions,
+    FactoryResetConfirmation, GatewayAddr, GatewayInfo, GatewayNotification, GatewayTime,
+    GroupUpdate, LightSetting, OtaStatus, PairingOptions, RawResponse, SceneUpdate, Secret,
+    TradfriGateway, TradfriGatewayError, UpdateWindow,
+};
+
+/// Async counterpart of [`TradfriGateway`], for use with tokio.
+///
+/// Requests are carried by an [`AsyncTransport`]. The `from_*` constructors
+/// talk to a real gateway over an [`AsyncDtlsTransport`], which waits for its
+/// socket without blocking a thread, and
+/// [`AsyncTradfriGateway::from_transport`] accepts any other transport, such as
+/// a [`MemoryTransport`](crate::MemoryTransport) in tests. Requests are built
+/// and responses parsed like they are by the blocking gateway.
+///
+/// Like the blocking gateway, all clones and all lights, groups and scenes
+/// created from it share one transport.
+#[derive(Debug, Clone)]
+pub struct AsyncTradfriGateway {
+    transport: Arc<dyn AsyncTransport>,
+}
+
+impl AsyncTradfriGateway {
+    pub async fn from_gateway_code(gateway_code: &str) -> Result<Self, TradfriGatewayError> {
+        Self::from_gateway_code_and_addr(Self::discover_ip().await?, gateway_code).await
+    }
+
+    pub async fn from_gateway_code_and_addr<A: Into<GatewayAddr>>(
+        address: A,
+        gateway_code: &str,
+    ) -> Result<Self, TradfriGatewayError> {
+        Self::from_credentials(&Self::pair(address, gateway_code).await?).await
+    }
+
+    /// Pairs with the gateway, see [`TradfriGateway::pair`].
+    pub async fn pair<A: Into<GatewayAddr>>(
+        address: A,
+        gateway_code: &str,
+    ) -> Result<Credentials, TradfriGatewayError> {
+        Self::pair_with(address, gateway_code, &PairingOptions::default()).await
+    }
+
+    /// Pairs with the gateway, retrying as configured in the options, see
+    /// [`TradfriGateway::pair_with`].
+    pub async fn pair_with<A: Into<GatewayAddr>>(
+        address: A,
+        gateway_code: &str,
+        options: &PairingOptions,
+    ) -> Result<Credentials, TradfriGatewayError> {
+        let address = address.into();
+        let security_code = pairing::security_code(gateway_code)?;
+        let mut attempts = PairingAttempts::new(options)?;
+
+        loop {
+            match TradfriAuthenticator::authenticate_async(
+                address,
+                attempts.identifier(),
+                &security_code,
+                options.timeout(),
+            )
+            .await
+            {
+                Ok(session_key) => {
+                    return Ok(
+                        Credentials::new(attempts.identifier(), session_key).with_address(address)
+                    )
+                }
+                Err(error) => attempts.retry(error)?,
+            }
+        }
+    }
+
+    /// Connects with stored credentials, discovering the gateway if they have
+    /// no address.
+    pub async fn from_credentials(credentials: &Credentials) -> Result<Self, TradfriGatewayError> {
+        let address = match credentials.address {
+            Some(address) => address,
+            None => Self::discover_ip().await?,
+        };
+
+        Ok(Self::from_identifier_and_session_key_and_addr(
+            address,
+            &credentials.identifier,
+            credentials.session_key.clone(),
+        ))
+    }
+
+    /// Connects with the credentials in the store, or pairs and saves them,
+    /// see [`TradfriGateway::from_credential_store`].
+    ///
+    /// The store is accessed on the calling task, which is fine for the small
+    /// file of a [`FileCredentialStore`](crate::FileCredentialStore).
+    pub async fn from_credential_store<S: CredentialStore + ?Sized>(
+        store: &S,
+        gateway_code: &str,
+    ) -> Result<Self, TradfriGatewayError> {
+        Self::from_stored_credentials(store, None, gateway_code).await
+    }
+
+    /// Like [`AsyncTradfriGateway::from_credential_store`], but for the
+    /// gateway at the given address. A stored address that differs is
+    /// replaced.
+    pub async fn from_credential_store_and_addr<
+        S: CredentialStore + ?Sized,
+        A: Into<GatewayAddr>,
+    >(
+        store: &S,
+        address: A,
+        gateway_code: &str,
+    ) -> Result<Self, TradfriGatewayError> {
+        Self::from_stored_credentials(store, Some(address.into()), gateway_code).await
+    }
+
+    async fn from_stored_credentials<S: CredentialStore + ?Sized>(
+        store: &S,
+        address: Option<GatewayAddr>,
+        gateway_code: &str,
+    ) -> Result<Self, TradfriGatewayError> {
+        let (gateway, changed) = match StoredConnection::plan(store.load()?, address) {
+            StoredConnection::Pair(address) => {
+                let address = match address {
+                    Some(address) => address,
+                    None => Self::discover_ip().await?,
+                };
+                let credentials = Self::pair(address, gateway_code).await?;
+                (
+                    Self::from_credentials(&credentials).await?,
+                    Some(credentials),
+                )
+            }
+            StoredConnection::Connect {
+                credentials,
+                changed,
+            } => {
+                let gateway = Self::from_credentials(&credentials).await?;
+                (gateway, changed.then_some(credentials))
+            }
+            StoredConnection::Reconnect(credentials) => Self::reconnect(credentials).await?,
+        };
+        if let Some(credentials) = changed {
+            store.save(&credentials)?;
+        }
+
+        Ok(gateway)
+    }
+
+    /// Connects to the stored address, or to a newly discovered one if the
+    /// stored address is missing or does not answer the handshake.
+    async fn reconnect(
+        credentials: Credentials,
+    ) -> Result<(Self, Option<Credentials>), TradfriGatewayError> {
+        let transport = |address: GatewayAddr| {
+            AsyncDtlsTransport::new(
+                address,
+                &credentials.identifier,
+                credentials.session_key.clone(),
+            )
+        };
+
+        if let Some(address) = credentials.address {
+            let transport = transport(address);
+            match transport.connect().await {
+                Ok(()) => return Ok((Self::from_transport(transport), None)),
+                Err(error) if is_unreachable(&error) => {
+                    log::debug!("Gateway does not answer at {}: {}", address, error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        let address = Self::discover_ip().await?;
+        if credentials.address == Some(address) {
+            return Err(CoapError::Unreachable.into());
+        }
+
+        let gateway = Self::from_transport(transport(address));
+        Ok((gateway, Some(credentials.with_address(address))))
+    }
+
+    pub async fn from_identifier_and_session_key<K: Into<Secret>>(
+        identifier: &str,
+        session_key: K,
+    ) -> Result<Self, TradfriGatewayError> {
+        Ok(Self::from_identifier_and_session_key_and_addr(
+            Self::discover_ip().await?,
+            identifier,
+            session_key,
+        ))
+    }
+
+    pub fn from_identifier_and_session_key_and_addr<A: Into<GatewayAddr>, K: Into<Secret>>(
+        address: A,
+        identifier: &str,
+        session_key: K,
+    ) -> Self {
+        Self::from_transport(AsyncDtlsTransport::new(address, identifier, session_key))
+    }
+
+    /// Creates a gateway that sends its requests over the given transport.
+    ///
+    /// Use this to configure an [`AsyncDtlsTransport`], for example with a
+    /// shorter request timeout, or to drive the gateway with a mock.
+    pub fn from_transport<T: AsyncTransport + 'static>(transport: T) -> Self {
+        Self {
+            transport: Arc::new(transport),
+        }
+    }
+
+    /// Fetches all devices, one request at a time over the shared session.
+    pub async fn devices(
+        &self,
+    ) -> Result<Vec<Result<AsyncDevice, TradfriGatewayError>>, TradfriGatewayError> {
+        let mut devices = Vec::new();
+        for id in self.device_ids().await?.into_iter().rev() {
+            devices.push(self.device(id).await);
+        }
+
+        Ok(devices)
+    }
+
+    pub async fn device(&self, id: u32) -> Result<AsyncDevice, TradfriGatewayError> {
+        let response = self.coap_request(requests::device(id)).await?;
+        AsyncDevice::new(self.clone(), &response.message.payload)
+            .map_err(|e| TradfriGatewayError::DeviceError(id, Box::new(e)))
+    }
+
+    async fn device_ids(&self) -> Result<Vec<u32>, TradfriGatewayError> {
+        requests::ids(&self.coap_request(requests::device_ids()).await?)
+    }
+
+    /// Fetches all groups, one request at a time over the shared session.
+    pub async fn groups(
+        &self,
+    ) -> Result<Vec<Result<AsyncGroup, TradfriGatewayError>>, TradfriGatewayError> {
+        let mut groups = Vec::new();
+        for id in self.group_ids().await?.into_iter().rev() {
+            groups.push(self.group(id).await);
+        }
+
+        Ok(groups)
+    }
+
+    pub async fn group(&self, id: u32) -> Result<AsyncGroup, TradfriGatewayError> {
+        let response = self.coap_request(requests::group(id)).await?;
+        AsyncGroup::new(self.clone(), &response.message.payload)
+            .map_err(|e| TradfriGatewayError::GroupError(id, Box::new(e)))
+    }
+
+    async fn group_ids(&self) -> Result<Vec<u32>, TradfriGatewayError> {
+        requests::ids(&self.coap_request(requests::group_ids()).await?)
+    }
+
+    /// Fetches all scenes of a group, see [`TradfriGateway::scenes`].
+    pub async fn scenes(
+        &self,
+        group_id: u32,
+    ) -> Result<Vec<Result<AsyncScene, TradfriGatewayError>>, TradfriGatewayError> {
+        let mut scenes = Vec::new();
+        for id in self.scene_ids(group_id).await?.into_iter().rev() {
+            scenes.push(self.scene(group_id, id).await);
+        }
+
+        Ok(scenes)
+    }
+
+    pub async fn scene(&self, group_id: u32, id: u32) -> Result<AsyncScene, TradfriGatewayError> {
+        let response = self.coap_request(requests::scene(group_id, id)).await?;
+        AsyncScene::new(self.clone(), group_id, &response.message.payload)
+            .map_err(|e| TradfriGatewayError::SceneError(id, Box::new(e)))
+    }
+
+    async fn scene_ids(&self, group_id: u32) -> Result<Vec<u32>, TradfriGatewayError> {
+        requests::ids(&self.coap_request(requests::scene_ids(group_id)).await?)
+    }
+
+    /// Creates a scene in a group, see [`TradfriGateway::create_scene`].
+    pub async fn create_scene(
+        &self,
+        group_id: u32,
+        name: &str,
+        lights: Vec<LightSetting>,
+    ) -> Result<AsyncScene, TradfriGatewayError> {
+        let req = requests::create_scene(group_id, name, lights)?;
+        let response = self.coap_request(req).await?;
+        self.scene(group_id, scene::created_id(&response)?).await
+    }
+
+    /// Reads the state of the gateway itself, see [`TradfriGateway::info`].
+    pub async fn info(&self) -> Result<GatewayInfo, TradfriGatewayError> {
+        let response = self.coap_request(requests::gateway_info()).await?;
+        Ok(serde_json::from_slice(&response.message.payload)?)
+    }
+
+    /// Reboots the gateway, see [`TradfriGateway::reboot`].
+    pub async fn reboot(&self) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::reboot()).await?;
+        Ok(())
+    }
+
+    /// Resets the gateway to factory defaults if the confirmation names it,
+    /// see [`TradfriGateway::factory_reset`].
+    pub async fn factory_reset(
+        &self,
+        confirmation: &FactoryResetConfirmation,
+    ) -> Result<(), TradfriGatewayError> {
+        confirmation.confirm(self.info().await?.id)?;
+
+        self.coap_request(requests::factory_reset()).await?;
+        Ok(())
+    }
+
+    /// Lets the gateway accept new devices for the given duration, see
+    /// [`TradfriGateway::open_commissioning`].
+    pub async fn open_commissioning(
+        &self,
+        duration: Duration,
+    ) -> Result<CommissioningWindow, TradfriGatewayError> {
+        let window = CommissioningWindow::open_for(duration);
+        self.set_commissioning(window.duration.as_secs()).await?;
+        Ok(window)
+    }
+
+    /// Closes the commissioning window before it runs out.
+    pub async fn close_commissioning(&self) -> Result<(), TradfriGatewayError> {
+        self.set_commissioning(0).await
+    }
+
+    async fn set_commissioning(&self, seconds: u64) -> Result<(), TradfriGatewayError> {
+        self.update_gateway(&serde_json::json!({ "9061": seconds }))
+            .await
+    }
+
+    /// Reads the firmware update status of the gateway, see
+    /// [`TradfriGateway::ota_status`].
+    pub async fn ota_status(&self) -> Result<OtaStatus, TradfriGatewayError> {
+        Ok(self.info().await?.into())
+    }
+
+    /// Makes the gateway look for new firmware, see
+    /// [`TradfriGateway::check_for_updates`].
+    pub async fn check_for_updates(&self) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::check_for_updates()).await?;
+        Ok(())
+    }
+
+    /// Installs downloaded firmware right away, see
+    /// [`TradfriGateway::start_update`].
+    pub async fn start_update(&self) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::start_update()).await?;
+        Ok(())
+    }
+
+    /// Sets the daily window in which the gateway installs updates on its own.
+    pub async fn set_update_window(
+        &self,
+        window: &UpdateWindow,
+    ) -> Result<(), TradfriGatewayError> {
+        self.update_gateway(&window.update()).await
+    }
+
+    /// Observes the firmware update status of the gateway, see
+    /// [`TradfriGateway::observe_ota`].
+    pub async fn observe_ota(&self) -> Result<AsyncOtaObserver, TradfriGatewayError> {
+        AsyncOtaObserver::ota(self).await
+    }
+
+    /// Reads the clock, time zone and NTP server of the gateway, see
+    /// [`TradfriGateway::time`].
+    pub async fn time(&self) -> Result<GatewayTime, TradfriGatewayError> {
+        let info = self.info().await?;
+        GatewayTime::from_info(info, Utc::now()).ok_or(TradfriGatewayError::TimeNotReported)
+    }
+
+    /// Sets the NTP server of the gateway, see
+    /// [`TradfriGateway::set_ntp_server`].
+    pub async fn set_ntp_server(&self, server: &str) -> Result<(), TradfriGatewayError> {
+        self.update_gateway(&gateway_time::set_ntp_server(server))
+            .await
+    }
+
+    /// Moves the gateway to another time zone, see
+    /// [`TradfriGateway::set_time_zone`].
+    pub async fn set_time_zone(&self, offset: FixedOffset) -> Result<(), TradfriGatewayError> {
+        let now = self.time().await?.utc;
+        self.update_gateway(&gateway_time::set_time_zone(now, offset))
+            .await?;
+
+        gateway_time::check_time_zone(&self.time().await?, offset)
+    }
+
+    /// Reads the notification list of the gateway, see
+    /// [`TradfriGateway::notifications`].
+    pub async fn notifications(&self) -> Result<Vec<GatewayNotification>, TradfriGatewayError> {
+        let response = self.coap_request(requests::notifications()).await?;
+        Ok(serde_json::from_slice(&response.message.payload)?)
+    }
+
+    /// Observes the notification list of the gateway, see
+    /// [`TradfriGateway::observe_notifications`].
+    pub async fn observe_notifications(
+        &self,
+    ) -> Result<AsyncNotificationObserver, TradfriGatewayError> {
+        AsyncNotificationObserver::notifications(self).await
+    }
+
+    /// Observes a single device, see [`TradfriGateway::observe_device`].
+    pub async fn observe_device(
+        &self,
+        id: u32,
+    ) -> Result<AsyncDeviceObserver, TradfriGatewayError> {
+        AsyncDeviceObserver::device(self, id).await
+    }
+
+    /// Observes every device on the gateway, see
+    /// [`TradfriGateway::observe_devices`].
+    pub async fn observe_devices(&self) -> Result<AsyncDeviceObserver, TradfriGatewayError> {
+        AsyncDeviceObserver::devices(self).await
+    }
+
+    /// Observes a single group, see [`TradfriGateway::observe_group`].
+    pub async fn observe_group(&self, id: u32) -> Result<AsyncGroupObserver, TradfriGatewayError> {
+        AsyncGroupObserver::group(self, id).await
+    }
+
+    /// Observes every group on the gateway, see
+    /// [`TradfriGateway::observe_groups`].
+    pub async fn observe_groups(&self) -> Result<AsyncGroupObserver, TradfriGatewayError> {
+        AsyncGroupObserver::groups(self).await
+    }
+
+    /// Reads the resource at a raw path, see [`TradfriGateway::get`].
+    pub async fn get(&self, path: &str) -> Result<RawResponse, TradfriGatewayError> {
+        self.raw(Method::Get, path, None).await
+    }
+
+    /// Changes the resource at a raw path, see [`TradfriGateway::put`].
+    pub async fn put(&self, path: &str, value: &Value) -> Result<RawResponse, TradfriGatewayError> {
+        self.raw(Method::Put, path, Some(value)).await
+    }
+
+    /// Posts JSON to a raw path, see [`TradfriGateway::post`].
+    pub async fn post(
+        &self,
+        path: &str,
+        value: &Value,
+    ) -> Result<RawResponse, TradfriGatewayError> {
+        self.raw(Method::Post, path, Some(value)).await
+    }
+
+    /// Deletes the resource at a raw path, see [`TradfriGateway::delete`].
+    pub async fn delete(&self, path: &str) -> Result<RawResponse, TradfriGatewayError> {
+        self.raw(Method::Delete, path, None).await
+    }
+
+    async fn raw(
+        &self,
+        method: Method,
+        path: &str,
+        value: Option<&Value>,
+    ) -> Result<RawResponse, TradfriGatewayError> {
+        let response = self.coap_request(raw_request(method, path, value)?).await?;
+
+        Ok(RawResponse::new(&response))
+    }
+
+    async fn update_gateway(&self, update: &Value) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::update_gateway(update)?).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn open_observe_channel(
+        &self,
+    ) -> Result<Box<dyn AsyncObserveChannel>, TradfriGatewayError> {
+        self.transport.open_observe_channel().await
+    }
+
+    pub(crate) async fn update_device(
+        &self,
+        id: u32,
+        update: &DeviceUpdate,
+    ) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::update_device(id, update)?)
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn update_group(
+        &self,
+        id: u32,
+        update: &GroupUpdate,
+    ) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::update_group(id, update)?)
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn update_scene(
+        &self,
+        group_id: u32,
+        id: u32,
+        update: &SceneUpdate,
+    ) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::update_scene(group_id, id, update)?)
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn delete_scene(
+        &self,
+        group_id: u32,
+        id: u32,
+    ) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::delete_scene(group_id, id))
+            .await?;
+        Ok(())
+    }
+
+    /// Sends a request over the transport, error responses from the gateway
+    /// are returned as errors.
+    async fn coap_request(&self, req: CoAPRequest) -> Result<CoAPResponse, TradfriGatewayError> {
+        check_response(self.transport.request(req).await?)
+    }
+
+    /// Discovers the gateway address on a blocking worker thread, see
+    /// [`TradfriGateway::discover_ip`].
+    pub async fn discover_ip() -> Result<GatewayAddr, TradfriGatewayError> {
+        run_blocking(TradfriGateway::discover_ip).await
+    }
+
+    /// Discovers the gateway address with the strategies of the options on a
+    /// blocking worker thread, see [`TradfriGateway::discover_ip_with`].
+    pub async fn discover_ip_with(
+        options: &DiscoveryOptions,
+    ) -> Result<GatewayAddr, TradfriGatewayError> {
+        let options = options.clone();
+        run_blocking(move || TradfriGateway::discover_ip_with(&options)).await
+    }
+
+    /// Discovers all gateways within the timeout on a blocking worker thread,
+    /// see [`TradfriGateway::discover`].
+    pub async fn discover(
+        timeout: Duration,
+    ) -> Result<Vec<DiscoveredGateway>, TradfriGatewayError> {
+        run_blocking(move || TradfriGateway::discover(timeout)).await
+    }
+
+    /// Discovers gateways with the strategies of the options on a blocking
+    /// worker thread, see [`TradfriGateway::discover_with`].
+    pub async fn discover_with(
+        options: &DiscoveryOptions,
+    ) -> Result<Vec<DiscoveredGateway>, TradfriGatewayError> {
+        let options = options.clone();
+        run_blocking(move || TradfriGateway::discover_with(&options)).await
+    }
+
+    /// Starts discovering gateways, yielding each gateway as soon as it has
+    /// been found, see [`TradfriGateway::discover_gateways`].
+    pub async fn discover_gateways() -> Result<AsyncGatewayDiscovery, TradfriGatewayError> {
+        run_blocking(TradfriGateway::discover_gateways)
+            .await
+            .map(AsyncGatewayDiscovery::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::MemoryTransport;
+
+    const GATEWAY_ID: &str = "7e0f0f0f0f0f0f0f";
+
+    fn gateway(transport: MemoryTransport) -> (Arc<MemoryTransport>, AsyncTradfriGateway) {
+        let transport = Arc::new(transport);
+        let gateway = AsyncTradfriGateway::from_transport(transport.clone());
+        (transport, gateway)
+    }
+
+    fn light(id: u32) -> Value {
+        json!({
+            "3": { "0": "IKEA of Sweden", "1": "TRADFRI bulb E27 WS opal 980lm", "3": "2.3.093" },
+            "3311": [{ "5706": "f5faf6", "5711": 250, "5850": 0, "5851": 254, "9003": 0 }],
+            "5750": 2,
+            "9001": "Kitchen ceiling",
+            "9002": 1700000000,
+            "9003": id,
+            "9019": 1,
+            "9020": 1700000000,
+            "9054": 0,
+        })
+    }
+
+    #[tokio::test]
+    async fn switches_light() {
+        let (transport, gateway) = gateway(MemoryTransport::new().with_device(light(65537)));
+
+        let devices = gateway.devices().await.unwrap();
+        let mut light = match devices.into_iter().next() {
+            Some(Ok(AsyncDevice::Light(light))) => light,
+            other => panic!("expected a light, got {:?}", other),
+        };
+        assert!(!light.is_on());
+
+        light.on().await.unwrap();
+        assert!(light.is_on());
+        assert_eq!(
+            transport.resource("15001/65537").unwrap()["3311"][0]["5850"],
+            1
+        );
+
+        light.off().await.unwrap();
+        assert!(!light.is_on());
+    }
+
+    #[tokio::test]
+    async fn creates_edits_and_deletes_scene() {
+        let (transport, gateway) =
+            gateway(MemoryTransport::new().with_resource("15005/131073", json!([])));
+
+        let mut scene = gateway
+            .create_scene(131073, "Evening", vec![LightSetting::new(65537, true)])
+            .await
+            .unwrap();
+        assert_eq!(scene.name(), "Evening");
+        assert_eq!(scene.lights().len(), 1);
+
+        scene
+            .edit(&SceneUpdate {
+                name: Some("Night".into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(scene.name(), "Night");
+        assert_eq!(gateway.scenes(131073).await.unwrap().len(), 1);
+
+        let id = scene.id();
+        scene.delete().await.unwrap();
+        assert_eq!(transport.resource("15005/131073").unwrap(), json!([]));
+        assert!(matches!(
+            gateway.scene(131073, id).await,
+            Err(TradfriGatewayError::NotFound(..))
+        ));
+    }
+
+    #[tokio::test]
+    async fn resets_confirmed_gateway_only() {
+        let (transport, gateway) = gateway(MemoryTransport::new().with_resource(
+            "15011/15012",
+            json!({ "9029": "1.19.32", "9081": GATEWAY_ID }),
+        ));
+
+        let other = FactoryResetConfirmation::for_gateway("0000000000000000");
+        assert!(matches!(
+            gateway.factory_reset(&other).await,
+            Err(TradfriGatewayError::FactoryResetNotConfirmed(..))
+        ));
+
+        let confirmation = FactoryResetConfirmation::for_gateway(GATEWAY_ID);
+        assert!(gateway.factory_reset(&confirmation).await.is_ok());
+        let last = transport.requests().pop().unwrap();
+        assert_eq!(last.method, Method::Post);
+        assert_eq!(last.path, "15011/9031");
+    }
+}
@@ -0,0 +1,33 @@
+use crate::{AsyncTradfriGateway, Group, GroupError, GroupUpdate};
+
+/// Async counterpart of [`Group`], sending its requests with an
+/// [`AsyncTradfriGateway`].
+pub type AsyncGroup = Group<AsyncTradfriGateway>;
+
+impl Group<AsyncTradfriGateway> {
+    pub async fn on(&mut self) -> Result<(), GroupError> {
+        self.apply(&GroupUpdate::switch(true)).await
+    }
+
+    pub async fn off(&mut self) -> Result<(), GroupError> {
+        self.apply(&GroupUpdate::switch(false)).await
+    }
+
+    /// Activates one of the scenes of the group, see
+    /// [`AsyncTradfriGateway::scenes`].
+    pub async fn activate_scene(&mut self, scene_id: u32) -> Result<(), GroupError> {
+        self.apply(&GroupUpdate::activate_scene(scene_id)).await
+    }
+
+    async fn apply(&mut self, update: &GroupUpdate) -> Result<(), GroupError> {
+        self.gateway().update_group(self.id(), update).await?;
+        self.update().await
+    }
+
+    pub async fn update(&mut self) -> Result<(), GroupError> {
+        let group = self.gateway().group(self.id()).await?;
+        self.refresh(group);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use crate::{AsyncTradfriGateway, DeviceError, DeviceUpdate, Light};
+
+/// Async counterpart of [`Light`], sending its requests with an
+/// [`AsyncTradfriGateway`].
+pub type AsyncLight = Light<AsyncTradfriGateway>;
+
+impl Light<AsyncTradfriGateway> {
+    pub async fn on(&mut self) -> Result<(), DeviceError> {
+        self.switch(true).await
+    }
+
+    pub async fn off(&mut self) -> Result<(), DeviceError> {
+        self.switch(false).await
+    }
+
+    async fn switch(&mut self, on: bool) -> Result<(), DeviceError> {
+        let update = DeviceUpdate::switch(self.bulbs(), on);
+        self.gateway().update_device(self.id(), &update).await?;
+        self.update().await
+    }
+
+    pub async fn update(&mut self) -> Result<(), DeviceError> {
+        let device = self.gateway().device(self.id()).await?;
+        self.refresh(device)
+    }
+}
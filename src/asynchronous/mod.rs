@@ -0,0 +1,57 @@
+//! Async flavour of the gateway API, for use with tokio.
+//!
+//! The async gateway sends the same requests as the blocking one and parses
+//! the responses into the same types, but over an [`AsyncTransport`]. Lights,
+//! groups and scenes are the blocking types with an
+//! [`AsyncTradfriGateway`] in place of the [`TradfriGateway`](crate::TradfriGateway),
+//! and observations share their protocol handling with the blocking observers.
+//! Only discovery, which is built on blocking mDNS and sockets, runs on the
+//! blocking thread pool of tokio.
+
+mod device;
+pub use device::*;
+
+mod discovery;
+pub use discovery::*;
+
+mod dtls;
+pub use dtls::*;
+
+mod gateway;
+pub use gateway::*;
+
+mod group;
+pub use group::*;
+
+mod light;
+pub use light::*;
+
+mod observe;
+pub use observe::*;
+
+mod scene;
+pub use scene::*;
+
+mod transport;
+pub use transport::*;
+
+use crate::TradfriGatewayError;
+
+/// Runs a blocking call on the blocking thread pool of tokio.
+///
+/// A panic in the call is resumed on the calling task, as if the call had been
+/// made there.
+pub(crate) async fn run_blocking<T, E, F>(call: F) -> Result<T, E>
+where
+    T: Send + 'static,
+    E: From<TradfriGatewayError> + Send + 'static,
+    F: FnOnce() -> Result<T, E> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(call).await {
+        Ok(result) => result,
+        Err(error) => match error.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            Err(_) => Err(TradfriGatewayError::Cancelled.into()),
+        },
+    }
+}
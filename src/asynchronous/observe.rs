@@ -0,0 +1,428 @@
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use futures::{
+    stream::{self, BoxStream},
+    Stream, StreamExt,
+};
+
+use super::AsyncObserveChannel;
+use crate::{
+    observe::{Collection, NewNotifications, ObservationState, Target},
+    AsyncDevice, AsyncGroup, AsyncTradfriGateway, GatewayInfo, GatewayNotification, OtaStatus,
+    TradfriGatewayError,
+};
+
+/// Async counterpart of the blocking observers, such as
+/// [`DeviceObserver`](crate::DeviceObserver), and a [`Stream`] of the same
+/// items.
+///
+/// The observation runs on a channel of its own and recovers from a lost
+/// session like the blocking observers do. Waiting for an item is cancel
+/// safe: if the future of [`AsyncObserver::next`] is dropped before it
+/// finishes, a notification that arrives meanwhile is yielded by the next
+/// call. The observation is cancelled when the observer is dropped.
+pub struct AsyncObserver<T> {
+    stream: BoxStream<'static, Result<T, TradfriGatewayError>>,
+}
+
+/// Async counterpart of [`DeviceObserver`](crate::DeviceObserver).
+pub type AsyncDeviceObserver = AsyncObserver<AsyncDevice>;
+
+/// Async counterpart of [`GroupObserver`](crate::GroupObserver).
+pub type AsyncGroupObserver = AsyncObserver<AsyncGroup>;
+
+/// Async counterpart of [`OtaObserver`](crate::OtaObserver).
+pub type AsyncOtaObserver = AsyncObserver<OtaStatus>;
+
+/// Async counterpart of [`NotificationObserver`](crate::NotificationObserver).
+pub type AsyncNotificationObserver = AsyncObserver<GatewayNotification>;
+
+impl<T> AsyncObserver<T> {
+    /// Waits for the next item, `None` once the observation has ended.
+    pub async fn next(&mut self) -> Option<Result<T, TradfriGatewayError>> {
+        self.stream.next().await
+    }
+}
+
+impl<T> Stream for AsyncObserver<T> {
+    type Item = Result<T, TradfriGatewayError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.stream.as_mut().poll_next(cx)
+    }
+}
+
+impl<T> fmt::Debug for AsyncObserver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncObserver").finish_non_exhaustive()
+    }
+}
+
+impl AsyncObserver<AsyncDevice> {
+    pub(crate) async fn device(
+        gateway: &AsyncTradfriGateway,
+        id: u32,
+    ) -> Result<Self, TradfriGatewayError> {
+        Self::items(gateway, Target::Item(Collection::Devices, id)).await
+    }
+
+    pub(crate) async fn devices(
+        gateway: &AsyncTradfriGateway,
+    ) -> Result<Self, TradfriGatewayError> {
+        Self::items(gateway, Target::Collection(Collection::Devices)).await
+    }
+
+    async fn items(
+        gateway: &AsyncTradfriGateway,
+        target: Target,
+    ) -> Result<Self, TradfriGatewayError> {
+        let observation = AsyncObservation::new(gateway, target).await?;
+        let stream = stream::unfold(observation, |mut observation| async move {
+            let item = match observation.next_item().await {
+                Ok(item) => item?,
+                Err(error) => return Some((Err(error), observation)),
+            };
+            let (id, payload) = item;
+            let device = AsyncDevice::new(observation.gateway.clone(), &payload)
+                .map_err(|e| TradfriGatewayError::DeviceError(id, Box::new(e)));
+
+            Some((device, observation))
+        });
+
+        Ok(Self {
+            stream: stream.boxed(),
+        })
+    }
+}
+
+impl AsyncObserver<AsyncGroup> {
+    pub(crate) async fn group(
+        gateway: &AsyncTradfriGateway,
+        id: u32,
+    ) -> Result<Self, TradfriGatewayError> {
+        Self::items(gateway, Target::Item(Collection::Groups, id)).await
+    }
+
+    pub(crate) async fn groups(gateway: &AsyncTradfriGateway) -> Result<Self, TradfriGatewayError> {
+        Self::items(gateway, Target::Collection(Collection::Groups)).await
+    }
+
+    async fn items(
+        gateway: &AsyncTradfriGateway,
+        target: Target,
+    ) -> Result<Self, TradfriGatewayError> {
+        let observation = AsyncObservation::new(gateway, target).await?;
+        let stream = stream::unfold(observation, |mut observation| async move {
+            let item = match observation.next_item().await {
+                Ok(item) => item?,
+                Err(error) => return Some((Err(error), observation)),
+            };
+            let (id, payload) = item;
+            let group = AsyncGroup::new(observation.gateway.clone(), &payload)
+                .map_err(|e| TradfriGatewayError::GroupError(id, Box::new(e)));
+
+            Some((group, observation))
+        });
+
+        Ok(Self {
+            stream: stream.boxed(),
+        })
+    }
+}
+
+impl AsyncObserver<OtaStatus> {
+    pub(crate) async fn ota(gateway: &AsyncTradfriGateway) -> Result<Self, TradfriGatewayError> {
+        let observation = AsyncObservation::new(gateway, Target::Gateway).await?;
+        let stream = stream::unfold(observation, |mut observation| async move {
+            let (_, payload) = match observation.next_notification().await {
+                Ok(notification) => notification?,
+                Err(error) => return Some((Err(error), observation)),
+            };
+            let status = serde_json::from_slice::<GatewayInfo>(&payload)
+                .map(OtaStatus::from)
+                .map_err(TradfriGatewayError::from);
+
+            Some((status, observation))
+        });
+
+        Ok(Self {
+            stream: stream.boxed(),
+        })
+    }
+}
+
+impl AsyncObserver<GatewayNotification> {
+    pub(crate) async fn notifications(
+        gateway: &AsyncTradfriGateway,
+    ) -> Result<Self, TradfriGatewayError> {
+        let observation = AsyncObservation::new(gateway, Target::Notifications).await?;
+        let state = (observation, NewNotifications::default());
+        let stream = stream::unfold(state, |(mut observation, mut notifications)| async move {
+            loop {
+                if let Some(notification) = notifications.pending.pop_front() {
+                    return Some((Ok(notification), (observation, notifications)));
+                }
+
+                let (_, payload) = match observation.next_notification().await {
+                    Ok(notification) => notification?,
+                    Err(error) => return Some((Err(error), (observation, notifications))),
+                };
+                if let Err(error) = notifications.update(&payload) {
+                    return Some((Err(error), (observation, notifications)));
+                }
+            }
+        });
+
+        Ok(Self {
+            stream: stream.boxed(),
+        })
+    }
+}
+
+/// Keeps observations open on a channel of an async gateway, the async
+/// driver of the [`ObservationState`] the blocking observers share.
+struct AsyncObservation {
+    gateway: AsyncTradfriGateway,
+    /// `None` once the session has been lost, until a new one is opened.
+    channel: Option<Box<dyn AsyncObserveChannel>>,
+    state: ObservationState,
+}
+
+impl AsyncObservation {
+    async fn new(
+        gateway: &AsyncTradfriGateway,
+        target: Target,
+    ) -> Result<Self, TradfriGatewayError> {
+        let mut observation = Self {
+            gateway: gateway.clone(),
+            channel: Some(gateway.open_observe_channel().await?),
+            state: ObservationState::new(target),
+        };
+        observation.flush();
+
+        Ok(observation)
+    }
+
+    /// Sends the queued messages, dropping the channel if that fails.
+    fn flush(&mut self) {
+        let messages = self.state.take_outgoing();
+        if let Some(channel) = self.channel.as_mut() {
+            for message in &messages {
+                if let Err(error) = channel.send(message) {
+                    log::debug!("Observe session lost: {}", error);
+                    self.channel = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Waits for the next notification for an observed item and returns its
+    /// id together with the raw payload, `None` once nothing is observed.
+    async fn next_item(&mut self) -> Result<Option<(u32, Vec<u8>)>, TradfriGatewayError> {
+        loop {
+            match self.next_notification().await? {
+                Some((Target::Item(_, id), payload)) => return Ok(Some((id, payload))),
+                Some(_) => (),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Waits for the next notification and returns its target together with
+    /// the raw payload, `None` once the gateway has ended all observations.
+    ///
+    /// The state only changes between waits, so the future can be dropped at
+    /// any point without losing a notification.
+    async fn next_notification(
+        &mut self,
+    ) -> Result<Option<(Target, Vec<u8>)>, TradfriGatewayError> {
+        loop {
+            if self.state.is_finished() {
+                return Ok(None);
+            }
+            if self.channel.is_none() {
+                self.channel = Some(self.gateway.open_observe_channel().await?);
+                self.state.restart();
+            }
+
+            let timeout = self.state.poll(Instant::now());
+            self.flush();
+            let received = match (timeout, self.channel.as_mut()) {
+                (Some(timeout), Some(channel)) => channel.receive(timeout).await,
+                (None, _) => {
+                    log::debug!("Gateway stopped answering, observing on a new session");
+                    self.channel = None;
+                    continue;
+                }
+                (_, None) => continue,
+            };
+
+            match received {
+                Ok(Some(message)) => {
+                    let notification = self.state.handle(message);
+                    self.flush();
+                    if let Some(notification) = notification {
+                        return notification.map(Some);
+                    }
+                }
+                Ok(None) => (),
+                Err(error) => {
+                    log::debug!("Observe session lost: {}", error);
+                    self.channel = None;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for AsyncObservation {
+    fn drop(&mut self) {
+        self.state.cancel();
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use coap::{
+        message::{
+            header::{MessageClass, ResponseType},
+            packet::Packet,
+        },
+        CoAPRequest, CoAPResponse, MessageType,
+    };
+    use futures::future::{self, BoxFuture};
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::AsyncTransport;
+
+    /// Hands out one channel that receives whatever the test sends it.
+    #[derive(Debug)]
+    struct ChannelTransport {
+        channel: Mutex<Option<ScriptedChannel>>,
+    }
+
+    #[derive(Debug)]
+    struct ScriptedChannel {
+        messages: mpsc::UnboundedReceiver<Packet>,
+        sent: Arc<Mutex<Vec<Packet>>>,
+    }
+
+    impl AsyncTransport for ChannelTransport {
+        fn request(
+            &self,
+            _: CoAPRequest,
+        ) -> BoxFuture<'_, Result<CoAPResponse, TradfriGatewayError>> {
+            Box::pin(future::ready(Err(TradfriGatewayError::Unsupported(
+                "requests",
+            ))))
+        }
+
+        fn open_observe_channel(
+            &self,
+        ) -> BoxFuture<'_, Result<Box<dyn AsyncObserveChannel>, TradfriGatewayError>> {
+            let channel = self.channel.lock().unwrap().take();
+            Box::pin(future::ready(match channel {
+                Some(channel) => Ok(Box::new(channel) as Box<dyn AsyncObserveChannel>),
+                None => Err(TradfriGatewayError::Unsupported("another session")),
+            }))
+        }
+    }
+
+    impl AsyncObserveChannel for ScriptedChannel {
+        fn send(&mut self, message: &Packet) -> Result<(), TradfriGatewayError> {
+            self.sent.lock().unwrap().push(message.clone());
+            Ok(())
+        }
+
+        fn receive(
+            &mut self,
+            timeout: Duration,
+        ) -> BoxFuture<'_, Result<Option<Packet>, TradfriGatewayError>> {
+            Box::pin(async move {
+                match tokio::time::timeout(timeout, self.messages.recv()).await {
+                    Ok(Some(message)) => Ok(Some(message)),
+                    Ok(None) => Err(TradfriGatewayError::Unsupported("closed session")),
+                    Err(_) => Ok(None),
+                }
+            })
+        }
+    }
+
+    fn observe() -> (
+        AsyncTradfriGateway,
+        mpsc::UnboundedSender<Packet>,
+        Arc<Mutex<Vec<Packet>>>,
+    ) {
+        let (sender, messages) = mpsc::unbounded_channel();
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let transport = ChannelTransport {
+            channel: Mutex::new(Some(ScriptedChannel {
+                messages,
+                sent: sent.clone(),
+            })),
+        };
+
+        (AsyncTradfriGateway::from_transport(transport), sender, sent)
+    }
+
+    fn notification(sequence: u8, payload: &str) -> Packet {
+        let mut packet = Packet::new();
+        packet.header.set_type(MessageType::NonConfirmable);
+        packet.header.code = MessageClass::Response(ResponseType::Content);
+        packet.set_token(vec![0, 0, 0, 1]);
+        packet.set_observe(vec![sequence]);
+        packet.payload = payload.as_bytes().to_vec();
+
+        packet
+    }
+
+    #[tokio::test]
+    async fn dropped_next_loses_no_notification() {
+        let (gateway, notifications, _) = observe();
+        let mut observer = gateway.observe_ota().await.unwrap();
+
+        let waiting = tokio::time::timeout(Duration::from_millis(20), observer.next()).await;
+        assert!(waiting.is_err());
+
+        notifications
+            .send(notification(1, r#"{ "9029": "1.19.32" }"#))
+            .unwrap();
+        notifications
+            .send(notification(2, r#"{ "9029": "1.21.31" }"#))
+            .unwrap();
+        let versions: Vec<String> = observer
+            .by_ref()
+            .take(2)
+            .map(|status| status.unwrap().firmware_version)
+            .collect()
+            .await;
+        assert_eq!(versions, ["1.19.32", "1.21.31"]);
+    }
+
+    #[tokio::test]
+    async fn deregisters_when_dropped() {
+        let (gateway, _notifications, sent) = observe();
+        let observer = gateway.observe_ota().await.unwrap();
+        drop(observer);
+
+        let sent = sent.lock().unwrap();
+        let observe: Vec<Option<Vec<u8>>> = sent
+            .iter()
+            .map(|message| message.get_observe().cloned())
+            .collect();
+        assert_eq!(observe, [Some(vec![]), Some(vec![1])]);
+    }
+}
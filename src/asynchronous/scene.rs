@@ -0,0 +1,39 @@
+use crate::{AsyncTradfriGateway, GroupUpdate, Scene, SceneError, SceneUpdate};
+
+/// Async counterpart of [`Scene`], sending its requests with an
+/// [`AsyncTradfriGateway`].
+pub type AsyncScene = Scene<AsyncTradfriGateway>;
+
+impl Scene<AsyncTradfriGateway> {
+    /// Puts the lights of the group in the states of the scene.
+    pub async fn activate(&self) -> Result<(), SceneError> {
+        let update = GroupUpdate::activate_scene(self.id());
+
+        self.gateway()
+            .update_group(self.group_id(), &update)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn edit(&mut self, update: &SceneUpdate) -> Result<(), SceneError> {
+        self.gateway()
+            .update_scene(self.group_id(), self.id(), update)
+            .await?;
+        self.update().await
+    }
+
+    /// Removes the scene from the gateway.
+    pub async fn delete(self) -> Result<(), SceneError> {
+        self.gateway()
+            .delete_scene(self.group_id(), self.id())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update(&mut self) -> Result<(), SceneError> {
+        let scene = self.gateway().scene(self.group_id(), self.id()).await?;
+        self.refresh(scene);
+
+        Ok(())
+    }
+}
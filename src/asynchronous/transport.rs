@@ -0,0 +1,76 @@
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use coap::{message::packet::Packet, CoAPRequest, CoAPResponse};
+use futures::future::{self, BoxFuture};
+
+use crate::{MemoryTransport, TradfriGatewayError, Transport};
+
+/// Async counterpart of [`Transport`], carrying the requests of an
+/// [`AsyncTradfriGateway`](crate::AsyncTradfriGateway).
+///
+/// [`AsyncDtlsTransport`](crate::AsyncDtlsTransport) talks to a real gateway
+/// without blocking a thread, [`MemoryTransport`] serves its canned resources
+/// to async code as well. Other implementations can be used with
+/// [`AsyncTradfriGateway::from_transport`](crate::AsyncTradfriGateway::from_transport).
+pub trait AsyncTransport: Debug + Send + Sync {
+    /// Sends a request and returns its response, whatever the response code.
+    fn request(&self, req: CoAPRequest)
+        -> BoxFuture<'_, Result<CoAPResponse, TradfriGatewayError>>;
+
+    /// Opens a channel of its own for observing resources, see
+    /// [`AsyncObserveChannel`].
+    ///
+    /// Transports that can not observe resources keep the default, which fails
+    /// with [`TradfriGatewayError::Unsupported`].
+    fn open_observe_channel(
+        &self,
+    ) -> BoxFuture<'_, Result<Box<dyn AsyncObserveChannel>, TradfriGatewayError>> {
+        Box::pin(future::ready(Err(TradfriGatewayError::Unsupported(
+            "observing resources",
+        ))))
+    }
+}
+
+/// Async counterpart of [`ObserveChannel`](crate::ObserveChannel).
+///
+/// Sending never waits, so that an observation can deregister when it is
+/// dropped. Receiving must be cancel safe: a message that arrives after the
+/// future returned by [`AsyncObserveChannel::receive`] has been dropped is
+/// returned by the next call.
+pub trait AsyncObserveChannel: Send {
+    /// Sends one message without waiting for an answer.
+    fn send(&mut self, message: &Packet) -> Result<(), TradfriGatewayError>;
+
+    /// Waits for the next message, `None` if none arrived within the timeout.
+    fn receive(
+        &mut self,
+        timeout: Duration,
+    ) -> BoxFuture<'_, Result<Option<Packet>, TradfriGatewayError>>;
+}
+
+/// Allows keeping a handle to a transport given to a gateway, for example to
+/// inspect a [`MemoryTransport`] afterwards.
+impl<T: AsyncTransport + ?Sized> AsyncTransport for Arc<T> {
+    fn request(
+        &self,
+        req: CoAPRequest,
+    ) -> BoxFuture<'_, Result<CoAPResponse, TradfriGatewayError>> {
+        (**self).request(req)
+    }
+
+    fn open_observe_channel(
+        &self,
+    ) -> BoxFuture<'_, Result<Box<dyn AsyncObserveChannel>, TradfriGatewayError>> {
+        (**self).open_observe_channel()
+    }
+}
+
+/// Serves requests right away, the resources are in memory.
+impl AsyncTransport for MemoryTransport {
+    fn request(
+        &self,
+        req: CoAPRequest,
+    ) -> BoxFuture<'_, Result<CoAPResponse, TradfriGatewayError>> {
+        Box::pin(future::ready(Transport::request(self, req)))
+    }
+}
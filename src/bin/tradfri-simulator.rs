@@ -0,0 +1,62 @@
+use std::{env, fs, thread};
+
+use tradfri_gateway::{Simulator, SimulatorConfig};
+
+const USAGE: &str =
+    "Usage: tradfri-simulator [--bind ADDRESS] [--config FILE] [--security-code CODE]
+
+Simulates a TRÅDFRI gateway on UDP port 5684.
+
+Options:
+    --bind ADDRESS        Address to listen on, defaults to 0.0.0.0:5684, use
+                          [::]:5684 to also accept IPv6 clients
+    --config FILE         JSON file with the lights, groups and scenes to serve,
+                          defaults to a small demo home
+    --security-code CODE  Security code clients pair with, overrides the one
+                          of the configuration";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut address = "0.0.0.0:5684".to_string();
+    let mut config = SimulatorConfig::demo();
+    let mut security_code = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bind" => address = args.next().ok_or("--bind requires an address")?,
+            "--config" => {
+                let path = args.next().ok_or("--config requires a file")?;
+                config = serde_json::from_str(&fs::read_to_string(path)?)?;
+            }
+            "--security-code" => {
+                security_code = Some(args.next().ok_or("--security-code requires a code")?)
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => return Err(format!("Unknown argument: {}\n\n{}", arg, USAGE).into()),
+        }
+    }
+    if let Some(security_code) = security_code {
+        config.security_code = security_code;
+    }
+
+    let lights = config.lights.len();
+    let groups = config.groups.len();
+    let scenes = config.scenes.len();
+    let code = config.security_code.clone();
+    let simulator = Simulator::bind(address, config)?;
+    println!(
+        "Simulating a gateway with {} lights, {} groups and {} scenes on {}, security code: {}",
+        lights,
+        groups,
+        scenes,
+        simulator.local_addr(),
+        code
+    );
+
+    loop {
+        thread::park();
+    }
+}
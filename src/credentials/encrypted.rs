@@ -0,0 +1,397 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use openssl::{
+    base64, pkcs5, rand,
+    symm::{self, Cipher},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use zeroize::Zeroizing;
+
+use super::{
+    file::{default_path, write_private},
+    CredentialError, CredentialStore, Credentials,
+};
+use crate::Secret;
+
+const FORMAT_VERSION: u32 = 1;
+
+/// scrypt cost for new files, 2^15 rounds with 32 MiB of memory.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+/// Largest scrypt cost accepted from a file, 2^20 rounds.
+const SCRYPT_MAX_LOG_N: u8 = 20;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+/// Credential store keeping the credentials in a file encrypted with a
+/// passphrase, for where keys may not be stored in plain text.
+///
+/// The key is derived from the passphrase with scrypt and a random salt, and
+/// the credentials are encrypted with AES-256-GCM, which also detects a
+/// wrong passphrase or a modified file. Like a
+/// [`FileCredentialStore`](crate::FileCredentialStore), the file is only
+/// readable by its owner on Unix and replaced atomically.
+///
+/// ```no_run
+/// use tradfri_gateway::{EncryptedFileCredentialStore, TradfriGateway};
+///
+/// let store = EncryptedFileCredentialStore::default_location("passphrase").unwrap();
+/// let gateway = TradfriGateway::from_credential_store(&store, "security code").unwrap();
+///
+/// // Change the passphrase of the file
+/// let store = store.rekey("new passphrase").unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedFileCredentialStore {
+    path: PathBuf,
+    passphrase: Secret,
+}
+
+impl EncryptedFileCredentialStore {
+    pub fn new<P: Into<PathBuf>, S: Into<Secret>>(path: P, passphrase: S) -> Self {
+        Self {
+            path: path.into(),
+            passphrase: passphrase.into(),
+        }
+    }
+
+    /// Store in `tradfri_gateway/credentials.enc` in the user configuration
+    /// directory, see
+    /// [`FileCredentialStore::default_location`](crate::FileCredentialStore::default_location).
+    pub fn default_location<S: Into<Secret>>(passphrase: S) -> Result<Self, CredentialError> {
+        Ok(Self::new(default_path("credentials.enc")?, passphrase))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Encrypts the stored credentials again with a new passphrase and a
+    /// fresh salt, and returns the store for the new passphrase.
+    ///
+    /// Fails without changing the file if the current passphrase is wrong,
+    /// and with [`CredentialError::NoCredentials`] if nothing is stored yet.
+    pub fn rekey<S: Into<Secret>>(&self, passphrase: S) -> Result<Self, CredentialError> {
+        let credentials = self
+            .load()?
+            .ok_or_else(|| CredentialError::NoCredentials(self.path.clone()))?;
+        let rekeyed = Self::new(self.path.clone(), passphrase);
+        rekeyed.save(&credentials)?;
+
+        Ok(rekeyed)
+    }
+}
+
+impl CredentialStore for EncryptedFileCredentialStore {
+    fn load(&self) -> Result<Option<Credentials>, CredentialError> {
+        let file: EncryptedFile = match fs::read(&self.path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+
+        let plaintext = file.decrypt(&self.passphrase)?;
+        Ok(Some(serde_json::from_slice(&plaintext)?))
+    }
+
+    fn save(&self, credentials: &Credentials) -> Result<(), CredentialError> {
+        let plaintext = Zeroizing::new(serde_json::to_vec(credentials)?);
+        let file = EncryptedFile::encrypt(&self.passphrase, &plaintext)?;
+        write_private(&self.path, &serde_json::to_vec_pretty(&file)?)?;
+        Ok(())
+    }
+}
+
+/// The file contents. The header is authenticated as additional data, so the
+/// KDF parameters can not be changed unnoticed. It is kept as the exact bytes
+/// of the file, as serializing it again need not give the same bytes.
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    header: Box<RawValue>,
+    ciphertext: String,
+    tag: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    kdf: Kdf,
+    cipher: String,
+    nonce: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Kdf {
+    algorithm: String,
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: String,
+}
+
+impl EncryptedFile {
+    fn encrypt(passphrase: &Secret, plaintext: &[u8]) -> Result<Self, CredentialError> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rand_bytes(&mut salt)?;
+        rand::rand_bytes(&mut nonce)?;
+
+        let header = Header {
+            version: FORMAT_VERSION,
+            kdf: Kdf {
+                algorithm: "scrypt".into(),
+                log_n: SCRYPT_LOG_N,
+                r: SCRYPT_R,
+                p: SCRYPT_P,
+                salt: base64::encode_block(&salt),
+            },
+            cipher: "aes-256-gcm".into(),
+            nonce: base64::encode_block(&nonce),
+        };
+
+        let key = header.kdf.derive_key(passphrase)?;
+        let header_json = RawValue::from_string(serde_json::to_string(&header)?)?;
+        let mut tag = [0u8; TAG_LEN];
+        let ciphertext = symm::encrypt_aead(
+            Cipher::aes_256_gcm(),
+            key.as_ref(),
+            Some(&nonce),
+            header_json.get().as_bytes(),
+            plaintext,
+            &mut tag,
+        )?;
+
+        Ok(Self {
+            header: header_json,
+            ciphertext: base64::encode_block(&ciphertext),
+            tag: base64::encode_block(&tag),
+        })
+    }
+
+    fn decrypt(&self, passphrase: &Secret) -> Result<Zeroizing<Vec<u8>>, CredentialError> {
+        let header: Header = serde_json::from_str(self.header.get())?;
+        if header.version != FORMAT_VERSION {
+            return Err(CredentialError::UnsupportedFormat(format!(
+                "version {}",
+                header.version
+            )));
+        }
+        if header.cipher != "aes-256-gcm" {
+            return Err(CredentialError::UnsupportedFormat(header.cipher.clone()));
+        }
+
+        let nonce = decode(&header.nonce)?;
+        let tag = decode(&self.tag)?;
+        if nonce.len() != NONCE_LEN || tag.len() != TAG_LEN {
+            return Err(CredentialError::DecryptionFailed);
+        }
+
+        let key = header.kdf.derive_key(passphrase)?;
+        symm::decrypt_aead(
+            Cipher::aes_256_gcm(),
+            key.as_ref(),
+            Some(&nonce),
+            self.header.get().as_bytes(),
+            &decode(&self.ciphertext)?,
+            &tag,
+        )
+        .map(Zeroizing::new)
+        .map_err(|_| CredentialError::DecryptionFailed)
+    }
+}
+
+impl Kdf {
+    fn derive_key(&self, passphrase: &Secret) -> Result<Zeroizing<[u8; KEY_LEN]>, CredentialError> {
+        if self.algorithm != "scrypt" {
+            return Err(CredentialError::UnsupportedFormat(self.algorithm.clone()));
+        }
+        if self.log_n > SCRYPT_MAX_LOG_N
+            || !(1..=16).contains(&self.r)
+            || !(1..=16).contains(&self.p)
+        {
+            return Err(CredentialError::UnsupportedFormat(format!(
+                "scrypt cost log_n={} r={} p={}",
+                self.log_n, self.r, self.p
+            )));
+        }
+
+        let n = 1u64 << self.log_n;
+        let (r, p) = (u64::from(self.r), u64::from(self.p));
+        // Memory scrypt needs for these parameters, as checked by OpenSSL.
+        let max_memory = 128 * r * (n + 2) + 128 * r * p;
+
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        pkcs5::scrypt(
+            passphrase.expose(),
+            &decode(&self.salt)?,
+            n,
+            r,
+            p,
+            max_memory,
+            key.as_mut(),
+        )?;
+
+        Ok(key)
+    }
+}
+
+fn decode(encoded: &str) -> Result<Vec<u8>, CredentialError> {
+    base64::decode_block(encoded).map_err(|_| CredentialError::DecryptionFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        process,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use serde_json::Value;
+
+    use super::*;
+
+    /// A file path of its own for each test, removed when dropped.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new() -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let name = format!(
+                "tradfri-gateway-{}-{}.enc",
+                process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            );
+            Self(std::env::temp_dir().join(name))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn credentials() -> Credentials {
+        Credentials::new("client", "0123456789abcdef").with_address([192, 168, 1, 10])
+    }
+
+    /// Changes the file as JSON and writes it back.
+    fn edit(path: &Path, change: impl FnOnce(&mut Value)) {
+        let mut file: Value = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+        change(&mut file);
+        fs::write(path, serde_json::to_vec(&file).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn loads_saved_credentials() {
+        let path = TempPath::new();
+        let store = EncryptedFileCredentialStore::new(&path.0, "passphrase");
+
+        assert_eq!(store.load().unwrap(), None);
+        store.save(&credentials()).unwrap();
+
+        assert_eq!(store.load().unwrap(), Some(credentials()));
+        let contents = fs::read_to_string(&path.0).unwrap();
+        assert!(!contents.contains("0123456789abcdef"));
+    }
+
+    #[test]
+    fn rejects_wrong_passphrase() {
+        let path = TempPath::new();
+        EncryptedFileCredentialStore::new(&path.0, "passphrase")
+            .save(&credentials())
+            .unwrap();
+
+        let store = EncryptedFileCredentialStore::new(&path.0, "wrong");
+        assert!(matches!(
+            store.load(),
+            Err(CredentialError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn rejects_tampered_ciphertext() {
+        let path = TempPath::new();
+        let store = EncryptedFileCredentialStore::new(&path.0, "passphrase");
+        store.save(&credentials()).unwrap();
+
+        edit(&path.0, |file| {
+            let mut ciphertext = decode(file["ciphertext"].as_str().unwrap()).unwrap();
+            ciphertext[0] ^= 1;
+            file["ciphertext"] = base64::encode_block(&ciphertext).into();
+        });
+
+        assert!(matches!(
+            store.load(),
+            Err(CredentialError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn authenticates_header_as_stored() {
+        let path = TempPath::new();
+        let store = EncryptedFileCredentialStore::new(&path.0, "passphrase");
+        store.save(&credentials()).unwrap();
+
+        // Writing the file again orders the keys of the header differently,
+        // which keeps its meaning but not its bytes.
+        let contents = fs::read_to_string(&path.0).unwrap();
+        edit(&path.0, |_| {});
+        assert_ne!(fs::read_to_string(&path.0).unwrap(), contents);
+
+        assert!(matches!(
+            store.load(),
+            Err(CredentialError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn rekeys_with_new_passphrase() {
+        let path = TempPath::new();
+        let store = EncryptedFileCredentialStore::new(&path.0, "passphrase");
+        store.save(&credentials()).unwrap();
+
+        let rekeyed = store.rekey("new passphrase").unwrap();
+
+        assert_eq!(rekeyed.load().unwrap(), Some(credentials()));
+        assert!(matches!(
+            store.load(),
+            Err(CredentialError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn does_not_rekey_with_wrong_passphrase() {
+        let path = TempPath::new();
+        let store = EncryptedFileCredentialStore::new(&path.0, "passphrase");
+        store.save(&credentials()).unwrap();
+
+        let wrong = EncryptedFileCredentialStore::new(&path.0, "wrong");
+        assert!(matches!(
+            wrong.rekey("new passphrase"),
+            Err(CredentialError::DecryptionFailed)
+        ));
+        assert_eq!(store.load().unwrap(), Some(credentials()));
+    }
+
+    #[test]
+    fn does_not_rekey_missing_store() {
+        let path = TempPath::new();
+        let store = EncryptedFileCredentialStore::new(&path.0, "passphrase");
+
+        assert!(matches!(
+            store.rekey("new passphrase"),
+            Err(CredentialError::NoCredentials(_))
+        ));
+        assert!(!path.0.exists());
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{CredentialError, CredentialStore, Credentials};
+
+/// Credential store keeping the credentials in a JSON file.
+///
+/// The file is only readable by its owner on Unix, and is replaced atomically
+/// when saving, so that an interrupted save does not lose the credentials.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileCredentialStore {
+    path: PathBuf,
+}
+
+impl FileCredentialStore {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Store in `tradfri_gateway/credentials.json` in the user configuration
+    /// directory: `$XDG_CONFIG_HOME`, `$HOME/.config`, or `%APPDATA%` on
+    /// Windows.
+    pub fn default_location() -> Result<Self, CredentialError> {
+        Ok(Self::new(default_path("credentials.json")?))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl CredentialStore for FileCredentialStore {
+    fn load(&self) -> Result<Option<Credentials>, CredentialError> {
+        match fs::read(&self.path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn save(&self, credentials: &Credentials) -> Result<(), CredentialError> {
+        write_private(&self.path, &serde_json::to_vec_pretty(credentials)?)?;
+        Ok(())
+    }
+}
+
+/// Writes a file only its owner can read, through a temporary file that is
+/// renamed over the target.
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(&temporary)?;
+    // The mode only applies when the file is created, a leftover temporary
+    // file keeps its permissions.
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+/// Path of a file in the `tradfri_gateway` directory of the user
+/// configuration directory.
+pub(crate) fn default_path(file_name: &str) -> Result<PathBuf, CredentialError> {
+    Ok(config_dir()?.join("tradfri_gateway").join(file_name))
+}
+
+fn config_dir() -> Result<PathBuf, CredentialError> {
+    let non_empty = |name: &str| env::var_os(name).filter(|value| !value.is_empty());
+
+    if let Some(dir) = non_empty("XDG_CONFIG_HOME") {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Some(home) = non_empty("HOME") {
+        return Ok(PathBuf::from(home).join(".config"));
+    }
+    non_empty("APPDATA")
+        .map(PathBuf::from)
+        .ok_or(CredentialError::NoConfigDir)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn saves_and_loads_credentials() {
+        let path = env::temp_dir().join(format!("tradfri-gateway-{}.json", process::id()));
+        let store = FileCredentialStore::new(&path);
+        let credentials =
+            Credentials::new("client", "0123456789abcdef").with_address([10, 0, 0, 2]);
+
+        store.save(&credentials).unwrap();
+        let loaded = store.load();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), Some(credentials));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restricts_permissions_of_leftover_temporary_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = env::temp_dir().join(format!("tradfri-gateway-{}.key", process::id()));
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, b"").unwrap();
+        fs::set_permissions(&temporary, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, b"secret").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,106 @@
+mod encrypted;
+mod file;
+
+pub use encrypted::*;
+pub use file::*;
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{GatewayAddr, Secret};
+
+/// What a client needs to talk to a gateway it has paired with, and where
+/// the gateway was last seen.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credentials {
+    pub identifier: String,
+    pub session_key: Secret,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<GatewayAddr>,
+}
+
+impl Credentials {
+    pub fn new<K: Into<Secret>>(identifier: &str, session_key: K) -> Self {
+        Self {
+            identifier: identifier.into(),
+            session_key: session_key.into(),
+            address: None,
+        }
+    }
+
+    pub fn with_address<A: Into<GatewayAddr>>(mut self, address: A) -> Self {
+        self.address = Some(address.into());
+        self
+    }
+}
+
+/// How to connect with the credentials loaded from a [`CredentialStore`].
+#[derive(Debug)]
+pub(crate) enum StoredConnection {
+    /// Nothing is stored, pair with the gateway at the address, or with a
+    /// discovered one if there is none.
+    Pair(Option<GatewayAddr>),
+    /// Connect with the credentials, which need saving if they changed.
+    Connect {
+        credentials: Credentials,
+        changed: bool,
+    },
+    /// Connect to the stored address, or to a newly discovered one if it does
+    /// not answer.
+    Reconnect(Credentials),
+}
+
+impl StoredConnection {
+    pub(crate) fn plan(stored: Option<Credentials>, address: Option<GatewayAddr>) -> Self {
+        match (stored, address) {
+            (None, address) => StoredConnection::Pair(address),
+            (Some(credentials), Some(address)) if credentials.address == Some(address) => {
+                StoredConnection::Connect {
+                    credentials,
+                    changed: false,
+                }
+            }
+            (Some(credentials), Some(address)) => StoredConnection::Connect {
+                credentials: credentials.with_address(address),
+                changed: true,
+            },
+            (Some(credentials), None) => StoredConnection::Reconnect(credentials),
+        }
+    }
+}
+
+/// Storage for the [`Credentials`] of a gateway, so that a client pairs once
+/// and reconnects with the stored credentials afterwards, see
+/// [`TradfriGateway::from_credential_store`](crate::TradfriGateway::from_credential_store).
+pub trait CredentialStore {
+    /// Returns the stored credentials, `None` if nothing has been stored yet.
+    fn load(&self) -> Result<Option<Credentials>, CredentialError>;
+
+    /// Stores the credentials, replacing any stored before.
+    fn save(&self, credentials: &Credentials) -> Result<(), CredentialError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CredentialError {
+    #[error("Credential file error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Invalid credential file: {0}")]
+    SerdeError(#[from] serde_json::Error),
+
+    #[error("No configuration directory, neither XDG_CONFIG_HOME, HOME nor APPDATA is set")]
+    NoConfigDir,
+
+    #[error("No credentials stored in {0}")]
+    NoCredentials(PathBuf),
+
+    #[error("Could not decrypt the credential file, wrong passphrase or damaged file")]
+    DecryptionFailed,
+
+    #[error("Unsupported credential file format: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Encryption error: {0}")]
+    CryptoError(#[from] openssl::error::ErrorStack),
+}
@@ -1,14 +1,19 @@
-use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, Utc};
 
 use crate::{
-    tradfri_coap::TradfriConnection, BulbColdWarmHexUpdate, BulbParsed, BulbRgbXYUpdate,
-    BulbUpdate, Device, DeviceError, DeviceInfoParsed, DeviceUpdate, DriverUpdate,
-    LightDeviceParsed, TradfriGateway,
+    BulbParsed, Device, DeviceError, DeviceInfoParsed, DeviceUpdate, LightDeviceParsed,
+    OtaUpdateState, TradfriGateway,
 };
 
-#[derive(Debug)]
-pub struct Light {
-    gateway: TradfriGateway,
+/// A light bulb or LED driver paired with the gateway.
+///
+/// The gateway parameter is the handle the light sends its requests with, a
+/// [`TradfriGateway`] by default or an
+/// [`AsyncTradfriGateway`](crate::AsyncTradfriGateway) for an
+/// [`AsyncLight`](crate::AsyncLight).
+#[derive(Debug, Clone)]
+pub struct Light<G = TradfriGateway> {
+    gateway: G,
     info: DeviceInfoParsed,
     id: u32,
     name: String,
@@ -18,13 +23,13 @@ pub struct Light {
     bulbs: Vec<BulbParsed>,
 }
 
-impl Light {
-    pub fn new(gateway: TradfriGateway, bytes: &[u8]) -> Result<Self, DeviceError> {
+impl<G> Light<G> {
+    pub fn new(gateway: G, bytes: &[u8]) -> Result<Self, DeviceError> {
         let parsed: LightDeviceParsed = match serde_json::from_slice(bytes) {
             Ok(p) => p,
             Err(error) => {
                 return Err(DeviceError::SerdeError(
-                    error.to_string(),
+                    error,
                     String::from_utf8_lossy(bytes).to_string(),
                 ))
             }
@@ -35,81 +40,13 @@ impl Light {
             info: parsed.info,
             id: parsed.id,
             name: parsed.name,
-            creation_date: Utc.from_utc_datetime(
-                &NaiveDateTime::from_timestamp_opt(parsed.creation_date.into(), 0).unwrap(),
-            ),
-            last_seen: Utc.from_utc_datetime(
-                &NaiveDateTime::from_timestamp_opt(parsed.last_seen.into(), 0).unwrap(),
-            ),
+            creation_date: DateTime::from_timestamp(parsed.creation_date.into(), 0).unwrap(),
+            last_seen: DateTime::from_timestamp(parsed.last_seen.into(), 0).unwrap(),
             reachable: parsed.reachable,
             bulbs: parsed.bulbs,
         })
     }
 
-    pub fn on(&mut self) -> Result<(), DeviceError> {
-        let update = DeviceUpdate::BulbUpdate {
-            bulbs: self
-                .bulbs
-                .iter()
-                .map(|bulb| match bulb {
-                    BulbParsed::LedDriver(_) => BulbUpdate::DriverUpdate(DriverUpdate {
-                        on: Some(true),
-                        ..Default::default()
-                    }),
-                    BulbParsed::BulbColdWarmHex(_) => {
-                        BulbUpdate::BulbColdWarmHexUpdate(BulbColdWarmHexUpdate {
-                            on: Some(true),
-                            ..Default::default()
-                        })
-                    }
-                    BulbParsed::BulbRgbXY(_) => BulbUpdate::BulbRgbXYUpdate(BulbRgbXYUpdate {
-                        on: Some(true),
-                        ..Default::default()
-                    }),
-                })
-                .collect(),
-        };
-
-        let mut connection = self.gateway.create_connection()?;
-        self.gateway
-            .update_device(self.id, &update, Some(&mut connection))?;
-        self.update_with_connection(&mut connection)?;
-
-        Ok(())
-    }
-
-    pub fn off(&mut self) -> Result<(), DeviceError> {
-        let update = DeviceUpdate::BulbUpdate {
-            bulbs: self
-                .bulbs
-                .iter()
-                .map(|bulb| match bulb {
-                    BulbParsed::LedDriver(_) => BulbUpdate::DriverUpdate(DriverUpdate {
-                        on: Some(false),
-                        ..Default::default()
-                    }),
-                    BulbParsed::BulbColdWarmHex(_) => {
-                        BulbUpdate::BulbColdWarmHexUpdate(BulbColdWarmHexUpdate {
-                            on: Some(false),
-                            ..Default::default()
-                        })
-                    }
-                    BulbParsed::BulbRgbXY(_) => BulbUpdate::BulbRgbXYUpdate(BulbRgbXYUpdate {
-                        on: Some(false),
-                        ..Default::default()
-                    }),
-                })
-                .collect(),
-        };
-
-        let mut connection = self.gateway.create_connection()?;
-        self.gateway
-            .update_device(self.id, &update, Some(&mut connection))?;
-        self.update_with_connection(&mut connection)?;
-
-        Ok(())
-    }
-
     pub fn is_on(&self) -> bool {
         self.bulbs
             .iter()
@@ -117,17 +54,33 @@ impl Light {
             > 0
     }
 
-    pub fn update(&mut self) -> Result<(), DeviceError> {
-        let mut connection = self.gateway.create_connection()?;
-        self.update_with_connection(&mut connection)
+    /// Manufacturer, model, firmware and power details reported by the device.
+    pub fn info(&self) -> &DeviceInfoParsed {
+        &self.info
+    }
+
+    /// Firmware update state of the device as of the last refresh.
+    pub fn ota_update_state(&self) -> OtaUpdateState {
+        self.info.ota_update_state
     }
 
-    fn update_with_connection(
-        &mut self,
-        connection: &mut TradfriConnection,
-    ) -> Result<(), DeviceError> {
-        let device = self.gateway.device_with_connection(self.id, connection)?;
+    #[cfg(feature = "async")]
+    pub(crate) fn gateway(&self) -> &G {
+        &self.gateway
+    }
 
+    #[cfg(feature = "async")]
+    pub(crate) fn id(&self) -> u32 {
+        self.id
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn bulbs(&self) -> &[BulbParsed] {
+        &self.bulbs
+    }
+
+    /// Takes over the state of the device as read again from the gateway.
+    pub(crate) fn refresh(&mut self, device: Device<G>) -> Result<(), DeviceError> {
         if let Device::Light(light) = device {
             self.info = light.info;
             self.id = light.id;
@@ -143,3 +96,22 @@ impl Light {
         Ok(())
     }
 }
+
+impl Light {
+    pub fn on(&mut self) -> Result<(), DeviceError> {
+        let update = DeviceUpdate::switch(&self.bulbs, true);
+        self.gateway.update_device(self.id, &update)?;
+        self.update()
+    }
+
+    pub fn off(&mut self) -> Result<(), DeviceError> {
+        let update = DeviceUpdate::switch(&self.bulbs, false);
+        self.gateway.update_device(self.id, &update)?;
+        self.update()
+    }
+
+    pub fn update(&mut self) -> Result<(), DeviceError> {
+        let device = self.gateway.device(self.id)?;
+        self.refresh(device)
+    }
+}
@@ -10,18 +10,18 @@ pub use update::*;
 use crate::{TradfriGateway, TradfriGatewayError};
 
 #[derive(Debug)]
-pub enum Device {
+pub enum Device<G = TradfriGateway> {
     RemoteControl,
-    Light(Box<Light>),
+    Light(Box<Light<G>>),
 }
 
-impl Device {
-    pub fn new(gateway: TradfriGateway, bytes: &[u8]) -> Result<Device, DeviceError> {
+impl<G> Device<G> {
+    pub fn new(gateway: G, bytes: &[u8]) -> Result<Self, DeviceError> {
         let device_type: DeviceTypeParsed = match serde_json::from_slice(bytes) {
             Ok(d) => d,
             Err(error) => {
                 return Err(DeviceError::SerdeError(
-                    error.to_string(),
+                    error,
                     String::from_utf8_lossy(bytes).to_string(),
                 ))
             }
@@ -48,7 +48,7 @@ pub enum DeviceError {
     ExpectedDeviceType(String),
 
     #[error("Serde error: {0}, raw data: {1}")]
-    SerdeError(String, String),
+    SerdeError(#[source] serde_json::Error, String),
 
     #[error("Tradfri gateway error: {0}")]
     TradfriGatewayError(#[from] TradfriGatewayError),
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{serialization::bool_from_int, ColdWarmColor, RgbColor};
+use crate::{serialization::bool_from_int, ColdWarmColor, OtaUpdateState, RgbColor};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DeviceTypeParsed {
@@ -8,14 +8,78 @@ pub struct DeviceTypeParsed {
     pub device_type: u32,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Details every device reports about itself.
+///
+/// Parsed from the device itself rather than its `3` object, as the gateway
+/// keeps the firmware update state of the device next to that object.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(from = "DeviceInfoFields", into = "DeviceInfoFields")]
 pub struct DeviceInfoParsed {
-    #[serde(rename = "0")]
     pub manufacturer: String,
-    #[serde(rename = "1")]
     pub product: String,
-    #[serde(rename = "3")]
+    pub serial: Option<String>,
     pub firmware: String,
+    /// How the device is powered, `1` for mains and `3` for battery.
+    pub power_source: Option<u8>,
+    /// Battery level in percent, for battery powered devices.
+    pub battery: Option<u8>,
+    /// Firmware update state of the device.
+    pub ota_update_state: OtaUpdateState,
+}
+
+#[derive(Deserialize, Serialize)]
+struct DeviceInfoFields {
+    #[serde(rename = "3")]
+    info: DeviceInfoObject,
+    #[serde(rename = "9054", default)]
+    ota_update_state: OtaUpdateState,
+}
+
+#[derive(Deserialize, Serialize)]
+struct DeviceInfoObject {
+    #[serde(rename = "0")]
+    manufacturer: String,
+    #[serde(rename = "1")]
+    product: String,
+    #[serde(rename = "2", default)]
+    serial: Option<String>,
+    #[serde(rename = "3")]
+    firmware: String,
+    #[serde(rename = "6", default)]
+    power_source: Option<u8>,
+    #[serde(rename = "9", default)]
+    battery: Option<u8>,
+}
+
+impl From<DeviceInfoFields> for DeviceInfoParsed {
+    fn from(fields: DeviceInfoFields) -> Self {
+        let info = fields.info;
+        Self {
+            manufacturer: info.manufacturer,
+            product: info.product,
+            serial: info.serial,
+            firmware: info.firmware,
+            power_source: info.power_source,
+            battery: info.battery,
+            ota_update_state: fields.ota_update_state,
+        }
+    }
+}
+
+impl From<DeviceInfoParsed> for DeviceInfoFields {
+    fn from(info: DeviceInfoParsed) -> Self {
+        Self {
+            info: DeviceInfoObject {
+                manufacturer: info.manufacturer,
+                product: info.product,
+                serial: info.serial,
+                firmware: info.firmware,
+                power_source: info.power_source,
+                battery: info.battery,
+            },
+            ota_update_state: info.ota_update_state,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -24,7 +88,7 @@ pub struct DeviceInfoWithBatteryParsed {
     pub manufacturer: String,
     #[serde(rename = "1")]
     pub product: String,
-    #[serde(rename = "2")]
+    #[serde(rename = "3")]
     pub firmware: String,
     #[serde(rename = "9")]
     pub battery: u8,
@@ -32,7 +96,7 @@ pub struct DeviceInfoWithBatteryParsed {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct LightDeviceParsed {
-    #[serde(rename = "3")]
+    #[serde(flatten)]
     pub info: DeviceInfoParsed,
     #[serde(rename = "3311")]
     pub bulbs: Vec<BulbParsed>,
@@ -134,3 +198,46 @@ pub struct BulbRgbXYParsed {
     #[serde(rename = "5851")]
     pub brightness: u8,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn reads_update_state_next_to_device_info() {
+        let light: LightDeviceParsed = serde_json::from_value(json!({
+            "3": { "0": "IKEA of Sweden", "1": "TRADFRI bulb E27 WS opal 980lm", "3": "2.3.093" },
+            "3311": [{ "5706": "f5faf6", "5711": 250, "5850": 1, "5851": 254, "9003": 0 }],
+            "5750": 2,
+            "9001": "Kitchen ceiling",
+            "9002": 1700000000,
+            "9003": 65537,
+            "9019": 1,
+            "9020": 1700000000,
+            "9054": 1,
+        }))
+        .unwrap();
+
+        assert_eq!(light.info.firmware, "2.3.093");
+        assert_eq!(light.info.serial, None);
+        assert_eq!(light.info.ota_update_state, OtaUpdateState::Available);
+        assert_eq!(light.id, 65537);
+        assert!(light.reachable);
+    }
+
+    #[test]
+    fn writes_update_state_next_to_device_info() {
+        let info: DeviceInfoParsed = serde_json::from_value(json!({
+            "3": { "0": "IKEA of Sweden", "1": "TRADFRI remote control", "3": "2.3.014", "9": 87 },
+        }))
+        .unwrap();
+
+        assert_eq!(info.ota_update_state, OtaUpdateState::UpToDate);
+        assert_eq!(info.battery, Some(87));
+        let value = serde_json::to_value(&info).unwrap();
+        assert_eq!(value["3"]["9"], 87);
+        assert_eq!(value["9054"], 0);
+    }
+}
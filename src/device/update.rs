@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     serialization::{option_bool_from_int, option_int_from_bool},
-    ColdWarmColor, RgbColor,
+    BulbParsed, ColdWarmColor, RgbColor,
 };
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -14,6 +14,33 @@ pub enum DeviceUpdate {
     },
 }
 
+impl DeviceUpdate {
+    /// Creates an update turning all of the given bulbs on or off.
+    pub fn switch(bulbs: &[BulbParsed], on: bool) -> Self {
+        DeviceUpdate::BulbUpdate {
+            bulbs: bulbs
+                .iter()
+                .map(|bulb| match bulb {
+                    BulbParsed::LedDriver(_) => BulbUpdate::DriverUpdate(DriverUpdate {
+                        on: Some(on),
+                        ..Default::default()
+                    }),
+                    BulbParsed::BulbColdWarmHex(_) => {
+                        BulbUpdate::BulbColdWarmHexUpdate(BulbColdWarmHexUpdate {
+                            on: Some(on),
+                            ..Default::default()
+                        })
+                    }
+                    BulbParsed::BulbRgbXY(_) => BulbUpdate::BulbRgbXYUpdate(BulbRgbXYUpdate {
+                        on: Some(on),
+                        ..Default::default()
+                    }),
+                })
+                .collect(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum BulbUpdate {
@@ -0,0 +1,215 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+/// Largest number of addresses a range may cover, to keep sweeps short.
+const MAX_ADDRESSES: u128 = 1 << 16;
+
+/// Range of IP addresses in CIDR notation, such as `192.168.1.0/24`, for
+/// sweeping a network for gateways, see
+/// [`DiscoveryOptions::with_sweep`](crate::DiscoveryOptions::with_sweep).
+///
+/// Ranges cover at most 65536 addresses, a `/16` for IPv4 and a `/112` for
+/// IPv6.
+///
+/// ```
+/// use tradfri_gateway::Cidr;
+///
+/// let range: Cidr = "192.168.1.0/30".parse().unwrap();
+/// let hosts: Vec<_> = range.hosts().map(|host| host.to_string()).collect();
+/// assert_eq!(hosts, ["192.168.1.1", "192.168.1.2"]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn new(address: IpAddr, prefix: u8) -> Result<Self, CidrError> {
+        let bits = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > bits {
+            return Err(CidrError::InvalidPrefix(prefix));
+        }
+        if bits - prefix > 16 {
+            return Err(CidrError::TooLarge(prefix));
+        }
+
+        let mask = u128::MAX << (bits - prefix);
+        let network = match address {
+            IpAddr::V4(ip) => IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask as u32)),
+            IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask)),
+        };
+
+        Ok(Self { network, prefix })
+    }
+
+    pub fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// The addresses of the range that can belong to a host, which for IPv4
+    /// leaves out the network and broadcast addresses of ranges larger than
+    /// two addresses.
+    pub fn hosts(&self) -> impl Iterator<Item = IpAddr> {
+        let network = self.network;
+        let (bits, start) = match network {
+            IpAddr::V4(ip) => (32, u32::from(ip) as u128),
+            IpAddr::V6(ip) => (128, u128::from(ip)),
+        };
+        let size = 1u128 << (bits - self.prefix);
+        debug_assert!(size <= MAX_ADDRESSES);
+
+        let (first, last) = match network {
+            IpAddr::V4(_) if size > 2 => (1, size - 2),
+            _ => (0, size - 1),
+        };
+
+        (first..=last).map(move |offset| match network {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from((start + offset) as u32)),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::from(start + offset)),
+        })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = CidrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CidrError::Invalid(s.to_string());
+        let (address, prefix) = s.split_once('/').ok_or_else(invalid)?;
+
+        Self::new(
+            address.parse().map_err(|_| invalid())?,
+            prefix.parse().map_err(|_| invalid())?,
+        )
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CidrError {
+    #[error("Invalid address range: {0}")]
+    Invalid(String),
+
+    #[error("Invalid prefix length: {0}")]
+    InvalidPrefix(u8),
+
+    #[error("Address range with prefix /{0} is too large to sweep")]
+    TooLarge(u8),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(range: &str) -> Vec<IpAddr> {
+        range.parse::<Cidr>().unwrap().hosts().collect()
+    }
+
+    #[test]
+    fn parses_ranges_to_their_network() {
+        let range: Cidr = "192.168.1.77/24".parse().unwrap();
+        assert_eq!(range.network(), IpAddr::from([192, 168, 1, 0]));
+        assert_eq!(range.prefix(), 24);
+        assert_eq!(range.to_string(), "192.168.1.0/24");
+
+        let range: Cidr = "fd00::1:2/112".parse().unwrap();
+        assert_eq!(range.to_string(), "fd00::1:0/112");
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        assert!(matches!(
+            "192.168.1.0".parse::<Cidr>(),
+            Err(CidrError::Invalid(_))
+        ));
+        assert!(matches!(
+            "192.168.1.0/x".parse::<Cidr>(),
+            Err(CidrError::Invalid(_))
+        ));
+        assert!(matches!(
+            "gateway/24".parse::<Cidr>(),
+            Err(CidrError::Invalid(_))
+        ));
+        assert!(matches!(
+            "192.168.1.0/33".parse::<Cidr>(),
+            Err(CidrError::InvalidPrefix(33))
+        ));
+        assert!(matches!(
+            "fd00::/129".parse::<Cidr>(),
+            Err(CidrError::InvalidPrefix(129))
+        ));
+    }
+
+    #[test]
+    fn rejects_ranges_too_large_to_sweep() {
+        assert!(matches!(
+            "0.0.0.0/0".parse::<Cidr>(),
+            Err(CidrError::TooLarge(0))
+        ));
+        assert!(matches!(
+            "10.0.0.0/15".parse::<Cidr>(),
+            Err(CidrError::TooLarge(15))
+        ));
+        assert!(matches!(
+            "fd00::/111".parse::<Cidr>(),
+            Err(CidrError::TooLarge(111))
+        ));
+        assert_eq!(hosts("10.0.0.0/16").len(), 65534);
+        assert_eq!(hosts("fd00::/112").len(), 65536);
+    }
+
+    #[test]
+    fn leaves_out_network_and_broadcast_addresses() {
+        let range = hosts("192.168.1.0/24");
+        assert_eq!(range.len(), 254);
+        assert_eq!(range.first(), Some(&IpAddr::from([192, 168, 1, 1])));
+        assert_eq!(range.last(), Some(&IpAddr::from([192, 168, 1, 254])));
+
+        assert_eq!(
+            hosts("192.168.1.4/31"),
+            [
+                IpAddr::from([192, 168, 1, 4]),
+                IpAddr::from([192, 168, 1, 5])
+            ]
+        );
+        assert_eq!(hosts("192.168.1.9/32"), [IpAddr::from([192, 168, 1, 9])]);
+        assert_eq!(
+            hosts("255.255.255.252/30"),
+            [
+                IpAddr::from([255, 255, 255, 253]),
+                IpAddr::from([255, 255, 255, 254])
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_every_ipv6_address() {
+        let range = hosts("fd00::/126");
+        assert_eq!(range.len(), 4);
+        assert_eq!(range.first(), Some(&"fd00::".parse::<IpAddr>().unwrap()));
+        assert_eq!(range.last(), Some(&"fd00::3".parse::<IpAddr>().unwrap()));
+
+        assert_eq!(
+            hosts("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff/128"),
+            ["ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff"
+                .parse::<IpAddr>()
+                .unwrap()]
+        );
+    }
+}
@@ -0,0 +1,570 @@
+mod cidr;
+pub use cidr::*;
+
+mod probe;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use mdns_sd::{Receiver, ServiceDaemon, ServiceEvent, ServiceInfo};
+
+use crate::{address, tradfri_coap::TF_PORT, GatewayAddr, TradfriGatewayError};
+
+/// Service type the gateway announces itself with.
+const SERVICE_TYPE: &str = "_coap._udp.local.";
+
+/// Host name prefix of the gateways among other CoAP services.
+const HOSTNAME_PREFIX: &str = "TRADFRI-Gateway-";
+
+/// A gateway announced on the local network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredGateway {
+    /// Host name, such as `TRADFRI-Gateway-b072bf257a41.local.`.
+    pub hostname: String,
+    /// All announced addresses, IPv4 addresses first.
+    pub addresses: Vec<GatewayAddr>,
+    pub port: u16,
+    /// The TXT properties of the announcement, such as the firmware version.
+    pub properties: BTreeMap<String, String>,
+    /// The strategies that found the gateway.
+    pub sources: Vec<DiscoverySource>,
+}
+
+/// Strategy that found a gateway, see [`DiscoveryOptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiscoverySource {
+    /// Announced with mDNS.
+    Mdns,
+    /// Answered a CoAP multicast request and a DTLS hello.
+    CoapMulticast,
+    /// Answered a DTLS hello sent while sweeping an address range.
+    Sweep,
+}
+
+impl DiscoveredGateway {
+    fn new(info: &ServiceInfo) -> Self {
+        // The announcement does not tell on which interface it was seen, so
+        // link-local addresses are only scoped when there is one interface
+        // they can be on.
+        let interfaces = address::link_local_interfaces();
+        let mut addresses: Vec<GatewayAddr> = info
+            .get_addresses()
+            .iter()
+            .map(|&ip| match (ip, interfaces.as_slice()) {
+                (IpAddr::V6(ip), &[scope_id]) if GatewayAddr::new(ip).is_link_local() => {
+                    GatewayAddr::with_scope_id(ip, scope_id)
+                }
+                (ip, _) => GatewayAddr::new(ip),
+            })
+            .collect();
+        addresses.sort_by_key(|address| (address.ip().is_ipv6(), address.ip()));
+
+        Self {
+            hostname: info.get_hostname().to_string(),
+            addresses,
+            port: info.get_port(),
+            properties: info
+                .get_properties()
+                .iter()
+                .map(|property| (property.key().to_string(), property.val_str().to_string()))
+                .collect(),
+            sources: vec![DiscoverySource::Mdns],
+        }
+    }
+
+    /// A gateway found by probing an address, which only tells the address.
+    fn probed(address: GatewayAddr, source: DiscoverySource) -> Self {
+        Self {
+            hostname: address.ip().to_string(),
+            addresses: vec![address],
+            port: TF_PORT,
+            properties: BTreeMap::new(),
+            sources: vec![source],
+        }
+    }
+
+    /// The address to connect to: the first IPv4 address, or else the first
+    /// IPv6 address that is not link-local, or else the first link-local
+    /// address whose network interface is known.
+    ///
+    /// A link-local address announced with mDNS has no scope id when the host
+    /// has several interfaces it could be on, pair such an address with the
+    /// right one with [`GatewayAddr::with_scope_id`].
+    pub fn address(&self) -> Option<GatewayAddr> {
+        self.addresses
+            .iter()
+            .find(|address| !address.is_link_local())
+            .or_else(|| {
+                self.addresses
+                    .iter()
+                    .find(|address| address.scope_id() != 0)
+            })
+            .copied()
+    }
+
+    /// The first IPv4 address of the gateway.
+    pub fn ipv4(&self) -> Option<IpAddr> {
+        self.addresses
+            .iter()
+            .map(GatewayAddr::ip)
+            .find(IpAddr::is_ipv4)
+    }
+}
+
+/// Blocking iterator over the gateways announced on the local network,
+/// yielding each gateway as soon as it has been resolved.
+///
+/// Created by [`TradfriGateway::discover_gateways`](crate::TradfriGateway::discover_gateways).
+/// A gateway is yielded again when its announcement changes, for example when
+/// another of its addresses has been resolved. The iterator ends when its
+/// timeout runs out or when it is cancelled through a [`DiscoveryCanceller`],
+/// without a timeout it runs until cancelled.
+///
+/// The mDNS daemon is shut down when the iterator ends or is dropped.
+pub struct GatewayDiscovery {
+    daemon: ServiceDaemon,
+    receiver: Receiver<ServiceEvent>,
+    deadline: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+    gateways: HashMap<String, DiscoveredGateway>,
+}
+
+impl GatewayDiscovery {
+    pub(crate) fn start() -> Result<Self, TradfriGatewayError> {
+        let daemon = ServiceDaemon::new()?;
+        let receiver = match daemon.browse(SERVICE_TYPE) {
+            Ok(receiver) => receiver,
+            Err(error) => {
+                let _ = daemon.shutdown();
+                return Err(error.into());
+            }
+        };
+
+        Ok(Self {
+            daemon,
+            receiver,
+            deadline: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            gateways: HashMap::new(),
+        })
+    }
+
+    /// Ends the discovery once the timeout, counted from now, has run out.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    /// Returns a handle that stops the discovery from another thread, for
+    /// example when the user closes the dialog listing the gateways.
+    pub fn canceller(&self) -> DiscoveryCanceller {
+        DiscoveryCanceller {
+            daemon: self.daemon.clone(),
+            cancelled: self.cancelled.clone(),
+        }
+    }
+
+    /// The gateways found so far, with their latest announcement.
+    pub fn gateways(&self) -> Vec<DiscoveredGateway> {
+        let mut gateways: Vec<DiscoveredGateway> = self.gateways.values().cloned().collect();
+        gateways.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+        gateways
+    }
+
+    fn shutdown(&mut self) {
+        if !self.cancelled.swap(true, Ordering::SeqCst) {
+            let _ = self.daemon.shutdown();
+        }
+    }
+}
+
+impl Iterator for GatewayDiscovery {
+    type Item = DiscoveredGateway;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.cancelled.load(Ordering::SeqCst) {
+                return None;
+            }
+
+            let event = match self.deadline {
+                Some(deadline) => self.receiver.recv_deadline(deadline).ok(),
+                None => self.receiver.recv().ok(),
+            };
+            let info = match event {
+                Some(ServiceEvent::ServiceResolved(info)) => info,
+                Some(_) => continue,
+                None => {
+                    self.shutdown();
+                    return None;
+                }
+            };
+
+            if !info.get_hostname().starts_with(HOSTNAME_PREFIX) {
+                continue;
+            }
+
+            let gateway = DiscoveredGateway::new(&info);
+            let previous = self
+                .gateways
+                .insert(info.get_fullname().to_string(), gateway.clone());
+            if previous.as_ref() != Some(&gateway) {
+                return Some(gateway);
+            }
+        }
+    }
+}
+
+impl Drop for GatewayDiscovery {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Stops a [`GatewayDiscovery`], which then ends instead of waiting for the
+/// next gateway.
+#[derive(Clone)]
+pub struct DiscoveryCanceller {
+    daemon: ServiceDaemon,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl DiscoveryCanceller {
+    pub fn cancel(&self) {
+        if !self.cancelled.swap(true, Ordering::SeqCst) {
+            let _ = self.daemon.shutdown();
+        }
+    }
+}
+
+/// Which strategies [`TradfriGateway::discover_with`](crate::TradfriGateway::discover_with)
+/// uses to find gateways, for networks where mDNS is filtered.
+///
+/// All strategies run at the same time for the duration of the timeout, and a
+/// gateway found by several of them is reported once, with all its sources.
+///
+/// * mDNS, enabled by default.
+/// * CoAP multicast, enabled by default, asks the "All CoAP Nodes" groups for
+///   `/.well-known/core`, and keeps the nodes that also answer a DTLS hello on
+///   port 5684.
+/// * Sweeps of address ranges, sending a DTLS hello to port 5684 of every
+///   address and keeping those that answer. None by default.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use tradfri_gateway::{DiscoveryOptions, TradfriGateway};
+///
+/// let options = DiscoveryOptions::new(Duration::from_secs(5))
+///     .with_sweep("192.168.1.0/24".parse()?);
+///
+/// for gateway in TradfriGateway::discover_with(&options)? {
+///     println!("{:?} found by {:?}", gateway.address(), gateway.sources);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveryOptions {
+    timeout: Duration,
+    mdns: bool,
+    coap_multicast: bool,
+    sweeps: Vec<Cidr>,
+}
+
+impl DiscoveryOptions {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            mdns: true,
+            coap_multicast: true,
+            sweeps: Vec::new(),
+        }
+    }
+
+    pub fn with_mdns(mut self, enabled: bool) -> Self {
+        self.mdns = enabled;
+        self
+    }
+
+    pub fn with_coap_multicast(mut self, enabled: bool) -> Self {
+        self.coap_multicast = enabled;
+        self
+    }
+
+    /// Adds an address range to sweep for gateways.
+    pub fn with_sweep(mut self, range: Cidr) -> Self {
+        self.sweeps.push(range);
+        self
+    }
+}
+
+/// Runs the strategies of the options side by side and merges their results.
+///
+/// A strategy that fails, for example because IPv6 is disabled, is logged and
+/// left out. The error is only returned if no gateway was found at all.
+pub(crate) fn discover_with(
+    options: &DiscoveryOptions,
+) -> Result<Vec<DiscoveredGateway>, TradfriGatewayError> {
+    let probes = Probes::start(options);
+
+    let mut failure = None;
+    let mut gateways = Vec::new();
+    if options.mdns {
+        match GatewayDiscovery::start() {
+            Ok(discovery) => {
+                let mut discovery = discovery.with_timeout(options.timeout);
+                discovery.by_ref().for_each(drop);
+                gateways = discovery.gateways();
+            }
+            Err(error) => {
+                log::debug!("mDNS discovery failed: {}", error);
+                failure = Some(error);
+            }
+        }
+    }
+
+    let (addresses, probe_failure) = probes.join();
+    for (address, source) in addresses {
+        merge(&mut gateways, address, source);
+    }
+
+    match failure.or(probe_failure) {
+        Some(error) if gateways.is_empty() => Err(error),
+        _ => Ok(gateways),
+    }
+}
+
+/// Finds the address of a gateway: the first one announced with mDNS, or if
+/// none is announced within the timeout, the first one found by the other
+/// strategies of the options.
+///
+/// The other strategies run in the background from the start, and are
+/// cancelled if mDNS finds a gateway first.
+pub(crate) fn discover_address(
+    options: &DiscoveryOptions,
+) -> Result<GatewayAddr, TradfriGatewayError> {
+    let probes = Probes::start(options);
+
+    let mut failure = None;
+    if options.mdns {
+        match GatewayDiscovery::start() {
+            Ok(discovery) => {
+                let address = discovery
+                    .with_timeout(options.timeout)
+                    .find_map(|gateway| gateway.address());
+                if let Some(address) = address {
+                    return Ok(address);
+                }
+            }
+            Err(error) => {
+                log::debug!("mDNS discovery failed: {}", error);
+                failure = Some(error);
+            }
+        }
+    }
+
+    let (addresses, probe_failure) = probes.join();
+    match addresses.first() {
+        Some((address, _)) => Ok(*address),
+        None => Err(failure
+            .or(probe_failure)
+            .unwrap_or(TradfriGatewayError::DiscoveryTimeout)),
+    }
+}
+
+type Probe = (JoinHandle<io::Result<Vec<GatewayAddr>>>, DiscoverySource);
+
+/// The strategies besides mDNS, each probing on a thread of its own.
+///
+/// Dropping the probes before they have been joined cancels them, and waits
+/// for their threads to end, which takes a fraction of a second.
+struct Probes {
+    probes: Vec<Probe>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Probes {
+    fn start(options: &DiscoveryOptions) -> Self {
+        let timeout = options.timeout;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let mut probes = Vec::new();
+
+        if options.coap_multicast {
+            let cancelled = cancelled.clone();
+            let probe = thread::spawn(move || {
+                // Half of the time to collect the nodes, half to check them.
+                let nodes = probe::coap_multicast(timeout / 2, &cancelled)?;
+                probe::dtls_sweep(nodes, timeout / 2, &cancelled)
+            });
+            probes.push((probe, DiscoverySource::CoapMulticast));
+        }
+        if !options.sweeps.is_empty() {
+            let cancelled = cancelled.clone();
+            let addresses: Vec<GatewayAddr> = options
+                .sweeps
+                .iter()
+                .flat_map(Cidr::hosts)
+                .map(GatewayAddr::from)
+                .collect();
+            let probe = thread::spawn(move || probe::dtls_sweep(addresses, timeout, &cancelled));
+            probes.push((probe, DiscoverySource::Sweep));
+        }
+
+        Self { probes, cancelled }
+    }
+
+    /// Waits for the probes and returns the addresses they found, together
+    /// with the error of the last probe that failed.
+    fn join(
+        mut self,
+    ) -> (
+        Vec<(GatewayAddr, DiscoverySource)>,
+        Option<TradfriGatewayError>,
+    ) {
+        let mut addresses = Vec::new();
+        let mut failure = None;
+
+        for (probe, source) in std::mem::take(&mut self.probes) {
+            match probe.join() {
+                Ok(Ok(found)) => {
+                    addresses.extend(found.into_iter().map(|address| (address, source)))
+                }
+                Ok(Err(error)) => {
+                    log::debug!("{:?} discovery failed: {}", source, error);
+                    failure = Some(TradfriGatewayError::DiscoveryError(error));
+                }
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+
+        (addresses, failure)
+    }
+}
+
+impl Drop for Probes {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        for (probe, _) in self.probes.drain(..) {
+            let _ = probe.join();
+        }
+    }
+}
+
+/// Adds a probed address to the gateway that has it, or as a gateway of its
+/// own. A probe that answered on a known interface scopes the address of the
+/// gateway if the announcement could not.
+fn merge(gateways: &mut Vec<DiscoveredGateway>, address: GatewayAddr, source: DiscoverySource) {
+    let known = gateways.iter_mut().find_map(|gateway| {
+        let index = gateway
+            .addresses
+            .iter()
+            .position(|known| known.ip() == address.ip())?;
+        Some((gateway, index))
+    });
+
+    match known {
+        Some((gateway, index)) => {
+            if gateway.addresses[index].scope_id() == 0 {
+                gateway.addresses[index] = address;
+            }
+            if !gateway.sources.contains(&source) {
+                gateway.sources.push(source);
+            }
+        }
+        None => gateways.push(DiscoveredGateway::probed(address, source)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    const LINK_LOCAL: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0x1ff, 0xfe23, 0x4567, 0x890a);
+
+    fn gateway(addresses: Vec<GatewayAddr>) -> DiscoveredGateway {
+        DiscoveredGateway {
+            hostname: "TRADFRI-Gateway-b072bf257a41.local.".into(),
+            addresses,
+            port: TF_PORT,
+            properties: BTreeMap::new(),
+            sources: vec![DiscoverySource::Mdns],
+        }
+    }
+
+    #[test]
+    fn prefers_routable_addresses() {
+        let ula = GatewayAddr::new(Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1));
+        let scoped = GatewayAddr::with_scope_id(LINK_LOCAL, 3);
+
+        let both = gateway(vec![GatewayAddr::from([192, 168, 1, 10]), ula, scoped]);
+        assert_eq!(both.address(), Some(GatewayAddr::from([192, 168, 1, 10])));
+        assert_eq!(both.ipv4(), Some(IpAddr::from([192, 168, 1, 10])));
+
+        assert_eq!(gateway(vec![scoped, ula]).address(), Some(ula));
+        assert_eq!(gateway(vec![scoped]).address(), Some(scoped));
+    }
+
+    #[test]
+    fn skips_link_local_address_without_scope() {
+        let gateway = gateway(vec![GatewayAddr::new(LINK_LOCAL)]);
+
+        assert_eq!(gateway.address(), None);
+        assert_eq!(gateway.ipv4(), None);
+    }
+
+    #[test]
+    fn scopes_announced_address_with_probe() {
+        let mut gateways = vec![gateway(vec![GatewayAddr::new(LINK_LOCAL)])];
+
+        let scoped = GatewayAddr::with_scope_id(LINK_LOCAL, 3);
+        merge(&mut gateways, scoped, DiscoverySource::CoapMulticast);
+        merge(&mut gateways, scoped, DiscoverySource::CoapMulticast);
+
+        assert_eq!(gateways.len(), 1);
+        assert_eq!(gateways[0].addresses, [scoped]);
+        assert_eq!(
+            gateways[0].sources,
+            [DiscoverySource::Mdns, DiscoverySource::CoapMulticast]
+        );
+        assert_eq!(gateways[0].address(), Some(scoped));
+    }
+
+    #[test]
+    fn cancels_probes_when_dropped() {
+        let options = DiscoveryOptions::new(Duration::from_secs(10))
+            .with_mdns(false)
+            .with_coap_multicast(false)
+            .with_sweep("192.0.2.0/24".parse().unwrap());
+
+        let started = Instant::now();
+        drop(Probes::start(&options));
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn adds_probed_gateway_of_its_own() {
+        let mut gateways = vec![gateway(vec![GatewayAddr::from([192, 168, 1, 10])])];
+
+        merge(
+            &mut gateways,
+            GatewayAddr::from([192, 168, 1, 20]),
+            DiscoverySource::Sweep,
+        );
+
+        assert_eq!(gateways.len(), 2);
+        assert_eq!(gateways[1].hostname, "192.168.1.20");
+        assert_eq!(gateways[1].sources, [DiscoverySource::Sweep]);
+    }
+}
@@ -0,0 +1,366 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use coap::{
+    message::{
+        header::{MessageClass, MessageType},
+        packet::Packet,
+    },
+    CoAPRequest, Method,
+};
+
+use crate::{
+    address::link_local_interfaces,
+    tradfri_coap::{initial_message_id, BUF_SIZE, TF_PORT},
+    GatewayAddr,
+};
+
+/// Port of unencrypted CoAP.
+const COAP_PORT: u16 = 5683;
+
+/// "All CoAP Nodes" multicast addresses (RFC 7252 section 12.8).
+const ALL_COAP_NODES_V4: Ipv4Addr = Ipv4Addr::new(224, 0, 1, 187);
+const ALL_COAP_NODES_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfd);
+
+/// DTLS record content types a server answers a client hello with.
+const HANDSHAKE_CONTENT_TYPE: u8 = 22;
+const ALERT_CONTENT_TYPE: u8 = 21;
+
+/// TLS_PSK_WITH_AES_128_CCM_8, the cipher suite of the gateway.
+const GATEWAY_CIPHER_SUITE: [u8; 2] = [0xc0, 0xa8];
+
+/// Sends a DTLS client hello to port 5684 of every address and returns the
+/// addresses that answered with a DTLS record, meaning that a DTLS server,
+/// such as a gateway, is listening there.
+///
+/// The hello is sent a second time halfway through the timeout to addresses
+/// that have not answered yet, in case a datagram got lost. The sweep ends
+/// early once `cancelled` is set.
+pub(crate) fn dtls_sweep<I>(
+    addresses: I,
+    timeout: Duration,
+    cancelled: &AtomicBool,
+) -> io::Result<Vec<GatewayAddr>>
+where
+    I: IntoIterator<Item = GatewayAddr>,
+{
+    let addresses: Vec<GatewayAddr> = addresses.into_iter().collect();
+    if addresses.is_empty() || cancelled.load(Ordering::SeqCst) {
+        return Ok(Vec::new());
+    }
+    let deadline = Instant::now() + timeout;
+    let resend_at = Instant::now() + timeout / 2;
+    let sockets = Sockets::bind(&addresses)?;
+    let hello = client_hello();
+
+    let mut answered: Vec<GatewayAddr> = Vec::new();
+    let mut resent = false;
+    sockets.send_all(&addresses, TF_PORT, &hello);
+
+    let mut buf = [0u8; BUF_SIZE];
+    while let Some((len, source)) = sockets.receive(&mut buf, deadline, cancelled)? {
+        if !resent && Instant::now() >= resend_at {
+            resent = true;
+            let missing: Vec<GatewayAddr> = addresses
+                .iter()
+                .filter(|address| !answered.contains(address))
+                .copied()
+                .collect();
+            sockets.send_all(&missing, TF_PORT, &hello);
+        }
+
+        let Some(source) = source else { continue };
+        let is_dtls = matches!(
+            buf[..len].first(),
+            Some(&HANDSHAKE_CONTENT_TYPE | &ALERT_CONTENT_TYPE)
+        );
+        if !is_dtls || source.port() != TF_PORT {
+            continue;
+        }
+        let source = unmapped(source);
+        let target = addresses.iter().find(|address| address.ip() == source.ip());
+        if let Some(&target) = target.filter(|target| !answered.contains(target)) {
+            answered.push(target);
+        }
+    }
+
+    Ok(answered)
+}
+
+/// Asks the "All CoAP Nodes" multicast groups for `/.well-known/core` and
+/// returns the addresses of the nodes that answered, IPv6 ones scoped to the
+/// interface they answered on. The probe ends early once `cancelled` is set.
+///
+/// The IPv6 group is link-local, so the request is sent to it once on every
+/// interface with an IPv6 link-local address.
+pub(crate) fn coap_multicast(
+    timeout: Duration,
+    cancelled: &AtomicBool,
+) -> io::Result<Vec<GatewayAddr>> {
+    let deadline = Instant::now() + timeout;
+    let groups: Vec<GatewayAddr> = std::iter::once(GatewayAddr::from(ALL_COAP_NODES_V4))
+        .chain(
+            link_local_interfaces()
+                .into_iter()
+                .map(|scope_id| GatewayAddr::with_scope_id(ALL_COAP_NODES_V6, scope_id)),
+        )
+        .collect();
+    let sockets = Sockets::bind(&groups)?;
+
+    let mut req = CoAPRequest::new();
+    req.message.header.set_type(MessageType::NonConfirmable);
+    req.message.header.set_message_id(initial_message_id());
+    req.message
+        .set_token(initial_message_id().to_be_bytes().to_vec());
+    req.set_method(Method::Get);
+    req.set_path(".well-known/core");
+    let bytes = req
+        .message
+        .to_bytes()
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error.to_string()))?;
+    sockets.send_all(&groups, COAP_PORT, &bytes);
+
+    let mut nodes: Vec<GatewayAddr> = Vec::new();
+    let mut buf = [0u8; BUF_SIZE];
+    while let Some((len, source)) = sockets.receive(&mut buf, deadline, cancelled)? {
+        let Some(source) = source else { continue };
+        let answered = Packet::from_bytes(&buf[..len]).is_ok_and(|packet| {
+            matches!(packet.header.code, MessageClass::Response(_))
+                && packet.get_token() == req.message.get_token()
+        });
+        if answered && !nodes.contains(&unmapped(source)) {
+            nodes.push(unmapped(source));
+        }
+    }
+
+    Ok(nodes)
+}
+
+/// One socket per address family that is needed.
+struct Sockets {
+    v4: Option<UdpSocket>,
+    v6: Option<UdpSocket>,
+}
+
+impl Sockets {
+    /// Binds a socket for each address family in use. A family that can not
+    /// be bound, such as IPv6 on a host without it, is skipped as long as the
+    /// other one can be.
+    fn bind(addresses: &[GatewayAddr]) -> io::Result<Self> {
+        let mut failure = None;
+        let mut bind = |needed: bool, address: IpAddr| -> Option<UdpSocket> {
+            if !needed {
+                return None;
+            }
+            match UdpSocket::bind(SocketAddr::new(address, 0)) {
+                Ok(socket) => Some(socket),
+                Err(error) => {
+                    log::debug!("Could not bind {}: {}", address, error);
+                    failure = Some(error);
+                    None
+                }
+            }
+        };
+
+        let sockets = Self {
+            v4: bind(
+                addresses.iter().any(|address| address.ip().is_ipv4()),
+                Ipv4Addr::UNSPECIFIED.into(),
+            ),
+            v6: bind(
+                addresses.iter().any(|address| address.ip().is_ipv6()),
+                Ipv6Addr::UNSPECIFIED.into(),
+            ),
+        };
+
+        match failure {
+            Some(error) if sockets.v4.is_none() && sockets.v6.is_none() => Err(error),
+            _ => Ok(sockets),
+        }
+    }
+
+    /// Sends the datagram to every address, addresses that can not be reached,
+    /// such as an IPv6 group without a route, are skipped.
+    fn send_all(&self, addresses: &[GatewayAddr], port: u16, bytes: &[u8]) {
+        for address in addresses {
+            let socket = match address.ip() {
+                IpAddr::V4(_) => self.v4.as_ref(),
+                IpAddr::V6(_) => self.v6.as_ref(),
+            };
+            if let Some(socket) = socket {
+                if let Err(error) = socket.send_to(bytes, address.socket_addr(port)) {
+                    log::debug!("Could not probe {}: {}", address, error);
+                }
+            }
+        }
+    }
+
+    /// Waits for the next datagram on either socket until the deadline,
+    /// returns `None` once the deadline has passed or the probe has been
+    /// cancelled. The source is `None` when the wait was cut short to poll the
+    /// other socket or to check for cancellation.
+    fn receive(
+        &self,
+        buf: &mut [u8],
+        deadline: Instant,
+        cancelled: &AtomicBool,
+    ) -> io::Result<Option<(usize, Option<SocketAddr>)>> {
+        let now = Instant::now();
+        if now >= deadline || cancelled.load(Ordering::SeqCst) {
+            return Ok(None);
+        }
+
+        let sockets: Vec<&UdpSocket> = self.v4.iter().chain(self.v6.iter()).collect();
+        if sockets.is_empty() {
+            thread::sleep((deadline - now).min(Duration::from_millis(50)));
+            return Ok(Some((0, None)));
+        }
+
+        // Take turns on the sockets in short slices.
+        let slice = (deadline - now)
+            .min(Duration::from_millis(50))
+            .max(Duration::from_millis(1));
+        for socket in sockets {
+            socket.set_read_timeout(Some(slice / 2 + Duration::from_millis(1)))?;
+            match socket.recv_from(buf) {
+                Ok((len, source)) => return Ok(Some((len, Some(source)))),
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        io::ErrorKind::WouldBlock
+                            | io::ErrorKind::TimedOut
+                            | io::ErrorKind::ConnectionRefused
+                            | io::ErrorKind::ConnectionReset
+                    ) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(Some((0, None)))
+    }
+}
+
+/// The address of a datagram source, with IPv4-mapped IPv6 addresses mapped
+/// back to IPv4.
+fn unmapped(source: SocketAddr) -> GatewayAddr {
+    match source {
+        SocketAddr::V6(address) => match address.ip().to_ipv4_mapped() {
+            Some(ip) => GatewayAddr::new(ip),
+            None => address.into(),
+        },
+        source => source.into(),
+    }
+}
+
+/// A minimal DTLS 1.2 client hello offering the cipher suite of the gateway.
+fn client_hello() -> Vec<u8> {
+    let mut random = [0u8; 32];
+    if openssl::rand::rand_bytes(&mut random).is_err() {
+        log::debug!("Using a fixed client random for probing");
+    }
+
+    let mut body = vec![0xfe, 0xfd]; // DTLS 1.2
+    body.extend_from_slice(&random);
+    body.push(0); // session id
+    body.push(0); // cookie
+    body.extend_from_slice(&(GATEWAY_CIPHER_SUITE.len() as u16).to_be_bytes());
+    body.extend_from_slice(&GATEWAY_CIPHER_SUITE);
+    body.extend_from_slice(&[1, 0]); // null compression
+
+    let length = (body.len() as u32).to_be_bytes();
+    let mut handshake = vec![1]; // client hello
+    handshake.extend_from_slice(&length[1..]);
+    handshake.extend_from_slice(&[0, 0]); // message sequence
+    handshake.extend_from_slice(&[0, 0, 0]); // fragment offset
+    handshake.extend_from_slice(&length[1..]); // fragment length
+    handshake.extend_from_slice(&body);
+
+    let mut record = vec![HANDSHAKE_CONTENT_TYPE, 0xfe, 0xff]; // DTLS 1.0 record layer
+    record.extend_from_slice(&[0, 0]); // epoch
+    record.extend_from_slice(&[0, 0, 0, 0, 0, 0]); // sequence number
+    record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+    record.extend_from_slice(&handshake);
+
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddrV6;
+
+    use super::*;
+
+    #[test]
+    fn builds_dtls_client_hello() {
+        let hello = client_hello();
+
+        // Record header: handshake content type, DTLS 1.0, epoch 0 and
+        // sequence number 0, then the length of the handshake message.
+        assert_eq!(&hello[..11], &[22, 0xfe, 0xff, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            usize::from(u16::from_be_bytes([hello[11], hello[12]])),
+            hello.len() - 13
+        );
+
+        // Handshake header: a client hello in one fragment.
+        let handshake = &hello[13..];
+        let body_length = handshake.len() - 12;
+        assert_eq!(handshake[0], 1);
+        assert_eq!(&handshake[1..4], &(body_length as u32).to_be_bytes()[1..]);
+        assert_eq!(&handshake[4..9], &[0, 0, 0, 0, 0]);
+        assert_eq!(&handshake[9..12], &handshake[1..4]);
+
+        // Body: DTLS 1.2, random, empty session id and cookie, the cipher
+        // suite of the gateway and null compression.
+        let body = &handshake[12..];
+        assert_eq!(&body[..2], &[0xfe, 0xfd]);
+        assert_eq!(&body[34..], &[0, 0, 0, 2, 0xc0, 0xa8, 1, 0]);
+    }
+
+    #[test]
+    fn uses_fresh_client_random() {
+        assert_ne!(client_hello()[27..59], client_hello()[27..59]);
+    }
+
+    #[test]
+    fn keeps_scope_of_answers() {
+        let ip = Ipv6Addr::new(0xfe80, 0, 0, 0, 0x1ff, 0xfe23, 0x4567, 0x890a);
+
+        assert_eq!(
+            unmapped(SocketAddr::V6(SocketAddrV6::new(ip, TF_PORT, 0, 3))),
+            GatewayAddr::with_scope_id(ip, 3)
+        );
+        assert_eq!(
+            unmapped(SocketAddr::V6(SocketAddrV6::new(
+                Ipv4Addr::new(192, 168, 1, 10).to_ipv6_mapped(),
+                TF_PORT,
+                0,
+                0
+            ))),
+            GatewayAddr::from([192, 168, 1, 10])
+        );
+    }
+
+    #[test]
+    fn stops_sweeping_when_cancelled() {
+        let cancelled = AtomicBool::new(false);
+        let addresses = [GatewayAddr::from([192, 0, 2, 1])];
+
+        let started = Instant::now();
+        let found = thread::scope(|scope| {
+            let sweep = scope.spawn(|| dtls_sweep(addresses, Duration::from_secs(10), &cancelled));
+            thread::sleep(Duration::from_millis(100));
+            cancelled.store(true, Ordering::SeqCst);
+            sweep.join().unwrap()
+        })
+        .unwrap();
+
+        assert!(found.is_empty());
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -1,24 +1,44 @@
-use std::{
-    cell::RefCell,
-    net::IpAddr,
-    rc::Rc,
-    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
-};
+use std::{sync::Arc, time::Duration};
 
-use coap::{CoAPRequest, CoAPResponse};
-use mdns_sd::{ServiceDaemon, ServiceEvent};
+use chrono::{FixedOffset, Utc};
+use coap::{CoAPRequest, CoAPResponse, Method};
+use serde_json::Value;
 
 use crate::{
+    credentials::StoredConnection,
     device::Device,
-    tradfri_coap::{TradfriAuthenticator, TradfriConnection},
-    DeviceUpdate, Group, GroupUpdate,
+    discovery, gateway_time,
+    pairing::{self, PairingAttempts},
+    raw::raw_request,
+    requests,
+    response_code::check_response,
+    scene,
+    tradfri_coap::TradfriAuthenticator,
+    CoapError, CommissioningWindow, CredentialError, CredentialStore, Credentials, DeviceError,
+    DeviceObserver, DeviceUpdate, DiscoveredGateway, DiscoveryCanceller, DiscoveryOptions,
+    DtlsTransport, FactoryResetConfirmation, GatewayAddr, GatewayDiscovery, GatewayInfo,
+    GatewayNotification, GatewayTime, Group, GroupError, GroupObserver, GroupUpdate, LightSetting,
+    NotificationObserver, ObserveChannel, OtaObserver, OtaStatus, PairingError, PairingOptions,
+    RawResponse, ResponseCode, Scene, SceneError, SceneUpdate, Secret, Transport, UpdateWindow,
 };
 
+/// Time [`TradfriGateway::discover_ip`] waits for a gateway to be announced.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Handle to a TRÅDFRI gateway.
+///
+/// Requests are carried by a [`Transport`]. The `from_*` constructors talk to
+/// a real gateway over a [`DtlsTransport`], [`TradfriGateway::from_transport`]
+/// accepts any other transport, such as a [`MemoryTransport`](crate::MemoryTransport)
+/// in tests.
+///
+/// All clones of a gateway, and all lights, groups and iterators created from
+/// it, share one transport, and with it one long-lived DTLS session. The
+/// gateway is `Send + Sync`, so one handle, or clones of it, can be used from
+/// several threads at once.
 #[derive(Debug, Clone)]
 pub struct TradfriGateway {
-    address: IpAddr,
-    identifier: String,
-    session_key: String,
+    transport: Arc<dyn Transport>,
 }
 
 impl TradfriGateway {
@@ -26,24 +46,178 @@ impl TradfriGateway {
         Self::from_gateway_code_and_addr(Self::discover_ip()?, gateway_code)
     }
 
-    pub fn from_gateway_code_and_addr<A: Into<IpAddr> + Clone>(
+    pub fn from_gateway_code_and_addr<A: Into<GatewayAddr>>(
         address: A,
         gateway_code: &str,
     ) -> Result<Self, TradfriGatewayError> {
-        let identifier = Self::generate_identifier();
-        let session_key =
-            TradfriAuthenticator::authenticate(address.clone(), &identifier, gateway_code, 10)?;
+        Self::from_credentials(&Self::pair(address, gateway_code)?)
+    }
+
+    /// Pairs with the gateway using the security code printed on its bottom
+    /// and returns the credentials to connect with, including the address.
+    ///
+    /// Each pairing registers a new identifier on the gateway, keep the
+    /// credentials, for example in a [`CredentialStore`], rather than pairing
+    /// on every start.
+    pub fn pair<A: Into<GatewayAddr>>(
+        address: A,
+        gateway_code: &str,
+    ) -> Result<Credentials, TradfriGatewayError> {
+        Self::pair_with(address, gateway_code, &PairingOptions::default())
+    }
+
+    /// Pairs with the gateway like [`TradfriGateway::pair`], retrying with a
+    /// fresh identifier as configured in the options.
+    ///
+    /// A security code that is malformed or rejected by the gateway fails
+    /// with the matching [`PairingError`] without retrying.
+    pub fn pair_with<A: Into<GatewayAddr>>(
+        address: A,
+        gateway_code: &str,
+        options: &PairingOptions,
+    ) -> Result<Credentials, TradfriGatewayError> {
+        let address = address.into();
+        let security_code = pairing::security_code(gateway_code)?;
+        let mut attempts = PairingAttempts::new(options)?;
+
+        loop {
+            match TradfriAuthenticator::authenticate(
+                address,
+                attempts.identifier(),
+                &security_code,
+                options.timeout(),
+            ) {
+                Ok(session_key) => {
+                    return Ok(
+                        Credentials::new(attempts.identifier(), session_key).with_address(address)
+                    )
+                }
+                Err(error) => attempts.retry(error)?,
+            }
+        }
+    }
 
-        Ok(TradfriGateway::from_identifier_and_session_key_and_addr(
+    /// Connects with stored credentials, discovering the gateway if they have
+    /// no address.
+    pub fn from_credentials(credentials: &Credentials) -> Result<Self, TradfriGatewayError> {
+        let address = match credentials.address {
+            Some(address) => address,
+            None => Self::discover_ip()?,
+        };
+
+        Ok(Self::from_identifier_and_session_key_and_addr(
             address,
-            &identifier,
-            &session_key,
+            &credentials.identifier,
+            credentials.session_key.clone(),
         ))
     }
 
-    pub fn from_identifier_and_session_key(
+    /// Connects with the credentials in the store, or, if the store is empty,
+    /// discovers the gateway, pairs with it and saves the credentials for the
+    /// next start.
+    ///
+    /// If the stored address no longer answers, for example because the
+    /// gateway got a new address from DHCP, the gateway is discovered again.
+    /// A newly discovered address is saved in the store.
+    ///
+    /// ```no_run
+    /// use tradfri_gateway::{FileCredentialStore, TradfriGateway};
+    ///
+    /// let store = FileCredentialStore::default_location().unwrap();
+    /// let gateway = TradfriGateway::from_credential_store(&store, "security code").unwrap();
+    /// ```
+    pub fn from_credential_store<S: CredentialStore + ?Sized>(
+        store: &S,
+        gateway_code: &str,
+    ) -> Result<Self, TradfriGatewayError> {
+        let (gateway, changed) = Self::from_stored_credentials(store.load()?, None, gateway_code)?;
+        if let Some(credentials) = changed {
+            store.save(&credentials)?;
+        }
+
+        Ok(gateway)
+    }
+
+    /// Like [`TradfriGateway::from_credential_store`], but for the gateway at
+    /// the given address. A stored address that differs is replaced.
+    pub fn from_credential_store_and_addr<S: CredentialStore + ?Sized, A: Into<GatewayAddr>>(
+        store: &S,
+        address: A,
+        gateway_code: &str,
+    ) -> Result<Self, TradfriGatewayError> {
+        let (gateway, changed) =
+            Self::from_stored_credentials(store.load()?, Some(address.into()), gateway_code)?;
+        if let Some(credentials) = changed {
+            store.save(&credentials)?;
+        }
+
+        Ok(gateway)
+    }
+
+    /// Connects with the loaded credentials, or pairs if there are none.
+    /// Returns the credentials to save along with the gateway if they are new
+    /// or their address changed.
+    pub(crate) fn from_stored_credentials(
+        stored: Option<Credentials>,
+        address: Option<GatewayAddr>,
+        gateway_code: &str,
+    ) -> Result<(Self, Option<Credentials>), TradfriGatewayError> {
+        match StoredConnection::plan(stored, address) {
+            StoredConnection::Pair(address) => {
+                let address = match address {
+                    Some(address) => address,
+                    None => Self::discover_ip()?,
+                };
+                let credentials = Self::pair(address, gateway_code)?;
+                Ok((Self::from_credentials(&credentials)?, Some(credentials)))
+            }
+            StoredConnection::Connect {
+                credentials,
+                changed,
+            } => {
+                let gateway = Self::from_credentials(&credentials)?;
+                Ok((gateway, changed.then_some(credentials)))
+            }
+            StoredConnection::Reconnect(credentials) => Self::reconnect(credentials),
+        }
+    }
+
+    /// Connects to the stored address, or to a newly discovered one if the
+    /// stored address is missing or does not answer the handshake.
+    fn reconnect(
+        credentials: Credentials,
+    ) -> Result<(Self, Option<Credentials>), TradfriGatewayError> {
+        let transport = |address: GatewayAddr| {
+            DtlsTransport::new(
+                address,
+                &credentials.identifier,
+                credentials.session_key.clone(),
+            )
+        };
+
+        if let Some(address) = credentials.address {
+            let transport = transport(address);
+            match transport.connect() {
+                Ok(()) => return Ok((Self::from_transport(transport), None)),
+                Err(error) if is_unreachable(&error) => {
+                    log::debug!("Gateway does not answer at {}: {}", address, error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        let address = Self::discover_ip()?;
+        if credentials.address == Some(address) {
+            return Err(CoapError::Unreachable.into());
+        }
+
+        let gateway = Self::from_transport(transport(address));
+        Ok((gateway, Some(credentials.with_address(address))))
+    }
+
+    pub fn from_identifier_and_session_key<K: Into<Secret>>(
         identifier: &str,
-        session_key: &str,
+        session_key: K,
     ) -> Result<Self, TradfriGatewayError> {
         Ok(Self::from_identifier_and_session_key_and_addr(
             Self::discover_ip()?,
@@ -52,222 +226,472 @@ impl TradfriGateway {
         ))
     }
 
-    pub fn from_identifier_and_session_key_and_addr<A: Into<IpAddr>>(
+    pub fn from_identifier_and_session_key_and_addr<A: Into<GatewayAddr>, K: Into<Secret>>(
         address: A,
         identifier: &str,
-        session_key: &str,
+        session_key: K,
     ) -> Self {
+        Self::from_transport(DtlsTransport::new(address, identifier, session_key))
+    }
+
+    /// Creates a gateway that sends its requests over the given transport.
+    ///
+    /// Use this to configure a [`DtlsTransport`], for example with a shorter
+    /// request timeout, or to drive the gateway with a mock.
+    pub fn from_transport<T: Transport + 'static>(transport: T) -> Self {
         Self {
-            address: address.into(),
-            identifier: identifier.into(),
-            session_key: session_key.into(),
+            transport: Arc::new(transport),
         }
     }
 
-    pub fn devices(&mut self) -> Result<DeviceIterator, TradfriGatewayError> {
-        let connection = Rc::new(RefCell::new(self.create_connection()?));
-        let ids = {
-            let mut connection_borrowed = connection.borrow_mut();
-            self.device_ids(&mut connection_borrowed)?
-        };
+    pub fn devices(&self) -> Result<DeviceIterator, TradfriGatewayError> {
         Ok(DeviceIterator {
-            ids,
-            connection,
-            gateway: Rc::new(RefCell::new(self.clone())),
+            ids: self.device_ids()?,
+            gateway: self.clone(),
         })
     }
 
-    pub fn device(&mut self, id: u32) -> Result<Device, TradfriGatewayError> {
-        let mut connection = self.create_connection()?;
-        self.device_with_connection(id, &mut connection)
+    pub fn device(&self, id: u32) -> Result<Device, TradfriGatewayError> {
+        let response = self.coap_request(requests::device(id))?;
+        Device::new(self.clone(), &response.message.payload)
+            .map_err(|e| TradfriGatewayError::DeviceError(id, Box::new(e)))
     }
 
-    pub fn device_with_connection(
-        &mut self,
-        id: u32,
-        connection: &mut TradfriConnection,
-    ) -> Result<Device, TradfriGatewayError> {
-        let mut req = coap::CoAPRequest::new();
-        req.set_path(&format!("15001/{}", id));
-        req.set_method(coap::Method::Get);
-
-        let response = self.coap_request(req, Some(connection))?;
-        let device = match Device::new(self.clone(), &response.message.payload) {
-            Ok(d) => d,
-            Err(e) => {
-                return Err(TradfriGatewayError::DeviceError(
-                    id.to_string(),
-                    e.to_string(),
-                ))
-            }
-        };
+    /// Reads the state of the gateway itself, such as its firmware version
+    /// and time, see [`GatewayInfo`].
+    pub fn info(&self) -> Result<GatewayInfo, TradfriGatewayError> {
+        let response = self.coap_request(requests::gateway_info())?;
+        Ok(serde_json::from_slice(&response.message.payload)?)
+    }
+
+    /// Reboots the gateway. Its devices keep their state, but requests fail
+    /// until the gateway is back, which takes about a minute.
+    pub fn reboot(&self) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::reboot())?;
+        Ok(())
+    }
+
+    /// Resets the gateway to factory defaults, removing all devices, groups,
+    /// scenes and paired clients, including this one.
+    ///
+    /// Fails with [`TradfriGatewayError::FactoryResetNotConfirmed`], without
+    /// resetting, if the confirmation names another gateway, and with
+    /// [`TradfriGatewayError::GatewayIdNotReported`] if the gateway does not
+    /// report its id to compare with.
+    ///
+    /// ```no_run
+    /// use tradfri_gateway::{FactoryResetConfirmation, TradfriGateway};
+    ///
+    /// let gateway = TradfriGateway::from_identifier_and_session_key_and_addr(
+    ///     [192, 168, 1, 10],
+    ///     "identifier",
+    ///     "session key",
+    /// );
+    /// let confirmation = FactoryResetConfirmation::for_gateway("7e0f0f0f0f0f0f0f");
+    /// gateway.factory_reset(&confirmation).unwrap();
+    /// ```
+    pub fn factory_reset(
+        &self,
+        confirmation: &FactoryResetConfirmation,
+    ) -> Result<(), TradfriGatewayError> {
+        confirmation.confirm(self.info()?.id)?;
 
-        Ok(device)
+        self.coap_request(requests::factory_reset())?;
+        Ok(())
+    }
+
+    /// Lets the gateway accept new devices for the given duration, rounded
+    /// up to whole seconds.
+    pub fn open_commissioning(
+        &self,
+        duration: Duration,
+    ) -> Result<CommissioningWindow, TradfriGatewayError> {
+        let window = CommissioningWindow::open_for(duration);
+        self.set_commissioning(window.duration.as_secs())?;
+        Ok(window)
     }
 
-    fn device_ids(
-        &mut self,
-        connection: &mut TradfriConnection,
-    ) -> Result<Vec<u32>, TradfriGatewayError> {
-        let mut req = CoAPRequest::new();
-        req.set_path("15001");
-        req.set_method(coap::Method::Get);
+    /// Closes the window opened with [`TradfriGateway::open_commissioning`]
+    /// before it runs out.
+    pub fn close_commissioning(&self) -> Result<(), TradfriGatewayError> {
+        self.set_commissioning(0)
+    }
 
-        let response = self.coap_request(req, Some(connection))?;
-        let device_ids: Vec<u32> = serde_json::from_slice(&response.message.payload)?;
+    fn set_commissioning(&self, seconds: u64) -> Result<(), TradfriGatewayError> {
+        self.update_gateway(&serde_json::json!({ "9061": seconds }))
+    }
 
-        Ok(device_ids)
+    /// Reads the firmware update status of the gateway, poll it or use
+    /// [`TradfriGateway::observe_ota`] to follow the progress of an update.
+    pub fn ota_status(&self) -> Result<OtaStatus, TradfriGatewayError> {
+        Ok(self.info()?.into())
     }
 
-    pub fn groups(&mut self) -> Result<GroupIterator, TradfriGatewayError> {
-        let connection = Rc::new(RefCell::new(self.create_connection()?));
-        let ids = {
-            let mut connection_borrowed = connection.borrow_mut();
-            self.group_ids(&mut connection_borrowed)?
-        };
+    /// Makes the gateway look for new firmware for itself and its devices,
+    /// the result shows up in [`TradfriGateway::ota_status`].
+    pub fn check_for_updates(&self) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::check_for_updates())?;
+        Ok(())
+    }
+
+    /// Installs downloaded firmware right away instead of waiting for the
+    /// update window.
+    pub fn start_update(&self) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::start_update())?;
+        Ok(())
+    }
+
+    /// Sets the daily window in which the gateway installs updates on its own.
+    pub fn set_update_window(&self, window: &UpdateWindow) -> Result<(), TradfriGatewayError> {
+        self.update_gateway(&window.update())
+    }
+
+    /// Observes the firmware update status of the gateway, see [`OtaObserver`].
+    pub fn observe_ota(&self) -> Result<OtaObserver, TradfriGatewayError> {
+        OtaObserver::new(self)
+    }
+
+    /// Reads the clock, time zone and NTP server of the gateway, see
+    /// [`GatewayTime`] for how far it is off from the local clock.
+    pub fn time(&self) -> Result<GatewayTime, TradfriGatewayError> {
+        let info = self.info()?;
+        GatewayTime::from_info(info, Utc::now()).ok_or(TradfriGatewayError::TimeNotReported)
+    }
+
+    /// Sets the NTP server the gateway synchronizes its clock with.
+    pub fn set_ntp_server(&self, server: &str) -> Result<(), TradfriGatewayError> {
+        self.update_gateway(&gateway_time::set_ntp_server(server))
+    }
+
+    /// Moves the gateway to the time zone with the given offset from UTC,
+    /// keeping its clock as it is. Smart tasks run by the local time of the
+    /// gateway, so they shift with it.
+    ///
+    /// The gateway has no attribute of its own for the time zone, it is the
+    /// offset of the local time under `9060`. This writes the current time
+    /// of the gateway there with the new offset, and reads the time back, as
+    /// IKEA does not document `9060` as writable: a gateway that keeps its
+    /// time zone fails with [`TradfriGatewayError::TimeZoneNotChanged`].
+    ///
+    /// ```no_run
+    /// use chrono::FixedOffset;
+    /// use tradfri_gateway::TradfriGateway;
+    ///
+    /// let gateway = TradfriGateway::from_identifier_and_session_key_and_addr(
+    ///     [192, 168, 1, 10],
+    ///     "identifier",
+    ///     "session key",
+    /// );
+    /// gateway
+    ///     .set_time_zone(FixedOffset::east_opt(2 * 3600).unwrap())
+    ///     .unwrap();
+    /// ```
+    pub fn set_time_zone(&self, offset: FixedOffset) -> Result<(), TradfriGatewayError> {
+        let now = self.time()?.utc;
+        self.update_gateway(&gateway_time::set_time_zone(now, offset))?;
+
+        gateway_time::check_time_zone(&self.time()?, offset)
+    }
+
+    /// Reads the notification list of the gateway, such as reboots and
+    /// available firmware, see [`GatewayNotification`].
+    pub fn notifications(&self) -> Result<Vec<GatewayNotification>, TradfriGatewayError> {
+        let response = self.coap_request(requests::notifications())?;
+        Ok(serde_json::from_slice(&response.message.payload)?)
+    }
+
+    /// Observes the notification list of the gateway, yielding only
+    /// notifications added after the observation started, see
+    /// [`NotificationObserver`].
+    ///
+    /// ```no_run
+    /// use tradfri_gateway::TradfriGateway;
+    ///
+    /// let gateway = TradfriGateway::from_identifier_and_session_key_and_addr(
+    ///     [192, 168, 1, 10],
+    ///     "identifier",
+    ///     "session key",
+    /// );
+    /// for notification in gateway.observe_notifications().unwrap() {
+    ///     let notification = notification.unwrap();
+    ///     println!("{:?}: {:?}", notification.created_at(), notification);
+    /// }
+    /// ```
+    pub fn observe_notifications(&self) -> Result<NotificationObserver, TradfriGatewayError> {
+        NotificationObserver::new(self)
+    }
+
+    fn update_gateway(&self, update: &Value) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::update_gateway(update)?)?;
+        Ok(())
+    }
+
+    fn device_ids(&self) -> Result<Vec<u32>, TradfriGatewayError> {
+        requests::ids(&self.coap_request(requests::device_ids())?)
+    }
+
+    pub fn groups(&self) -> Result<GroupIterator, TradfriGatewayError> {
         Ok(GroupIterator {
-            ids,
-            connection,
-            gateway: Rc::new(RefCell::new(self.clone())),
+            ids: self.group_ids()?,
+            gateway: self.clone(),
         })
     }
 
-    pub fn group(&mut self, id: u32) -> Result<Group, TradfriGatewayError> {
-        let mut connection = self.create_connection()?;
-        self.group_with_connection(id, &mut connection)
+    pub fn group(&self, id: u32) -> Result<Group, TradfriGatewayError> {
+        let response = self.coap_request(requests::group(id))?;
+        Group::new(self.clone(), &response.message.payload)
+            .map_err(|e| TradfriGatewayError::GroupError(id, Box::new(e)))
     }
 
-    pub fn group_with_connection(
-        &mut self,
-        id: u32,
-        connection: &mut TradfriConnection,
-    ) -> Result<Group, TradfriGatewayError> {
-        let mut req = coap::CoAPRequest::new();
-        req.set_path(&format!("15004/{}", id));
-        req.set_method(coap::Method::Get);
-
-        let response = self.coap_request(req, Some(connection))?;
-        let group = match Group::new(self.clone(), &response.message.payload) {
-            Ok(d) => d,
-            Err(e) => {
-                return Err(TradfriGatewayError::DeviceError(
-                    id.to_string(),
-                    e.to_string(),
-                ))
-            }
-        };
+    fn group_ids(&self) -> Result<Vec<u32>, TradfriGatewayError> {
+        requests::ids(&self.coap_request(requests::group_ids())?)
+    }
+
+    /// Iterates over the scenes of a group, one request per scene.
+    pub fn scenes(&self, group_id: u32) -> Result<SceneIterator, TradfriGatewayError> {
+        Ok(SceneIterator {
+            group_id,
+            ids: self.scene_ids(group_id)?,
+            gateway: self.clone(),
+        })
+    }
+
+    pub fn scene(&self, group_id: u32, id: u32) -> Result<Scene, TradfriGatewayError> {
+        let response = self.coap_request(requests::scene(group_id, id))?;
+        Scene::new(self.clone(), group_id, &response.message.payload)
+            .map_err(|e| TradfriGatewayError::SceneError(id, Box::new(e)))
+    }
+
+    /// Creates a scene in a group that puts the lights in the given states.
+    ///
+    /// The id of the new scene is taken from the `2.01 Created` response of the
+    /// gateway, which fails with [`TradfriGatewayError::UnexpectedResponse`] if
+    /// it does not carry one.
+    pub fn create_scene(
+        &self,
+        group_id: u32,
+        name: &str,
+        lights: Vec<LightSetting>,
+    ) -> Result<Scene, TradfriGatewayError> {
+        let req = requests::create_scene(group_id, name, lights)?;
+        let response = self.coap_request(req)?;
+        self.scene(group_id, scene::created_id(&response)?)
+    }
 
-        Ok(group)
+    fn scene_ids(&self, group_id: u32) -> Result<Vec<u32>, TradfriGatewayError> {
+        requests::ids(&self.coap_request(requests::scene_ids(group_id))?)
     }
 
-    fn group_ids(
-        &mut self,
-        connection: &mut TradfriConnection,
-    ) -> Result<Vec<u32>, TradfriGatewayError> {
-        let mut req = CoAPRequest::new();
-        req.set_path("15004");
-        req.set_method(coap::Method::Get);
+    /// Observes a single device, yielding its state every time it changes.
+    ///
+    /// The observation uses a connection of its own and blocks while waiting
+    /// for the next notification, see [`DeviceObserver`].
+    pub fn observe_device(&self, id: u32) -> Result<DeviceObserver, TradfriGatewayError> {
+        DeviceObserver::device(self, id)
+    }
+
+    /// Observes every device on the gateway, including devices paired after
+    /// the observation was started.
+    pub fn observe_devices(&self) -> Result<DeviceObserver, TradfriGatewayError> {
+        DeviceObserver::devices(self)
+    }
+
+    /// Observes a single group, yielding its state every time it changes.
+    pub fn observe_group(&self, id: u32) -> Result<GroupObserver, TradfriGatewayError> {
+        GroupObserver::group(self, id)
+    }
+
+    /// Observes every group on the gateway, including groups created after the
+    /// observation was started.
+    pub fn observe_groups(&self) -> Result<GroupObserver, TradfriGatewayError> {
+        GroupObserver::groups(self)
+    }
+
+    /// Reads the resource at a raw path, such as `15011/15012` for the gateway
+    /// details, for resources this crate does not model yet.
+    pub fn get(&self, path: &str) -> Result<RawResponse, TradfriGatewayError> {
+        self.raw(Method::Get, path, None)
+    }
+
+    /// Changes the resource at a raw path, the gateway merges the given JSON
+    /// into the resource.
+    pub fn put(&self, path: &str, value: &Value) -> Result<RawResponse, TradfriGatewayError> {
+        self.raw(Method::Put, path, Some(value))
+    }
+
+    /// Posts JSON to a raw path, used by the gateway to create resources and
+    /// to trigger actions.
+    pub fn post(&self, path: &str, value: &Value) -> Result<RawResponse, TradfriGatewayError> {
+        self.raw(Method::Post, path, Some(value))
+    }
+
+    /// Deletes the resource at a raw path.
+    pub fn delete(&self, path: &str) -> Result<RawResponse, TradfriGatewayError> {
+        self.raw(Method::Delete, path, None)
+    }
 
-        let response = self.coap_request(req, Some(connection))?;
-        let device_ids: Vec<u32> = serde_json::from_slice(&response.message.payload)?;
+    fn raw(
+        &self,
+        method: Method,
+        path: &str,
+        value: Option<&Value>,
+    ) -> Result<RawResponse, TradfriGatewayError> {
+        let response = self.coap_request(raw_request(method, path, value)?)?;
 
-        Ok(device_ids)
+        Ok(RawResponse::new(&response))
     }
 
-    pub(crate) fn create_connection(&self) -> Result<TradfriConnection, TradfriGatewayError> {
-        Ok(TradfriConnection::new(
-            self.address,
-            self.identifier.as_bytes(),
-            self.session_key.as_bytes(),
-        )?)
+    pub(crate) fn open_observe_channel(
+        &self,
+    ) -> Result<Box<dyn ObserveChannel>, TradfriGatewayError> {
+        self.transport.open_observe_channel()
     }
 
     pub(crate) fn update_device(
-        &mut self,
+        &self,
         id: u32,
         update: &DeviceUpdate,
-        connection: Option<&mut TradfriConnection>,
     ) -> Result<(), TradfriGatewayError> {
-        let mut req = coap::CoAPRequest::new();
-        req.set_path(&format!("15001/{}", id));
-        req.set_method(coap::Method::Put);
-        req.message.payload = serde_json::to_vec(&update)?;
-
-        self.coap_request(req, connection)?;
+        self.coap_request(requests::update_device(id, update)?)?;
 
         Ok(())
     }
 
     pub(crate) fn update_group(
-        &mut self,
+        &self,
         id: u32,
         update: &GroupUpdate,
-        connection: Option<&mut TradfriConnection>,
     ) -> Result<(), TradfriGatewayError> {
-        let mut req = coap::CoAPRequest::new();
-        req.set_path(&format!("15004/{}", id));
-        req.set_method(coap::Method::Put);
-        req.message.payload = serde_json::to_vec(&update)?;
-
-        self.coap_request(req, connection)?;
+        self.coap_request(requests::update_group(id, update)?)?;
 
         Ok(())
     }
 
-    fn coap_request(
+    pub(crate) fn update_scene(
         &self,
-        req: CoAPRequest,
-        connection: Option<&mut TradfriConnection>,
-    ) -> Result<CoAPResponse, TradfriGatewayError> {
-        if let Some(connection) = connection {
-            connection.send(req)?;
-            Ok(connection.receive()?)
-        } else {
-            let mut connection = self.create_connection()?;
-            connection.send(req)?;
-            Ok(connection.receive()?)
-        }
+        group_id: u32,
+        id: u32,
+        update: &SceneUpdate,
+    ) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::update_scene(group_id, id, update)?)?;
+
+        Ok(())
     }
 
-    fn generate_identifier() -> String {
-        format!(
-            "user{}",
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or(Duration::from_secs(123))
-                .as_secs()
-        )
-    }
-
-    pub fn discover_ip() -> Result<IpAddr, TradfriGatewayError> {
-        let mdns = ServiceDaemon::new()?;
-        let receiver = mdns.browse("_coap._udp.local.")?;
-
-        let start_time = Instant::now();
-        while let Ok(event) = receiver.recv() {
-            if let ServiceEvent::ServiceResolved(info) = event {
-                if info.get_hostname().starts_with("TRADFRI-Gateway-") {
-                    return Ok(if let Some(ip) = info.get_addresses_v4().iter().next() {
-                        std::net::IpAddr::V4(**ip)
-                    } else {
-                        continue;
-                    });
-                }
-            }
+    pub(crate) fn delete_scene(&self, group_id: u32, id: u32) -> Result<(), TradfriGatewayError> {
+        self.coap_request(requests::delete_scene(group_id, id))?;
 
-            if Instant::now() - start_time > Duration::from_secs(15) {
-                break;
-            }
-        }
+        Ok(())
+    }
+
+    /// Sends a request over the transport, error responses from the gateway
+    /// are returned as errors.
+    fn coap_request(&self, req: CoAPRequest) -> Result<CoAPResponse, TradfriGatewayError> {
+        check_response(self.transport.request(req)?)
+    }
 
-        Err(TradfriGatewayError::DiscoveryTimeout)
+    /// Discovers the address of a gateway with mDNS, waiting up to 15 seconds,
+    /// see [`DiscoveredGateway::address`]. If mDNS is filtered, falls back to
+    /// the gateways that answered CoAP multicast in the meantime.
+    ///
+    /// The `from_*` constructors without an address discover the gateway this
+    /// way.
+    pub fn discover_ip() -> Result<GatewayAddr, TradfriGatewayError> {
+        Self::discover_ip_with(&DiscoveryOptions::new(DISCOVERY_TIMEOUT))
     }
+
+    /// Like [`TradfriGateway::discover_ip`], but with the strategies of the
+    /// options, for example to sweep an address range when multicast is
+    /// filtered altogether.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use tradfri_gateway::{DiscoveryOptions, TradfriGateway};
+    ///
+    /// let options = DiscoveryOptions::new(Duration::from_secs(10))
+    ///     .with_sweep("192.168.1.0/24".parse()?);
+    /// let address = TradfriGateway::discover_ip_with(&options)?;
+    /// let gateway = TradfriGateway::from_gateway_code_and_addr(address, "security code")?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn discover_ip_with(
+        options: &DiscoveryOptions,
+    ) -> Result<GatewayAddr, TradfriGatewayError> {
+        discovery::discover_address(options)
+    }
+
+    /// Discovers all gateways announced on the local network within the
+    /// timeout.
+    pub fn discover(timeout: Duration) -> Result<Vec<DiscoveredGateway>, TradfriGatewayError> {
+        let mut discovery = Self::discover_gateways()?.with_timeout(timeout);
+        discovery.by_ref().for_each(drop);
+
+        Ok(discovery.gateways())
+    }
+
+    /// Discovers gateways with the strategies of the options, for networks
+    /// where mDNS alone does not find the gateway, see [`DiscoveryOptions`].
+    pub fn discover_with(
+        options: &DiscoveryOptions,
+    ) -> Result<Vec<DiscoveredGateway>, TradfriGatewayError> {
+        discovery::discover_with(options)
+    }
+
+    /// Starts discovering gateways, yielding each gateway as soon as it has
+    /// been found, see [`GatewayDiscovery`].
+    ///
+    /// ```no_run
+    /// use std::{thread, time::Duration};
+    ///
+    /// use tradfri_gateway::TradfriGateway;
+    ///
+    /// let discovery = TradfriGateway::discover_gateways()?;
+    /// let canceller = discovery.canceller();
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_secs(30));
+    ///     canceller.cancel();
+    /// });
+    ///
+    /// for gateway in discovery {
+    ///     println!("{} at {:?}", gateway.hostname, gateway.addresses);
+    /// }
+    /// # Ok::<(), tradfri_gateway::TradfriGatewayError>(())
+    /// ```
+    pub fn discover_gateways() -> Result<GatewayDiscovery, TradfriGatewayError> {
+        GatewayDiscovery::start()
+    }
+}
+
+/// Whether connecting failed because nothing answered at the address.
+pub(crate) fn is_unreachable(error: &TradfriGatewayError) -> bool {
+    matches!(
+        error,
+        TradfriGatewayError::CoapError(CoapError::Unreachable | CoapError::Io(_))
+    )
 }
 
+#[allow(dead_code)]
+fn assert_send_sync<T: Send + Sync>() {}
+
+// Compile time check that the public types can be shared between threads.
+const _: fn() = || {
+    assert_send_sync::<TradfriGateway>();
+    assert_send_sync::<DeviceIterator>();
+    assert_send_sync::<GroupIterator>();
+    assert_send_sync::<Device>();
+    assert_send_sync::<Group>();
+    assert_send_sync::<SceneIterator>();
+    assert_send_sync::<Scene>();
+    assert_send_sync::<DeviceObserver>();
+    assert_send_sync::<GroupObserver>();
+    assert_send_sync::<GatewayDiscovery>();
+    assert_send_sync::<DiscoveryCanceller>();
+};
+
 pub struct DeviceIterator {
     ids: Vec<u32>,
-    connection: Rc<RefCell<TradfriConnection>>,
-    gateway: Rc<RefCell<TradfriGateway>>,
+    gateway: TradfriGateway,
 }
 
 impl Iterator for DeviceIterator {
@@ -275,16 +699,13 @@ impl Iterator for DeviceIterator {
 
     fn next(&mut self) -> Option<Self::Item> {
         let id = self.ids.pop()?;
-        let mut connection_borrowed = self.connection.borrow_mut();
-        let mut gateway_borrowed = self.gateway.borrow_mut();
-        Some(gateway_borrowed.device_with_connection(id, &mut connection_borrowed))
+        Some(self.gateway.device(id))
     }
 }
 
 pub struct GroupIterator {
     ids: Vec<u32>,
-    connection: Rc<RefCell<TradfriConnection>>,
-    gateway: Rc<RefCell<TradfriGateway>>,
+    gateway: TradfriGateway,
 }
 
 impl Iterator for GroupIterator {
@@ -292,29 +713,401 @@ impl Iterator for GroupIterator {
 
     fn next(&mut self) -> Option<Self::Item> {
         let id = self.ids.pop()?;
-        let mut connection_borrowed = self.connection.borrow_mut();
-        let mut gateway_borrowed = self.gateway.borrow_mut();
-        Some(gateway_borrowed.group_with_connection(id, &mut connection_borrowed))
+        Some(self.gateway.group(id))
     }
 }
 
+pub struct SceneIterator {
+    group_id: u32,
+    ids: Vec<u32>,
+    gateway: TradfriGateway,
+}
+
+impl Iterator for SceneIterator {
+    type Item = Result<Scene, TradfriGatewayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.ids.pop()?;
+        Some(self.gateway.scene(self.group_id, id))
+    }
+}
+
+/// Error of the gateway.
+///
+/// Failures of the connection are kept in [`TradfriGatewayError::CoapError`],
+/// error responses of the gateway have variants of their own, and
+/// [`TradfriGatewayError::is_retryable`] tells whether trying again may help.
+///
+/// ```no_run
+/// use tradfri_gateway::{CoapError, TradfriGateway, TradfriGatewayError};
+///
+/// let gateway = TradfriGateway::from_identifier_and_session_key_and_addr(
+///     [192, 168, 1, 10],
+///     "identifier",
+///     "session key",
+/// );
+///
+/// match gateway.devices() {
+///     Ok(devices) => println!("{} devices", devices.count()),
+///     Err(TradfriGatewayError::CoapError(CoapError::PskRejected(_))) => {
+///         println!("pair again, the session key is no longer accepted")
+///     }
+///     Err(error) if error.is_retryable() => println!("try again later: {}", error),
+///     Err(error) => println!("{}", error),
+/// }
+/// ```
 #[derive(Debug, thiserror::Error)]
 pub enum TradfriGatewayError {
     #[error("Error getting device with id: {0}, error: {1}")]
-    DeviceError(String, String),
+    DeviceError(u32, #[source] Box<DeviceError>),
 
     #[error("Error getting group with id: {0}, error: {1}")]
-    GroupError(String, String),
+    GroupError(u32, #[source] Box<GroupError>),
+
+    #[error("Error getting scene with id: {0}, error: {1}")]
+    SceneError(u32, #[source] Box<SceneError>),
 
     #[error("COAP error: {0}")]
-    CoapError(#[from] crate::tradfri_coap::Error),
+    CoapError(#[from] CoapError),
 
     #[error("Serde error: {0}")]
     SerdeError(#[from] serde_json::Error),
 
+    #[error("Factory reset confirmed for gateway {0}, but the gateway is {1}")]
+    FactoryResetNotConfirmed(String, String),
+
+    #[error("Gateway did not report its id")]
+    GatewayIdNotReported,
+
+    #[error("Gateway did not report its time")]
+    TimeNotReported,
+
+    #[error("Gateway kept its time zone at UTC{0}")]
+    TimeZoneNotChanged(FixedOffset),
+
+    #[error("Pairing error: {0}")]
+    PairingError(#[from] PairingError),
+
+    #[error("Credential store error: {0}")]
+    CredentialError(#[from] CredentialError),
+
     #[error("Mdns error: {0}")]
     MdnsError(#[from] mdns_sd::Error),
 
-    #[error("Gateway not found, mDNS discovery timeout")]
+    #[error("Gateway not found within the discovery timeout")]
     DiscoveryTimeout,
+
+    #[error("Discovery error: {0}")]
+    DiscoveryError(#[source] std::io::Error),
+
+    #[error("Gateway did not respond within {0:?}")]
+    Timeout(Duration),
+
+    #[error("Not supported by the transport: {0}")]
+    Unsupported(&'static str),
+
+    #[error("Blocking task was cancelled before it finished")]
+    Cancelled,
+
+    #[error("Bad request ({0}): {1}")]
+    BadRequest(ResponseCode, String),
+
+    #[error("Unauthorized ({0}): {1}")]
+    Unauthorized(ResponseCode, String),
+
+    #[error("Resource not found ({0}): {1}")]
+    NotFound(ResponseCode, String),
+
+    #[error("Method not allowed ({0}): {1}")]
+    MethodNotAllowed(ResponseCode, String),
+
+    #[error("Gateway error ({0}): {1}")]
+    ServerError(ResponseCode, String),
+
+    #[error("Gateway unavailable ({0}): {1}")]
+    ServiceUnavailable(ResponseCode, String),
+
+    #[error("Unexpected response ({0}): {1}")]
+    UnexpectedResponse(ResponseCode, String),
+}
+
+impl TradfriGatewayError {
+    /// Returns `true` for errors that may go away by trying again, such as
+    /// timeouts, an unreachable or busy gateway and network errors, and
+    /// `false` for errors that will repeat, such as rejected credentials or a
+    /// missing resource.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TradfriGatewayError::DeviceError(_, error) => match error.as_ref() {
+                DeviceError::TradfriGatewayError(error) => error.is_retryable(),
+                _ => false,
+            },
+            TradfriGatewayError::GroupError(_, error) => match error.as_ref() {
+                GroupError::TradfriGatewayError(error) => error.is_retryable(),
+                _ => false,
+            },
+            TradfriGatewayError::SceneError(_, error) => match error.as_ref() {
+                SceneError::TradfriGatewayError(error) => error.is_retryable(),
+                _ => false,
+            },
+            TradfriGatewayError::CoapError(error) => error.is_retryable(),
+            TradfriGatewayError::PairingError(error) => error.is_retryable(),
+            TradfriGatewayError::DiscoveryTimeout
+            | TradfriGatewayError::Timeout(_)
+            | TradfriGatewayError::ServerError(..)
+            | TradfriGatewayError::ServiceUnavailable(..) => true,
+            TradfriGatewayError::SerdeError(_)
+            | TradfriGatewayError::CredentialError(_)
+            | TradfriGatewayError::MdnsError(_)
+            | TradfriGatewayError::DiscoveryError(_)
+            | TradfriGatewayError::Unsupported(_)
+            | TradfriGatewayError::Cancelled
+            | TradfriGatewayError::FactoryResetNotConfirmed(..)
+            | TradfriGatewayError::GatewayIdNotReported
+            | TradfriGatewayError::TimeNotReported
+            | TradfriGatewayError::TimeZoneNotChanged(_)
+            | TradfriGatewayError::BadRequest(..)
+            | TradfriGatewayError::Unauthorized(..)
+            | TradfriGatewayError::NotFound(..)
+            | TradfriGatewayError::MethodNotAllowed(..)
+            | TradfriGatewayError::UnexpectedResponse(..) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+    use serde_json::json;
+
+    use super::*;
+    use crate::MemoryTransport;
+
+    const GATEWAY_ID: &str = "7e0f0f0f0f0f0f0f";
+
+    fn gateway(info: Value) -> (Arc<MemoryTransport>, TradfriGateway) {
+        let transport = Arc::new(MemoryTransport::new().with_resource("15011/15012", info));
+        let gateway = TradfriGateway::from_transport(transport.clone());
+        (transport, gateway)
+    }
+
+    fn reset_requested(transport: &MemoryTransport) -> bool {
+        transport
+            .requests()
+            .iter()
+            .any(|request| request.method == Method::Post && request.path == "15011/9031")
+    }
+
+    #[test]
+    fn resets_confirmed_gateway() {
+        let (transport, gateway) = gateway(json!({ "9029": "1.19.32", "9081": GATEWAY_ID }));
+
+        let confirmation = FactoryResetConfirmation::for_gateway(GATEWAY_ID);
+        assert!(gateway.factory_reset(&confirmation).is_ok());
+
+        let last = transport.requests().pop().unwrap();
+        assert_eq!(last.method, Method::Post);
+        assert_eq!(last.path, "15011/9031");
+        assert_eq!(last.payload, None);
+    }
+
+    #[test]
+    fn does_not_reset_other_gateway() {
+        let (transport, gateway) = gateway(json!({ "9029": "1.19.32", "9081": GATEWAY_ID }));
+
+        let confirmation = FactoryResetConfirmation::for_gateway("0000000000000000");
+        assert!(matches!(
+            gateway.factory_reset(&confirmation),
+            Err(TradfriGatewayError::FactoryResetNotConfirmed(..))
+        ));
+        assert!(!reset_requested(&transport));
+    }
+
+    #[test]
+    fn does_not_reset_gateway_without_id() {
+        let (transport, gateway) = gateway(json!({ "9029": "1.19.32" }));
+
+        let confirmation = FactoryResetConfirmation::for_gateway("");
+        assert!(matches!(
+            gateway.factory_reset(&confirmation),
+            Err(TradfriGatewayError::GatewayIdNotReported)
+        ));
+        assert!(!reset_requested(&transport));
+    }
+
+    #[test]
+    fn posts_ota_actions_to_their_resources() {
+        let (transport, gateway) = gateway(json!({ "9029": "1.19.32" }));
+
+        gateway.check_for_updates().unwrap();
+        gateway.start_update().unwrap();
+
+        let requests = transport.requests();
+        for (request, path) in requests[requests.len() - 2..]
+            .iter()
+            .zip(["15011/9032", "15011/9037"])
+        {
+            assert_eq!(request.method, Method::Post);
+            assert_eq!(request.path, path);
+            assert_eq!(request.payload, None);
+        }
+    }
+
+    #[test]
+    fn sets_update_window_in_minutes() {
+        let (transport, gateway) = gateway(json!({ "9029": "1.19.32" }));
+
+        let window = UpdateWindow::new(
+            NaiveTime::from_hms_opt(2, 0, 30).unwrap(),
+            NaiveTime::from_hms_opt(5, 30, 0).unwrap(),
+        );
+        gateway.set_update_window(&window).unwrap();
+
+        let last = transport.requests().pop().unwrap();
+        assert_eq!(last.method, Method::Put);
+        assert_eq!(last.path, "15011/15012");
+        assert_eq!(last.payload, Some(json!({ "9064": 120, "9065": 330 })));
+    }
+
+    #[test]
+    fn moves_gateway_to_time_zone() {
+        let (transport, gateway) = gateway(json!({
+            "9029": "1.19.32",
+            "9059": 1700000000,
+            "9060": "2023-11-14T23:13:20+01:00",
+        }));
+
+        gateway
+            .set_time_zone(FixedOffset::east_opt(2 * 3600).unwrap())
+            .unwrap();
+
+        let update = transport
+            .requests()
+            .into_iter()
+            .find(|request| request.method == Method::Put)
+            .unwrap();
+        assert_eq!(update.path, "15011/15012");
+        assert_eq!(
+            update.payload,
+            Some(json!({ "9060": "2023-11-15T00:13:20+02:00" }))
+        );
+        assert_eq!(
+            gateway.time().unwrap().local().to_rfc3339(),
+            "2023-11-15T00:13:20+02:00"
+        );
+    }
+
+    /// Acknowledges updates of the gateway resource without applying them.
+    #[derive(Debug)]
+    struct FixedTimeZone(MemoryTransport);
+
+    impl Transport for FixedTimeZone {
+        fn request(&self, req: CoAPRequest) -> Result<CoAPResponse, TradfriGatewayError> {
+            let info = self.0.resource("15011/15012").unwrap();
+            let response = self.0.request(req)?;
+            self.0.set_resource("15011/15012", info);
+            Ok(response)
+        }
+    }
+
+    #[test]
+    fn reports_time_zone_the_gateway_kept() {
+        let transport = MemoryTransport::new().with_resource(
+            "15011/15012",
+            json!({ "9029": "1.19.32", "9060": "2023-11-14T23:13:20+01:00" }),
+        );
+        let gateway = TradfriGateway::from_transport(FixedTimeZone(transport));
+
+        assert!(matches!(
+            gateway.set_time_zone(FixedOffset::east_opt(2 * 3600).unwrap()),
+            Err(TradfriGatewayError::TimeZoneNotChanged(offset))
+                if offset == FixedOffset::east_opt(3600).unwrap()
+        ));
+    }
+
+    fn scenes_gateway() -> (Arc<MemoryTransport>, TradfriGateway) {
+        let transport = Arc::new(
+            MemoryTransport::new()
+                .with_resource("15004/131073", json!({ "9003": 131073, "5850": 0 }))
+                .with_resource("15005/131073", json!([])),
+        );
+        let gateway = TradfriGateway::from_transport(transport.clone());
+        (transport, gateway)
+    }
+
+    #[test]
+    fn creates_edits_and_deletes_scenes() {
+        let (transport, gateway) = scenes_gateway();
+
+        let mut scene = gateway
+            .create_scene(131073, "Reading", vec![LightSetting::new(65537, true)])
+            .unwrap();
+        assert_eq!(scene.id(), 196608);
+        assert_eq!(scene.name(), "Reading");
+        let create = &transport.requests()[0];
+        assert_eq!(create.method, Method::Post);
+        assert_eq!(create.path, "15005/131073");
+        assert_eq!(
+            create.payload,
+            Some(json!({ "9001": "Reading", "15013": [{ "9003": 65537, "5850": 1 }] }))
+        );
+
+        scene
+            .edit(&SceneUpdate {
+                lights: Some(vec![LightSetting::new(65537, true).with_brightness(200)]),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(scene.lights()[0].brightness, Some(200));
+        assert_eq!(scene.name(), "Reading");
+
+        scene.activate().unwrap();
+        let activate = transport.requests().pop().unwrap();
+        assert_eq!(activate.path, "15004/131073");
+        assert_eq!(activate.payload, Some(json!({ "5850": 1, "9039": 196608 })));
+
+        scene.delete().unwrap();
+        let delete = transport.requests().pop().unwrap();
+        assert_eq!(delete.method, Method::Delete);
+        assert_eq!(delete.path, "15005/131073/196608");
+        assert_eq!(gateway.scenes(131073).unwrap().count(), 0);
+    }
+
+    /// Answers creating an item without telling its id.
+    #[derive(Debug)]
+    struct AnonymousCreated(Arc<MemoryTransport>);
+
+    impl Transport for AnonymousCreated {
+        fn request(&self, req: CoAPRequest) -> Result<CoAPResponse, TradfriGatewayError> {
+            let mut response = self.0.request(req)?;
+            response.message.payload.clear();
+            Ok(response)
+        }
+    }
+
+    #[test]
+    fn fails_to_create_scene_without_id() {
+        let (transport, _) = scenes_gateway();
+        let gateway = TradfriGateway::from_transport(AnonymousCreated(transport.clone()));
+
+        assert!(matches!(
+            gateway.create_scene(131073, "Reading", vec![]),
+            Err(TradfriGatewayError::UnexpectedResponse(..))
+        ));
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[test]
+    fn redacts_session_key_in_debug() {
+        let gateway = TradfriGateway::from_identifier_and_session_key_and_addr(
+            [192, 168, 1, 10],
+            "home-assistant",
+            "5Gb3dBlRUVy7DDqQ",
+        );
+        let debug = format!("{:?}", gateway);
+
+        assert!(debug.contains("home-assistant"));
+        assert!(debug.contains("[REDACTED]"));
+        assert!(!debug.contains("5Gb3dBlRUVy7DDqQ"));
+    }
 }
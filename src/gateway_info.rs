@@ -0,0 +1,119 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::Deserialize;
+
+use crate::{
+    serialization::{
+        bool_from_nonzero, option_datetime_from_rfc3339, option_datetime_from_timestamp,
+    },
+    OtaStatus, OtaUpdateState, UpdatePriority, UpdateWindow,
+};
+
+/// State of the gateway itself, read from the `15011/15012` resource with
+/// [`TradfriGateway::info`](crate::TradfriGateway::info).
+///
+/// Fields some firmware versions leave out are optional.
+///
+/// ```
+/// use serde_json::json;
+/// use tradfri_gateway::{MemoryTransport, TradfriGateway};
+///
+/// let transport = MemoryTransport::new().with_resource(
+///     "15011/15012",
+///     json!({
+///         "9029": "1.21.31",
+///         "9059": 1700000000,
+///         "9060": "2023-11-14T23:13:20+01:00",
+///         "9061": 0,
+///         "9081": "7e0f0f0f0f0f0f0f",
+///     }),
+/// );
+/// let info = TradfriGateway::from_transport(transport).info().unwrap();
+///
+/// assert_eq!(info.firmware_version, "1.21.31");
+/// assert_eq!(info.utc_offset().unwrap().local_minus_utc(), 3600);
+/// assert!(!info.commissioning_mode);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GatewayInfo {
+    /// Unique id of the gateway.
+    #[serde(rename = "9081", default)]
+    pub id: String,
+
+    #[serde(rename = "9083", default)]
+    pub homekit_id: Option<String>,
+
+    #[serde(rename = "9029")]
+    pub firmware_version: String,
+
+    /// Current time of the gateway.
+    #[serde(
+        rename = "9059",
+        default,
+        deserialize_with = "option_datetime_from_timestamp"
+    )]
+    pub current_time: Option<DateTime<Utc>>,
+
+    /// Current time of the gateway in its configured time zone.
+    #[serde(
+        rename = "9060",
+        default,
+        deserialize_with = "option_datetime_from_rfc3339"
+    )]
+    pub local_time: Option<DateTime<FixedOffset>>,
+
+    #[serde(rename = "9023", default)]
+    pub ntp_server: Option<String>,
+
+    /// When the gateway was first set up.
+    #[serde(
+        rename = "9069",
+        default,
+        deserialize_with = "option_datetime_from_timestamp"
+    )]
+    pub first_setup: Option<DateTime<Utc>>,
+
+    /// Whether the gateway currently accepts new devices.
+    #[serde(rename = "9061", default, deserialize_with = "bool_from_nonzero")]
+    pub commissioning_mode: bool,
+
+    #[serde(rename = "9054", default)]
+    pub ota_update_state: OtaUpdateState,
+
+    /// Progress of a firmware update in percent.
+    #[serde(rename = "9055", default)]
+    pub update_progress: Option<u8>,
+
+    /// Release notes of an available firmware update.
+    #[serde(rename = "9056", default)]
+    pub update_details_url: Option<String>,
+
+    #[serde(rename = "9066", default)]
+    pub update_priority: Option<UpdatePriority>,
+
+    #[serde(rename = "9064", default)]
+    update_window_start: Option<u16>,
+    #[serde(rename = "9065", default)]
+    update_window_end: Option<u16>,
+}
+
+impl GatewayInfo {
+    /// Offset of the time zone of the gateway from UTC.
+    pub fn utc_offset(&self) -> Option<FixedOffset> {
+        self.local_time.map(|time| *time.offset())
+    }
+
+    /// The firmware update part of the information.
+    pub fn ota_status(&self) -> OtaStatus {
+        self.clone().into()
+    }
+
+    /// The daily window for automatic updates, if one has been set.
+    pub fn update_window(&self) -> Option<UpdateWindow> {
+        UpdateWindow::from_minutes(self.update_window_start?, self.update_window_end?)
+    }
+
+    /// How long ago the gateway was first set up, by its own clock.
+    pub fn since_first_setup(&self) -> Option<chrono::Duration> {
+        Some(self.current_time? - self.first_setup?)
+    }
+}
@@ -0,0 +1,218 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use serde_json::{json, Value};
+
+use crate::{GatewayInfo, TradfriGatewayError};
+
+const NTP_SERVER: &str = "9023";
+const LOCAL_TIME: &str = "9060";
+
+/// Clock of the gateway, read with
+/// [`TradfriGateway::time`](crate::TradfriGateway::time).
+///
+/// The gateway runs all smart tasks by this clock. It keeps time in whole
+/// seconds, so [`GatewayTime::drift`] is only accurate to about a second plus
+/// the time the request took.
+///
+/// ```
+/// use serde_json::json;
+/// use tradfri_gateway::{MemoryTransport, TradfriGateway};
+///
+/// let transport = MemoryTransport::new().with_resource(
+///     "15011/15012",
+///     json!({
+///         "9023": "pool.ntp.org",
+///         "9029": "1.21.31",
+///         "9059": 1700000000,
+///         "9060": "2023-11-14T23:13:20+01:00",
+///     }),
+/// );
+/// let time = TradfriGateway::from_transport(transport).time().unwrap();
+///
+/// assert_eq!(time.utc.timestamp(), 1700000000);
+/// assert_eq!(time.local().to_rfc3339(), "2023-11-14T23:13:20+01:00");
+/// assert_eq!(time.ntp_server.as_deref(), Some("pool.ntp.org"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GatewayTime {
+    /// Current time of the gateway.
+    pub utc: DateTime<Utc>,
+    /// Offset of the time zone of the gateway from UTC, UTC if the gateway
+    /// does not report its time zone.
+    pub offset: FixedOffset,
+    pub ntp_server: Option<String>,
+    /// Time of the local system when the time was read.
+    pub read_at: DateTime<Utc>,
+}
+
+impl GatewayTime {
+    pub(crate) fn from_info(info: GatewayInfo, read_at: DateTime<Utc>) -> Option<Self> {
+        let offset = info.utc_offset().unwrap_or_else(utc);
+        let time = info
+            .current_time
+            .or_else(|| info.local_time.map(|time| time.with_timezone(&Utc)))?;
+
+        Some(Self {
+            utc: time,
+            offset,
+            ntp_server: info.ntp_server,
+            read_at,
+        })
+    }
+
+    /// Current time of the gateway in its time zone.
+    pub fn local(&self) -> DateTime<FixedOffset> {
+        self.utc.with_timezone(&self.offset)
+    }
+
+    /// How far the clock of the gateway is ahead of the local system clock,
+    /// negative if it is behind.
+    pub fn drift(&self) -> chrono::Duration {
+        self.utc - self.read_at
+    }
+
+    /// Whether the clocks of the gateway and the local system are at most
+    /// `tolerance` apart.
+    pub fn is_in_sync(&self, tolerance: chrono::Duration) -> bool {
+        self.drift().abs() <= tolerance
+    }
+}
+
+fn utc() -> FixedOffset {
+    FixedOffset::east_opt(0).expect("zero is a valid offset")
+}
+
+pub(crate) fn set_ntp_server(server: &str) -> Value {
+    json!({ NTP_SERVER: server })
+}
+
+/// Moves the gateway to another time zone by handing it its own current
+/// time in that zone, so its clock itself stays as it is.
+pub(crate) fn set_time_zone(now: DateTime<Utc>, offset: FixedOffset) -> Value {
+    json!({ LOCAL_TIME: now.with_timezone(&offset).to_rfc3339() })
+}
+
+/// Checks that the gateway took the time zone, see [`set_time_zone`].
+pub(crate) fn check_time_zone(
+    time: &GatewayTime,
+    offset: FixedOffset,
+) -> Result<(), TradfriGatewayError> {
+    if time.offset == offset {
+        Ok(())
+    } else {
+        Err(TradfriGatewayError::TimeZoneNotChanged(time.offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    fn info(value: Value) -> GatewayInfo {
+        let mut info = json!({ "9029": "1.21.31" });
+        info.as_object_mut()
+            .unwrap()
+            .extend(value.as_object().unwrap().clone());
+        serde_json::from_value(info).unwrap()
+    }
+
+    fn at(timestamp: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(timestamp, 0).unwrap()
+    }
+
+    fn hours(hours: i32) -> FixedOffset {
+        FixedOffset::east_opt(hours * 3600).unwrap()
+    }
+
+    #[test]
+    fn prefers_unix_time_and_takes_offset_of_local_time() {
+        let info = info(json!({
+            "9059": 1700000000,
+            "9060": "2023-11-14T23:13:25+01:00",
+            "9023": "pool.ntp.org",
+        }));
+
+        let time = GatewayTime::from_info(info, at(1700000000)).unwrap();
+        assert_eq!(time.utc, at(1700000000));
+        assert_eq!(time.offset, hours(1));
+        assert_eq!(time.local().to_rfc3339(), "2023-11-14T23:13:20+01:00");
+        assert_eq!(time.ntp_server.as_deref(), Some("pool.ntp.org"));
+    }
+
+    #[test]
+    fn falls_back_to_local_time() {
+        let info = info(json!({ "9060": "2023-11-14T23:13:20+01:00" }));
+
+        let time = GatewayTime::from_info(info, at(1700000000)).unwrap();
+        assert_eq!(time.utc, at(1700000000));
+        assert_eq!(time.offset, hours(1));
+        assert_eq!(time.ntp_server, None);
+    }
+
+    #[test]
+    fn falls_back_to_utc_without_local_time() {
+        let info = info(json!({ "9059": 1700000000 }));
+
+        let time = GatewayTime::from_info(info, at(1700000000)).unwrap();
+        assert_eq!(time.offset, hours(0));
+        assert_eq!(time.local().to_rfc3339(), "2023-11-14T22:13:20+00:00");
+    }
+
+    #[test]
+    fn has_no_time_without_either() {
+        assert_eq!(
+            GatewayTime::from_info(info(json!({})), at(1700000000)),
+            None
+        );
+    }
+
+    #[test]
+    fn measures_drift_from_local_clock() {
+        let ahead = GatewayTime::from_info(info(json!({ "9059": 1700000005 })), at(1700000000));
+        let ahead = ahead.unwrap();
+        assert_eq!(ahead.drift(), Duration::seconds(5));
+        assert!(ahead.is_in_sync(Duration::seconds(5)));
+        assert!(!ahead.is_in_sync(Duration::seconds(4)));
+
+        let behind = GatewayTime::from_info(info(json!({ "9059": 1699999997 })), at(1700000000));
+        let behind = behind.unwrap();
+        assert_eq!(behind.drift(), Duration::seconds(-3));
+        assert!(behind.is_in_sync(Duration::seconds(3)));
+        assert!(!behind.is_in_sync(Duration::seconds(2)));
+    }
+
+    #[test]
+    fn writes_current_time_in_new_zone() {
+        assert_eq!(
+            set_time_zone(at(1700000000), hours(2)),
+            json!({ "9060": "2023-11-15T00:13:20+02:00" })
+        );
+        assert_eq!(
+            set_time_zone(
+                at(1700000000),
+                FixedOffset::west_opt(9 * 3600 + 1800).unwrap()
+            ),
+            json!({ "9060": "2023-11-14T12:43:20-09:30" })
+        );
+        assert_eq!(
+            set_ntp_server("time.example.com"),
+            json!({ "9023": "time.example.com" })
+        );
+    }
+
+    #[test]
+    fn checks_time_zone_took() {
+        let time = GatewayTime::from_info(
+            info(json!({ "9060": "2023-11-14T23:13:20+01:00" })),
+            at(1700000000),
+        )
+        .unwrap();
+
+        assert!(check_time_zone(&time, hours(1)).is_ok());
+        assert!(matches!(
+            check_time_zone(&time, hours(2)),
+            Err(TradfriGatewayError::TimeZoneNotChanged(offset)) if offset == hours(1)
+        ));
+    }
+}
@@ -1,15 +1,19 @@
-use crate::{tradfri_coap::TradfriConnection, TradfriGateway, TradfriGatewayError};
+use crate::{TradfriGateway, TradfriGatewayError};
 
 mod parse;
-use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, Utc};
 pub use parse::*;
 
 mod update;
 pub use update::*;
 
+/// A group of devices, called a room in the IKEA app.
+///
+/// Like a [`Light`](crate::Light), a group is generic over the gateway handle
+/// it sends its requests with.
 #[derive(Debug, Clone)]
-pub struct Group {
-    gateway: TradfriGateway,
+pub struct Group<G = TradfriGateway> {
+    gateway: G,
     on: bool,
     brightness: u8,
     name: String,
@@ -24,26 +28,24 @@ pub struct Items {
     // pub items: Vec<Device>,
 }
 
-impl Group {
-    pub fn new(gateway: TradfriGateway, bytes: &[u8]) -> Result<Group, GroupError> {
+impl<G> Group<G> {
+    pub fn new(gateway: G, bytes: &[u8]) -> Result<Self, GroupError> {
         let parsed: GroupParsed = match serde_json::from_slice(bytes) {
             Ok(d) => d,
             Err(error) => {
                 return Err(GroupError::SerdeError(
-                    error.to_string(),
+                    error,
                     String::from_utf8_lossy(bytes).to_string(),
                 ))
             }
         };
 
-        Ok(Group {
+        Ok(Self {
             gateway,
             on: parsed.on,
             brightness: parsed.brightness,
             name: parsed.name,
-            creation_date: Utc.from_utc_datetime(
-                &NaiveDateTime::from_timestamp_opt(parsed.creation_date.into(), 0).unwrap(),
-            ),
+            creation_date: DateTime::from_timestamp(parsed.creation_date.into(), 0).unwrap(),
             id: parsed.id,
             items: Items {
                 // items: parsed.items,
@@ -51,51 +53,51 @@ impl Group {
         })
     }
 
-    pub fn on(&mut self) -> Result<(), GroupError> {
-        let update = GroupUpdate {
-            on: Some(true),
-            ..Default::default()
-        };
-
-        let mut connection = self.gateway.create_connection()?;
-        self.gateway
-            .update_group(self.id, &update, Some(&mut connection))?;
-        self.update_with_connection(&mut connection)?;
-
-        Ok(())
+    pub fn id(&self) -> u32 {
+        self.id
     }
 
-    pub fn off(&mut self) -> Result<(), GroupError> {
-        let update = GroupUpdate {
-            on: Some(false),
-            ..Default::default()
-        };
-
-        let mut connection = self.gateway.create_connection()?;
-        self.gateway
-            .update_group(self.id, &update, Some(&mut connection))?;
-        self.update_with_connection(&mut connection)?;
-
-        Ok(())
+    #[cfg(feature = "async")]
+    pub(crate) fn gateway(&self) -> &G {
+        &self.gateway
     }
 
-    pub fn update(&mut self) -> Result<(), GroupError> {
-        let mut connection = self.gateway.create_connection()?;
-        self.update_with_connection(&mut connection)
-    }
-
-    fn update_with_connection(
-        &mut self,
-        connection: &mut TradfriConnection,
-    ) -> Result<(), GroupError> {
-        let group = self.gateway.group_with_connection(self.id, connection)?;
-
+    /// Takes over the state of the group as read again from the gateway.
+    pub(crate) fn refresh(&mut self, group: Group<G>) {
         self.on = group.on;
         self.brightness = group.brightness;
         self.name = group.name;
         self.creation_date = group.creation_date;
         self.id = group.id;
         self.items = group.items;
+    }
+}
+
+impl Group {
+    pub fn on(&mut self) -> Result<(), GroupError> {
+        self.gateway
+            .update_group(self.id, &GroupUpdate::switch(true))?;
+        self.update()
+    }
+
+    pub fn off(&mut self) -> Result<(), GroupError> {
+        self.gateway
+            .update_group(self.id, &GroupUpdate::switch(false))?;
+        self.update()
+    }
+
+    /// Activates one of the scenes of the group, see
+    /// [`TradfriGateway::scenes`].
+    pub fn activate_scene(&mut self, scene_id: u32) -> Result<(), GroupError> {
+        let update = GroupUpdate::activate_scene(scene_id);
+
+        self.gateway.update_group(self.id, &update)?;
+        self.update()
+    }
+
+    pub fn update(&mut self) -> Result<(), GroupError> {
+        let group = self.gateway.group(self.id)?;
+        self.refresh(group);
 
         Ok(())
     }
@@ -104,7 +106,7 @@ impl Group {
 #[derive(Debug, thiserror::Error)]
 pub enum GroupError {
     #[error("Serde error: {0}, raw data: {1}")]
-    SerdeError(String, String),
+    SerdeError(#[source] serde_json::Error, String),
 
     #[error("Tradfri gateway error: {0}")]
     TradfriGatewayError(#[from] TradfriGatewayError),
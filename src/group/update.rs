@@ -23,3 +23,23 @@ pub struct GroupUpdate {
     #[serde(rename = "9039", skip_serializing_if = "Option::is_none")]
     pub scene_id: Option<u32>,
 }
+
+impl GroupUpdate {
+    /// Turns all lights of the group on or off.
+    pub fn switch(on: bool) -> Self {
+        Self {
+            on: Some(on),
+            ..Default::default()
+        }
+    }
+
+    /// Activates a scene of the group. The gateway only applies the scene
+    /// when the group is switched on along with it.
+    pub fn activate_scene(scene_id: u32) -> Self {
+        Self {
+            on: Some(true),
+            scene_id: Some(scene_id),
+            ..Default::default()
+        }
+    }
+}
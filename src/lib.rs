@@ -1,12 +1,61 @@
+mod address;
+#[cfg(feature = "async")]
+mod asynchronous;
 mod color;
+mod credentials;
 mod device;
+mod discovery;
 mod gateway;
+mod gateway_info;
+mod gateway_time;
 mod group;
+mod maintenance;
+mod notification;
+mod observe;
+mod ota;
+mod pairing;
+mod raw;
+mod requests;
+mod response_code;
+mod scene;
+mod secret;
 mod serialization;
+#[cfg(feature = "simulator")]
+mod simulator;
 mod tradfri_coap;
+mod transport;
 mod udp_dtls;
 
+pub use crate::address::{GatewayAddr, GatewayAddrParseError};
+#[cfg(feature = "async")]
+pub use crate::asynchronous::*;
 pub use crate::color::*;
+pub use crate::credentials::*;
 pub use crate::device::*;
+pub use crate::discovery::{
+    Cidr, CidrError, DiscoveredGateway, DiscoveryCanceller, DiscoveryOptions, DiscoverySource,
+    GatewayDiscovery,
+};
 pub use crate::gateway::*;
+pub use crate::gateway_info::GatewayInfo;
+pub use crate::gateway_time::GatewayTime;
 pub use crate::group::*;
+pub use crate::maintenance::{CommissioningWindow, FactoryResetConfirmation};
+pub use crate::notification::{GatewayNotification, NotificationDetails, RebootReason};
+pub use crate::observe::*;
+pub use crate::ota::{OtaStatus, OtaUpdateState, UpdatePriority, UpdateWindow};
+pub use crate::pairing::{PairingError, PairingOptions};
+pub use crate::raw::RawResponse;
+pub use crate::response_code::ResponseCode;
+pub use crate::scene::*;
+pub use crate::secret::Secret;
+#[cfg(feature = "simulator")]
+pub use crate::simulator::*;
+pub use crate::tradfri_coap::{Error as CoapError, TransmissionParameters};
+pub use crate::transport::*;
+#[cfg(feature = "dtls-server")]
+pub use crate::udp_dtls::{
+    DtlsAcceptor, DtlsAcceptorBuilder, DtlsListener, DtlsStream, Error as DtlsError,
+    HandshakeError as DtlsHandshakeError, MidHandshakeDtlsStream, PeerChannel,
+    Protocol as DtlsProtocol, PskServerCallback,
+};
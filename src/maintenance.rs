@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::TradfriGatewayError;
+
+/// Confirmation that [`TradfriGateway::factory_reset`](crate::TradfriGateway::factory_reset)
+/// requires, naming the gateway to erase.
+///
+/// A factory reset removes all devices, groups, scenes and paired clients
+/// from the gateway. It only goes ahead if the id matches the
+/// [`GatewayInfo::id`](crate::GatewayInfo::id) of the gateway, so that a
+/// confirmation for one gateway of a fleet can not reset another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FactoryResetConfirmation {
+    gateway_id: String,
+}
+
+impl FactoryResetConfirmation {
+    pub fn for_gateway(gateway_id: &str) -> Self {
+        Self {
+            gateway_id: gateway_id.into(),
+        }
+    }
+
+    pub fn gateway_id(&self) -> &str {
+        &self.gateway_id
+    }
+
+    /// Checks that the confirmation names the gateway with the reported id.
+    pub(crate) fn confirm(&self, reported_id: String) -> Result<(), TradfriGatewayError> {
+        if reported_id.is_empty() {
+            return Err(TradfriGatewayError::GatewayIdNotReported);
+        }
+        if reported_id != self.gateway_id {
+            return Err(TradfriGatewayError::FactoryResetNotConfirmed(
+                self.gateway_id.clone(),
+                reported_id,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Pairing window opened with
+/// [`TradfriGateway::open_commissioning`](crate::TradfriGateway::open_commissioning),
+/// during which the gateway accepts new devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommissioningWindow {
+    /// How long the window is open, in whole seconds.
+    pub duration: Duration,
+    /// When the window closes, by the local clock.
+    pub closes_at: DateTime<Utc>,
+}
+
+impl CommissioningWindow {
+    pub(crate) fn open_for(duration: Duration) -> Self {
+        let duration = Duration::from_secs(commissioning_seconds(duration));
+        let closes_at = chrono::Duration::from_std(duration)
+            .ok()
+            .and_then(|duration| Utc::now().checked_add_signed(duration))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+
+        Self {
+            duration,
+            closes_at,
+        }
+    }
+}
+
+/// The duration in seconds the gateway takes, rounded up to at least one.
+fn commissioning_seconds(duration: Duration) -> u64 {
+    let seconds = duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+    seconds.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_commissioning_time_up_to_whole_seconds() {
+        assert_eq!(commissioning_seconds(Duration::ZERO), 1);
+        assert_eq!(commissioning_seconds(Duration::from_millis(1)), 1);
+        assert_eq!(commissioning_seconds(Duration::from_secs(30)), 30);
+        assert_eq!(commissioning_seconds(Duration::from_millis(30_001)), 31);
+        assert_eq!(
+            commissioning_seconds(Duration::from_nanos(59_999_999_999)),
+            60
+        );
+    }
+
+    #[test]
+    fn window_reports_rounded_duration() {
+        let window = CommissioningWindow::open_for(Duration::from_millis(1500));
+
+        assert_eq!(window.duration, Duration::from_secs(2));
+        assert!(window.closes_at > Utc::now());
+    }
+}
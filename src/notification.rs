@@ -0,0 +1,381 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+const NEW_FIRMWARE_AVAILABLE: u32 = 1001;
+const REBOOT: u32 = 1003;
+const DEVICE_LOST: u32 = 1004;
+const LOGIN: u32 = 1005;
+const INTERNET_CONNECTION_LOST: u32 = 1006;
+const TOO_MANY_GROUPS: u32 = 5000;
+
+/// Names of the pairs that carry the id of an affected device or group.
+const DEVICE_ID: &str = "deviceId";
+const GROUP_ID: &str = "groupId";
+
+/// An entry of the notification list of the gateway, read with
+/// [`TradfriGateway::notifications`](crate::TradfriGateway::notifications)
+/// or followed with
+/// [`TradfriGateway::observe_notifications`](crate::TradfriGateway::observe_notifications).
+///
+/// Every notification carries the devices and groups it concerns, see
+/// [`NotificationDetails`]. Events this crate does not know yet are kept as
+/// [`GatewayNotification::Other`].
+///
+/// ```
+/// use serde_json::json;
+/// use tradfri_gateway::{GatewayNotification, MemoryTransport, RebootReason, TradfriGateway};
+///
+/// let transport = MemoryTransport::new().with_resource(
+///     "15006",
+///     json!([
+///         { "9015": 1003, "9002": 1700000000, "9017": ["reason=1"], "9014": 0 },
+///         { "9015": 1004, "9002": 1700000100, "9017": ["deviceId=65537"], "9014": 0 },
+///     ]),
+/// );
+/// let notifications = TradfriGateway::from_transport(transport)
+///     .notifications()
+///     .unwrap();
+///
+/// assert!(matches!(
+///     notifications[0],
+///     GatewayNotification::Reboot {
+///         reason: RebootReason::FirmwareUpdate,
+///         ..
+///     }
+/// ));
+/// assert!(matches!(notifications[1], GatewayNotification::DeviceLost { .. }));
+/// assert_eq!(notifications[1].details().devices, [65537]);
+/// assert_eq!(
+///     notifications[1].created_at().map(|time| time.timestamp()),
+///     Some(1700000100)
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(from = "NotificationParsed")]
+pub enum GatewayNotification {
+    /// New firmware is available for the gateway or its devices.
+    NewFirmwareAvailable {
+        created_at: Option<DateTime<Utc>>,
+        details: NotificationDetails,
+    },
+    /// The gateway restarted.
+    Reboot {
+        created_at: Option<DateTime<Utc>>,
+        reason: RebootReason,
+        details: NotificationDetails,
+    },
+    /// The gateway lost the connection to a device, listed in the details.
+    DeviceLost {
+        created_at: Option<DateTime<Utc>>,
+        details: NotificationDetails,
+    },
+    /// A client logged in to the gateway.
+    Login {
+        created_at: Option<DateTime<Utc>>,
+        details: NotificationDetails,
+    },
+    /// The gateway lost its connection to the internet.
+    InternetConnectionLost {
+        created_at: Option<DateTime<Utc>>,
+        details: NotificationDetails,
+    },
+    /// A group could not be created as the gateway holds too many.
+    TooManyGroups {
+        created_at: Option<DateTime<Utc>>,
+        details: NotificationDetails,
+    },
+    Other {
+        event: u32,
+        created_at: Option<DateTime<Utc>>,
+        details: NotificationDetails,
+    },
+}
+
+impl GatewayNotification {
+    /// The event code the gateway uses for this kind of notification.
+    pub fn event(&self) -> u32 {
+        match self {
+            GatewayNotification::NewFirmwareAvailable { .. } => NEW_FIRMWARE_AVAILABLE,
+            GatewayNotification::Reboot { .. } => REBOOT,
+            GatewayNotification::DeviceLost { .. } => DEVICE_LOST,
+            GatewayNotification::Login { .. } => LOGIN,
+            GatewayNotification::InternetConnectionLost { .. } => INTERNET_CONNECTION_LOST,
+            GatewayNotification::TooManyGroups { .. } => TOO_MANY_GROUPS,
+            GatewayNotification::Other { event, .. } => *event,
+        }
+    }
+
+    /// When the gateway raised the notification, `None` if it did not say or
+    /// the time is out of range.
+    pub fn created_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            GatewayNotification::NewFirmwareAvailable { created_at, .. }
+            | GatewayNotification::Reboot { created_at, .. }
+            | GatewayNotification::DeviceLost { created_at, .. }
+            | GatewayNotification::Login { created_at, .. }
+            | GatewayNotification::InternetConnectionLost { created_at, .. }
+            | GatewayNotification::TooManyGroups { created_at, .. }
+            | GatewayNotification::Other { created_at, .. } => *created_at,
+        }
+    }
+
+    /// The devices and groups the notification concerns, and its other
+    /// name-value pairs.
+    pub fn details(&self) -> &NotificationDetails {
+        match self {
+            GatewayNotification::NewFirmwareAvailable { details, .. }
+            | GatewayNotification::Reboot { details, .. }
+            | GatewayNotification::DeviceLost { details, .. }
+            | GatewayNotification::Login { details, .. }
+            | GatewayNotification::InternetConnectionLost { details, .. }
+            | GatewayNotification::TooManyGroups { details, .. }
+            | GatewayNotification::Other { details, .. } => details,
+        }
+    }
+}
+
+/// What a [`GatewayNotification`] concerns, from the name-value pairs the
+/// gateway attached to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct NotificationDetails {
+    /// Ids of the affected devices.
+    pub devices: Vec<u32>,
+    /// Ids of the affected groups.
+    pub groups: Vec<u32>,
+    /// The remaining pairs, such as the `reason` of a reboot.
+    pub other: BTreeMap<String, String>,
+}
+
+impl NotificationDetails {
+    /// Parses pairs in the form `name=value`.
+    fn parse(pairs: &[String]) -> Self {
+        let mut details = Self::default();
+
+        for pair in pairs {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let id = value.parse::<u32>().ok();
+            match (name, id) {
+                (DEVICE_ID, Some(id)) => details.devices.push(id),
+                (GROUP_ID, Some(id)) => details.groups.push(id),
+                _ => {
+                    details.other.insert(name.to_string(), value.to_string());
+                }
+            }
+        }
+
+        details
+    }
+}
+
+/// Why the gateway rebooted, from the `reason` of a
+/// [`GatewayNotification::Reboot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RebootReason {
+    Default,
+    FirmwareUpdate,
+    /// Requested by a client, for example with
+    /// [`TradfriGateway::reboot`](crate::TradfriGateway::reboot).
+    Client,
+    HomekitReset,
+    FactoryReset,
+    Unknown(i32),
+}
+
+impl From<i32> for RebootReason {
+    fn from(reason: i32) -> Self {
+        match reason {
+            0 => RebootReason::Default,
+            1 => RebootReason::FirmwareUpdate,
+            2 => RebootReason::Client,
+            3 => RebootReason::HomekitReset,
+            -1 => RebootReason::FactoryReset,
+            other => RebootReason::Unknown(other),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NotificationParsed {
+    #[serde(rename = "9015")]
+    event: u32,
+    #[serde(rename = "9002", default)]
+    created_at: Option<i64>,
+    /// Pairs in the form `name=value`.
+    #[serde(rename = "9017", default)]
+    details: Vec<String>,
+}
+
+impl From<NotificationParsed> for GatewayNotification {
+    fn from(parsed: NotificationParsed) -> Self {
+        let created_at = parsed
+            .created_at
+            .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0));
+        let details = NotificationDetails::parse(&parsed.details);
+
+        match parsed.event {
+            NEW_FIRMWARE_AVAILABLE => GatewayNotification::NewFirmwareAvailable {
+                created_at,
+                details,
+            },
+            REBOOT => GatewayNotification::Reboot {
+                created_at,
+                reason: details
+                    .other
+                    .get("reason")
+                    .and_then(|reason| reason.parse::<i32>().ok())
+                    .unwrap_or(0)
+                    .into(),
+                details,
+            },
+            DEVICE_LOST => GatewayNotification::DeviceLost {
+                created_at,
+                details,
+            },
+            LOGIN => GatewayNotification::Login {
+                created_at,
+                details,
+            },
+            INTERNET_CONNECTION_LOST => GatewayNotification::InternetConnectionLost {
+                created_at,
+                details,
+            },
+            TOO_MANY_GROUPS => GatewayNotification::TooManyGroups {
+                created_at,
+                details,
+            },
+            event => GatewayNotification::Other {
+                event,
+                created_at,
+                details,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn notification(value: Value) -> GatewayNotification {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn details(pairs: &[&str]) -> NotificationDetails {
+        let pairs: Vec<String> = pairs.iter().map(|pair| pair.to_string()).collect();
+        NotificationDetails::parse(&pairs)
+    }
+
+    #[test]
+    fn parses_device_and_group_ids() {
+        let details = details(&["deviceId=65537", "groupId=131073", "deviceId=65538"]);
+
+        assert_eq!(details.devices, [65537, 65538]);
+        assert_eq!(details.groups, [131073]);
+        assert!(details.other.is_empty());
+    }
+
+    #[test]
+    fn keeps_non_numeric_ids_and_other_pairs() {
+        let details = details(&["deviceId=lamp", "groupId=-1", "reason=2", "flag", "a=b=c"]);
+
+        assert!(details.devices.is_empty());
+        assert!(details.groups.is_empty());
+        assert_eq!(
+            details.other,
+            BTreeMap::from([
+                ("deviceId".to_string(), "lamp".to_string()),
+                ("groupId".to_string(), "-1".to_string()),
+                ("reason".to_string(), "2".to_string()),
+                ("flag".to_string(), String::new()),
+                ("a".to_string(), "b=c".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn maps_reboot_reasons() {
+        for (reason, expected) in [
+            (0, RebootReason::Default),
+            (1, RebootReason::FirmwareUpdate),
+            (2, RebootReason::Client),
+            (3, RebootReason::HomekitReset),
+            (-1, RebootReason::FactoryReset),
+            (4, RebootReason::Unknown(4)),
+            (-2, RebootReason::Unknown(-2)),
+        ] {
+            assert_eq!(RebootReason::from(reason), expected);
+        }
+    }
+
+    #[test]
+    fn reads_reboot_reason_from_details() {
+        let reboot = notification(json!({ "9015": 1003, "9017": ["reason=-1"] }));
+        assert!(matches!(
+            reboot,
+            GatewayNotification::Reboot {
+                reason: RebootReason::FactoryReset,
+                ..
+            }
+        ));
+        assert_eq!(reboot.details().other["reason"], "-1");
+
+        for pairs in [json!([]), json!(["reason=soon"])] {
+            assert!(matches!(
+                notification(json!({ "9015": 1003, "9017": pairs })),
+                GatewayNotification::Reboot {
+                    reason: RebootReason::Default,
+                    ..
+                }
+            ));
+        }
+    }
+
+    #[test]
+    fn maps_events() {
+        for event in [1001, 1003, 1004, 1005, 1006, 5000] {
+            let notification = notification(json!({ "9015": event }));
+            assert!(!matches!(notification, GatewayNotification::Other { .. }));
+            assert_eq!(notification.event(), event);
+        }
+    }
+
+    #[test]
+    fn keeps_unknown_events_as_other() {
+        let notification = notification(json!({
+            "9015": 1002,
+            "9002": 1700000000,
+            "9017": ["deviceId=65537"],
+            "9014": 0,
+        }));
+
+        assert!(matches!(
+            notification,
+            GatewayNotification::Other { event: 1002, .. }
+        ));
+        assert_eq!(notification.event(), 1002);
+        assert_eq!(notification.details().devices, [65537]);
+        assert_eq!(
+            notification.created_at().map(|time| time.timestamp()),
+            Some(1700000000)
+        );
+    }
+
+    #[test]
+    fn has_no_time_without_9002() {
+        let notification = notification(json!({ "9015": 1004, "9017": ["deviceId=65537"] }));
+
+        assert_eq!(notification.created_at(), None);
+        assert_eq!(notification.details().devices, [65537]);
+    }
+
+    #[test]
+    fn has_no_time_out_of_range() {
+        let notification = notification(json!({ "9015": 1004, "9002": i64::MAX }));
+
+        assert_eq!(notification.created_at(), None);
+    }
+}
@@ -0,0 +1,1011 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use coap::{
+    message::{
+        header::{MessageClass, ResponseType},
+        packet::Packet,
+    },
+    CoAPRequest, MessageType, Method,
+};
+
+use crate::{
+    response_code::response_error,
+    tradfri_coap::{initial_message_id, TransmissionParameters},
+    CoapError, Device, GatewayInfo, GatewayNotification, Group, ObserveChannel, OtaStatus,
+    ResponseCode, TradfriGateway, TradfriGatewayError,
+};
+
+/// Observe option value registering an observation (RFC 7641), encoded as an
+/// empty unsigned integer.
+const OBSERVE_REGISTER: &[u8] = &[];
+
+/// Observe option value deregistering an observation (RFC 7641).
+const OBSERVE_DEREGISTER: &[u8] = &[1];
+
+/// Notifications older than this are always considered fresh, regardless of
+/// their sequence number (RFC 7641 section 3.4).
+const OBSERVE_FRESHNESS: Duration = Duration::from_secs(128);
+
+/// Quiet time after which an observation pings the gateway to check that the
+/// session is still up.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Collection {
+    Devices,
+    Groups,
+}
+
+impl Collection {
+    fn path(&self) -> &'static str {
+        match self {
+            Collection::Devices => "15001",
+            Collection::Groups => "15004",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Target {
+    Collection(Collection),
+    Item(Collection, u32),
+    Gateway,
+    Notifications,
+}
+
+impl Target {
+    fn path(&self) -> String {
+        match self {
+            Target::Collection(collection) => collection.path().to_string(),
+            Target::Item(collection, id) => format!("{}/{}", collection.path(), id),
+            Target::Gateway => "15011/15012".to_string(),
+            Target::Notifications => "15006".to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Subscription {
+    target: Target,
+    /// Subscribed to by a collection observation rather than by the caller.
+    implicit: bool,
+    last_sequence: Option<(u32, Instant)>,
+    /// Registration the gateway has not answered yet.
+    registration: Option<Retransmission>,
+}
+
+/// Confirmable message waiting for an answer, sent again with a doubling
+/// interval like a regular request (RFC 7252 section 4.2).
+#[derive(Debug, Clone, Copy)]
+struct Retransmission {
+    message_id: u16,
+    retransmissions: u32,
+    interval: Duration,
+    retransmit_at: Instant,
+}
+
+impl Retransmission {
+    fn new(message_id: u16, now: Instant) -> Self {
+        let interval = TransmissionParameters::default().ack_timeout;
+        Self {
+            message_id,
+            retransmissions: 0,
+            interval,
+            retransmit_at: now + interval,
+        }
+    }
+
+    /// Schedules the next retransmission, `false` once all have been used up.
+    fn advance(&mut self, now: Instant) -> bool {
+        if self.retransmissions >= TransmissionParameters::default().max_retransmit {
+            return false;
+        }
+        self.retransmissions += 1;
+        self.interval *= 2;
+        self.retransmit_at = now + self.interval;
+        true
+    }
+}
+
+/// The CoAP observations kept open on one channel.
+///
+/// Notifications arrive whenever the gateway decides to send them, so an
+/// observation never shares its channel with regular requests. The state
+/// does not touch the channel itself: messages to send are queued and taken
+/// with [`ObservationState::take_outgoing`], which lets the blocking
+/// observers and the async ones drive the same state. When the gateway stops
+/// answering registrations or pings, for example after a reboot, the driver
+/// opens a new channel and calls [`ObservationState::restart`].
+#[derive(Debug)]
+pub(crate) struct ObservationState {
+    subscriptions: HashMap<Vec<u8>, Subscription>,
+    next_token: u32,
+    message_id: u16,
+    last_heard: Instant,
+    ping: Option<Retransmission>,
+    outgoing: Vec<Packet>,
+}
+
+impl ObservationState {
+    /// Starts observing the target, the registration is the first outgoing
+    /// message.
+    pub(crate) fn new(target: Target) -> Self {
+        let mut state = Self {
+            subscriptions: HashMap::new(),
+            next_token: 1,
+            message_id: initial_message_id(),
+            last_heard: Instant::now(),
+            ping: None,
+            outgoing: Vec::new(),
+        };
+        state.subscribe(target, false);
+
+        state
+    }
+
+    fn subscribe(&mut self, target: Target, implicit: bool) {
+        let token = self.next_token.to_be_bytes().to_vec();
+        self.next_token = self.next_token.wrapping_add(1);
+
+        self.subscriptions.insert(
+            token.clone(),
+            Subscription {
+                target,
+                implicit,
+                last_sequence: None,
+                registration: None,
+            },
+        );
+        self.register(&token);
+    }
+
+    /// Queues a new registration for a subscription.
+    fn register(&mut self, token: &[u8]) {
+        let message_id = self.next_message_id();
+        let Some(subscription) = self.subscriptions.get_mut(token) else {
+            return;
+        };
+        subscription.registration = Some(Retransmission::new(message_id, Instant::now()));
+        subscription.last_sequence = None;
+
+        let message = observe_message(subscription.target, token, OBSERVE_REGISTER, message_id);
+        self.outgoing.push(message);
+    }
+
+    fn next_message_id(&mut self) -> u16 {
+        self.message_id = self.message_id.wrapping_add(1);
+        self.message_id
+    }
+
+    /// Queues an empty message, such as an acknowledgement or reset for a
+    /// confirmable message or a ping.
+    fn send_empty(&mut self, message_type: MessageType, message_id: u16) {
+        let mut packet = Packet::new();
+        packet.header.set_type(message_type);
+        packet.header.code = MessageClass::Empty;
+        packet.header.set_message_id(message_id);
+
+        self.outgoing.push(packet);
+    }
+
+    /// Takes the messages to send on the channel, in order.
+    pub(crate) fn take_outgoing(&mut self) -> Vec<Packet> {
+        std::mem::take(&mut self.outgoing)
+    }
+
+    /// Whether nothing is observed any more, after the gateway ended all
+    /// observations.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+
+    /// Registers all subscriptions again on a new channel, as the gateway
+    /// forgets its observations along with the session.
+    pub(crate) fn restart(&mut self) {
+        self.outgoing.clear();
+        self.last_heard = Instant::now();
+        self.ping = None;
+
+        let tokens: Vec<Vec<u8>> = self.subscriptions.keys().cloned().collect();
+        for token in tokens {
+            self.register(&token);
+        }
+    }
+
+    /// Queues the registrations and pings that are due, and returns how long
+    /// to wait for the next message. Returns `None` if the gateway left one
+    /// unanswered after all retransmissions, and the session should be
+    /// replaced.
+    pub(crate) fn poll(&mut self, now: Instant) -> Option<Duration> {
+        let mut due = Vec::new();
+        for (token, subscription) in self.subscriptions.iter_mut() {
+            if let Some(registration) = subscription.registration.as_mut() {
+                if now >= registration.retransmit_at {
+                    if !registration.advance(now) {
+                        return None;
+                    }
+                    due.push(observe_message(
+                        subscription.target,
+                        token,
+                        OBSERVE_REGISTER,
+                        registration.message_id,
+                    ));
+                }
+            }
+        }
+        self.outgoing.extend(due);
+
+        match self.ping.as_mut() {
+            Some(ping) if now >= ping.retransmit_at => {
+                if !ping.advance(now) {
+                    return None;
+                }
+                let message_id = ping.message_id;
+                self.send_empty(MessageType::Confirmable, message_id);
+            }
+            // The gateway sends nothing while nothing changes, so check with
+            // a CoAP ping that the session is still up.
+            None if now >= self.last_heard + KEEPALIVE_INTERVAL => {
+                let message_id = self.next_message_id();
+                self.ping = Some(Retransmission::new(message_id, now));
+                self.send_empty(MessageType::Confirmable, message_id);
+            }
+            _ => (),
+        }
+
+        Some(
+            self.next_retransmission()
+                .saturating_duration_since(now)
+                .max(Duration::from_millis(1)),
+        )
+    }
+
+    /// When the next registration or ping is due.
+    fn next_retransmission(&self) -> Instant {
+        let ping = match self.ping {
+            Some(ping) => ping.retransmit_at,
+            None => self.last_heard + KEEPALIVE_INTERVAL,
+        };
+
+        self.subscriptions
+            .values()
+            .filter_map(|subscription| subscription.registration)
+            .map(|registration| registration.retransmit_at)
+            .fold(ping, Instant::min)
+    }
+
+    /// Handles a message from the gateway and returns the notification it
+    /// carries for the caller, if any, together with its target.
+    ///
+    /// Notifications for an observed collection are handled here by
+    /// subscribing to added items and forgetting removed ones.
+    pub(crate) fn handle(
+        &mut self,
+        message: Packet,
+    ) -> Option<Result<(Target, Vec<u8>), TradfriGatewayError>> {
+        self.last_heard = Instant::now();
+        self.ping = None;
+
+        let message_type = message.header.get_type();
+        let message_id = message.header.get_message_id();
+
+        if message.header.code == MessageClass::Empty {
+            let token = self
+                .subscriptions
+                .iter()
+                .find(|(_, s)| s.registration.map(|r| r.message_id) == Some(message_id))
+                .map(|(token, _)| token.clone())?;
+
+            if message_type == MessageType::Reset {
+                // The gateway refused the registration and will never send a
+                // notification for it.
+                let implicit = self.subscriptions.remove(&token).map(|s| s.implicit);
+                if implicit == Some(false) {
+                    return Some(Err(CoapError::Rejected.into()));
+                }
+            } else if let Some(subscription) = self.subscriptions.get_mut(&token) {
+                // A separate response to a registration follows its empty
+                // acknowledgement.
+                subscription.registration = None;
+            }
+            return None;
+        }
+
+        let token = message.get_token().clone();
+        let (target, implicit, last_sequence) = match self.subscriptions.get_mut(&token) {
+            Some(s) => {
+                s.registration = None;
+                (s.target, s.implicit, s.last_sequence)
+            }
+            None => {
+                // Tells the gateway to stop sending notifications for an
+                // observation that is no longer known.
+                if message_type == MessageType::Confirmable {
+                    self.send_empty(MessageType::Reset, message_id);
+                }
+                return None;
+            }
+        };
+
+        if message_type == MessageType::Confirmable {
+            self.send_empty(MessageType::Acknowledgement, message_id);
+        }
+
+        let succeeded = matches!(
+            message.header.code,
+            MessageClass::Response(ResponseType::Content)
+                | MessageClass::Response(ResponseType::Valid)
+        );
+        if !succeeded {
+            self.subscriptions.remove(&token);
+            if implicit {
+                return None;
+            }
+            return Some(Err(response_error(
+                ResponseCode::of(&message),
+                &message.payload,
+            )));
+        }
+
+        match message.get_observe().map(|value| decode_uint(value)) {
+            Some(sequence) => {
+                let now = Instant::now();
+                if let Some(previous) = last_sequence {
+                    if !is_fresh(previous, sequence, now) {
+                        return None;
+                    }
+                }
+                if let Some(subscription) = self.subscriptions.get_mut(&token) {
+                    subscription.last_sequence = Some((sequence, now));
+                }
+            }
+            // The gateway answered without registering the observation.
+            None => {
+                self.subscriptions.remove(&token);
+            }
+        }
+
+        match target {
+            Target::Collection(collection) => {
+                match serde_json::from_slice::<Vec<u32>>(&message.payload) {
+                    Ok(ids) => {
+                        self.sync_collection(collection, &ids);
+                        None
+                    }
+                    Err(error) => Some(Err(error.into())),
+                }
+            }
+            target => Some(Ok((target, message.payload))),
+        }
+    }
+
+    fn sync_collection(&mut self, collection: Collection, ids: &[u32]) {
+        self.subscriptions
+            .retain(|_, subscription| match subscription.target {
+                Target::Item(c, id) if c == collection && subscription.implicit => {
+                    ids.contains(&id)
+                }
+                _ => true,
+            });
+
+        for id in ids {
+            let target = Target::Item(collection, *id);
+            if !self.subscriptions.values().any(|s| s.target == target) {
+                self.subscribe(target, true);
+            }
+        }
+    }
+
+    /// Ends all observations, queueing a deregistration for each.
+    pub(crate) fn cancel(&mut self) {
+        let subscriptions: Vec<(Vec<u8>, Target)> = self
+            .subscriptions
+            .drain()
+            .map(|(token, subscription)| (token, subscription.target))
+            .collect();
+
+        for (token, target) in subscriptions {
+            let message_id = self.next_message_id();
+            self.outgoing.push(observe_message(
+                target,
+                &token,
+                OBSERVE_DEREGISTER,
+                message_id,
+            ));
+        }
+    }
+}
+
+/// Keeps observations open on a channel of a blocking gateway.
+struct Observation {
+    gateway: TradfriGateway,
+    /// `None` once the session has been lost, until a new one is opened.
+    channel: Option<Box<dyn ObserveChannel>>,
+    state: ObservationState,
+}
+
+impl Observation {
+    fn new(gateway: &TradfriGateway, target: Target) -> Result<Self, TradfriGatewayError> {
+        let mut observation = Self {
+            gateway: gateway.clone(),
+            channel: Some(gateway.open_observe_channel()?),
+            state: ObservationState::new(target),
+        };
+        observation.flush();
+
+        Ok(observation)
+    }
+
+    /// Sends the queued messages, dropping the channel if that fails.
+    fn flush(&mut self) {
+        let messages = self.state.take_outgoing();
+        if let Some(channel) = self.channel.as_mut() {
+            for message in &messages {
+                if let Err(error) = channel.send(message) {
+                    log::debug!("Observe session lost: {}", error);
+                    self.channel = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Blocks until the next notification for an observed item and returns its
+    /// id together with the raw payload, `None` once nothing is observed.
+    fn next_item(&mut self) -> Result<Option<(u32, Vec<u8>)>, TradfriGatewayError> {
+        loop {
+            match self.next_notification()? {
+                Some((Target::Item(_, id), payload)) => return Ok(Some((id, payload))),
+                Some(_) => (),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Blocks until the next notification and returns its target together
+    /// with the raw payload, `None` once the gateway has ended all
+    /// observations. Opens a new channel whenever the session has been lost.
+    fn next_notification(&mut self) -> Result<Option<(Target, Vec<u8>)>, TradfriGatewayError> {
+        loop {
+            if self.state.is_finished() {
+                return Ok(None);
+            }
+            if self.channel.is_none() {
+                self.channel = Some(self.gateway.open_observe_channel()?);
+                self.state.restart();
+            }
+
+            let timeout = self.state.poll(Instant::now());
+            self.flush();
+            let received = match (timeout, self.channel.as_mut()) {
+                (Some(timeout), Some(channel)) => channel.receive(timeout),
+                (None, _) => {
+                    log::debug!("Gateway stopped answering, observing on a new session");
+                    self.channel = None;
+                    continue;
+                }
+                (_, None) => continue,
+            };
+
+            match received {
+                Ok(Some(message)) => {
+                    let notification = self.state.handle(message);
+                    self.flush();
+                    if let Some(notification) = notification {
+                        return notification.map(Some);
+                    }
+                }
+                Ok(None) => (),
+                Err(error) => {
+                    log::debug!("Observe session lost: {}", error);
+                    self.channel = None;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Observation {
+    fn drop(&mut self) {
+        self.state.cancel();
+        self.flush();
+    }
+}
+
+fn observe_message(target: Target, token: &[u8], value: &[u8], message_id: u16) -> Packet {
+    let mut req = CoAPRequest::new();
+    req.set_path(&target.path());
+    req.set_method(Method::Get);
+    req.message.header.set_type(MessageType::Confirmable);
+    req.message.header.set_message_id(message_id);
+    req.message.set_observe(value.to_vec());
+    req.message.set_token(token.to_vec());
+
+    req.message
+}
+
+/// Decodes a CoAP unsigned integer option value.
+fn decode_uint(value: &[u8]) -> u32 {
+    value
+        .iter()
+        .take(4)
+        .fold(0, |acc, byte| (acc << 8) | u32::from(*byte))
+}
+
+/// Decides whether a notification is newer than the previous one for the same
+/// observation, according to RFC 7641 section 3.4.
+fn is_fresh(previous: (u32, Instant), sequence: u32, now: Instant) -> bool {
+    let (last_sequence, last_time) = previous;
+    (last_sequence < sequence && sequence - last_sequence < 1 << 23)
+        || (last_sequence > sequence && last_sequence - sequence > 1 << 23)
+        || now > last_time + OBSERVE_FRESHNESS
+}
+
+/// Blocking iterator over device change notifications.
+///
+/// Created by [`TradfriGateway::observe_device`] and
+/// [`TradfriGateway::observe_devices`]. The first item for each device is its
+/// current state, after that an item is yielded every time the gateway reports
+/// a change. Observations are cancelled when the iterator is dropped.
+///
+/// When the session is lost, for example because the gateway rebooted, the
+/// observations are registered again on a new session and the current state
+/// of each device is yielded again. An error is yielded each time a new
+/// session can not be set up. When the gateway refuses the registration,
+/// [`CoapError::Rejected`] is yielded. The iterator ends once the gateway has
+/// ended all observations.
+pub struct DeviceObserver {
+    observation: Observation,
+}
+
+impl DeviceObserver {
+    pub(crate) fn device(gateway: &TradfriGateway, id: u32) -> Result<Self, TradfriGatewayError> {
+        Ok(Self {
+            observation: Observation::new(gateway, Target::Item(Collection::Devices, id))?,
+        })
+    }
+
+    pub(crate) fn devices(gateway: &TradfriGateway) -> Result<Self, TradfriGatewayError> {
+        Ok(Self {
+            observation: Observation::new(gateway, Target::Collection(Collection::Devices))?,
+        })
+    }
+}
+
+impl Iterator for DeviceObserver {
+    type Item = Result<Device, TradfriGatewayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, payload) = match self.observation.next_item() {
+            Ok(item) => item?,
+            Err(error) => return Some(Err(error)),
+        };
+
+        Some(
+            Device::new(self.observation.gateway.clone(), &payload)
+                .map_err(|e| TradfriGatewayError::DeviceError(id, Box::new(e))),
+        )
+    }
+}
+
+/// Blocking iterator over group change notifications.
+///
+/// Created by [`TradfriGateway::observe_group`] and
+/// [`TradfriGateway::observe_groups`], behaves like [`DeviceObserver`].
+pub struct GroupObserver {
+    observation: Observation,
+}
+
+impl GroupObserver {
+    pub(crate) fn group(gateway: &TradfriGateway, id: u32) -> Result<Self, TradfriGatewayError> {
+        Ok(Self {
+            observation: Observation::new(gateway, Target::Item(Collection::Groups, id))?,
+        })
+    }
+
+    pub(crate) fn groups(gateway: &TradfriGateway) -> Result<Self, TradfriGatewayError> {
+        Ok(Self {
+            observation: Observation::new(gateway, Target::Collection(Collection::Groups))?,
+        })
+    }
+}
+
+impl Iterator for GroupObserver {
+    type Item = Result<Group, TradfriGatewayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, payload) = match self.observation.next_item() {
+            Ok(item) => item?,
+            Err(error) => return Some(Err(error)),
+        };
+
+        Some(
+            Group::new(self.observation.gateway.clone(), &payload)
+                .map_err(|e| TradfriGatewayError::GroupError(id, Box::new(e))),
+        )
+    }
+}
+
+/// Blocking iterator over the firmware update status of the gateway.
+///
+/// Created by [`TradfriGateway::observe_ota`]. The first item is the current
+/// status, after that an item is yielded every time the gateway reports a
+/// change to its details, such as the progress of an update. The observation
+/// is cancelled when the iterator is dropped, and recovers from a lost session
+/// like [`DeviceObserver`].
+pub struct OtaObserver {
+    observation: Observation,
+}
+
+impl OtaObserver {
+    pub(crate) fn new(gateway: &TradfriGateway) -> Result<Self, TradfriGatewayError> {
+        Ok(Self {
+            observation: Observation::new(gateway, Target::Gateway)?,
+        })
+    }
+}
+
+impl Iterator for OtaObserver {
+    type Item = Result<OtaStatus, TradfriGatewayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (_, payload) = match self.observation.next_notification() {
+            Ok(notification) => notification?,
+            Err(error) => return Some(Err(error)),
+        };
+
+        Some(
+            serde_json::from_slice::<GatewayInfo>(&payload)
+                .map(OtaStatus::from)
+                .map_err(TradfriGatewayError::from),
+        )
+    }
+}
+
+/// Blocking iterator over new entries of the notification list of the gateway.
+///
+/// Created by [`TradfriGateway::observe_notifications`]. Notifications that
+/// are already on the list when the observation starts are skipped, after
+/// that every notification the gateway adds is yielded once, oldest first.
+/// The observation is cancelled when the iterator is dropped, and recovers
+/// from a lost session like [`DeviceObserver`].
+pub struct NotificationObserver {
+    observation: Observation,
+    notifications: NewNotifications,
+}
+
+impl NotificationObserver {
+    pub(crate) fn new(gateway: &TradfriGateway) -> Result<Self, TradfriGatewayError> {
+        Ok(Self {
+            observation: Observation::new(gateway, Target::Notifications)?,
+            notifications: NewNotifications::default(),
+        })
+    }
+}
+
+impl Iterator for NotificationObserver {
+    type Item = Result<GatewayNotification, TradfriGatewayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(notification) = self.notifications.pending.pop_front() {
+                return Some(Ok(notification));
+            }
+
+            let (_, payload) = match self.observation.next_notification() {
+                Ok(notification) => notification?,
+                Err(error) => return Some(Err(error)),
+            };
+            if let Err(error) = self.notifications.update(&payload) {
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+/// Tells the notifications the gateway added to its list apart from the ones
+/// that were already on it.
+#[derive(Debug, Default)]
+pub(crate) struct NewNotifications {
+    /// Notifications on the latest list, `None` until the first list arrives.
+    seen: Option<HashSet<GatewayNotification>>,
+    /// New notifications not yielded yet, oldest first.
+    pub(crate) pending: VecDeque<GatewayNotification>,
+}
+
+impl NewNotifications {
+    /// Queues the notifications on the list that were not on the previous one.
+    pub(crate) fn update(&mut self, payload: &[u8]) -> Result<(), TradfriGatewayError> {
+        let notifications: Vec<GatewayNotification> = serde_json::from_slice(payload)?;
+
+        if let Some(seen) = &self.seen {
+            let mut new: Vec<GatewayNotification> = notifications
+                .iter()
+                .filter(|notification| !seen.contains(*notification))
+                .cloned()
+                .collect();
+            new.sort_by_key(|notification| notification.created_at());
+            self.pending.extend(new);
+        }
+        // Only the notifications still on the list need to be remembered.
+        self.seen = Some(notifications.into_iter().collect());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use super::*;
+    use crate::Transport;
+
+    const TOKEN: [u8; 4] = [0, 0, 0, 1];
+
+    #[derive(Debug)]
+    enum Event {
+        Message(Packet),
+        /// Nothing arrives within the timeout.
+        Silence,
+        /// The gateway resets the last registration.
+        Reset,
+    }
+
+    /// Hands out one scripted channel per session and records what is sent.
+    #[derive(Debug, Default)]
+    struct ScriptedTransport {
+        sessions: Mutex<VecDeque<Vec<Event>>>,
+        sent: Arc<Mutex<Vec<(usize, Packet)>>>,
+        opened: Mutex<usize>,
+    }
+
+    impl ScriptedTransport {
+        fn new(sessions: Vec<Vec<Event>>) -> Arc<Self> {
+            Arc::new(Self {
+                sessions: Mutex::new(sessions.into()),
+                ..Default::default()
+            })
+        }
+
+        fn registrations(&self, session: usize) -> Vec<u16> {
+            self.sent
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(s, packet)| {
+                    *s == session && packet.get_observe() == Some(&OBSERVE_REGISTER.to_vec())
+                })
+                .map(|(_, packet)| packet.header.get_message_id())
+                .collect()
+        }
+    }
+
+    impl Transport for ScriptedTransport {
+        fn request(&self, _: CoAPRequest) -> Result<coap::CoAPResponse, TradfriGatewayError> {
+            Err(TradfriGatewayError::Unsupported("requests"))
+        }
+
+        fn open_observe_channel(&self) -> Result<Box<dyn ObserveChannel>, TradfriGatewayError> {
+            let events = self
+                .sessions
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or(TradfriGatewayError::Unsupported("another session"))?;
+            let mut opened = self.opened.lock().unwrap();
+            *opened += 1;
+
+            Ok(Box::new(ScriptedChannel {
+                session: *opened,
+                events: events.into(),
+                sent: self.sent.clone(),
+            }))
+        }
+    }
+
+    struct ScriptedChannel {
+        session: usize,
+        events: VecDeque<Event>,
+        sent: Arc<Mutex<Vec<(usize, Packet)>>>,
+    }
+
+    impl ObserveChannel for ScriptedChannel {
+        fn send(&mut self, message: &Packet) -> Result<(), TradfriGatewayError> {
+            self.sent
+                .lock()
+                .unwrap()
+                .push((self.session, message.clone()));
+            Ok(())
+        }
+
+        /// Fails like a broken session once the script has run out.
+        fn receive(&mut self, timeout: Duration) -> Result<Option<Packet>, TradfriGatewayError> {
+            match self.events.pop_front() {
+                Some(Event::Message(message)) => Ok(Some(message)),
+                Some(Event::Silence) => {
+                    thread::sleep(timeout);
+                    Ok(None)
+                }
+                Some(Event::Reset) => {
+                    let sent = self.sent.lock().unwrap();
+                    let (_, registration) = sent.last().unwrap();
+                    let mut reset = Packet::new();
+                    reset.header.set_type(MessageType::Reset);
+                    reset.header.code = MessageClass::Empty;
+                    reset
+                        .header
+                        .set_message_id(registration.header.get_message_id());
+                    Ok(Some(reset))
+                }
+                None => Err(TradfriGatewayError::Unsupported("closed session")),
+            }
+        }
+    }
+
+    fn notification(sequence: Option<u8>, payload: &str) -> Event {
+        let mut packet = Packet::new();
+        packet.header.set_type(MessageType::NonConfirmable);
+        packet.header.code = MessageClass::Response(ResponseType::Content);
+        packet.set_token(TOKEN.to_vec());
+        if let Some(sequence) = sequence {
+            packet.set_observe(vec![sequence]);
+        }
+        packet.payload = payload.as_bytes().to_vec();
+
+        Event::Message(packet)
+    }
+
+    fn observe(transport: &Arc<ScriptedTransport>) -> Observation {
+        let gateway = TradfriGateway::from_transport(transport.clone());
+        Observation::new(&gateway, Target::Gateway).unwrap()
+    }
+
+    fn payload(observation: &mut Observation) -> Option<String> {
+        let (_, payload) = observation.next_notification().unwrap()?;
+        Some(String::from_utf8(payload).unwrap())
+    }
+
+    #[test]
+    fn ends_when_gateway_does_not_register_observation() {
+        let transport = ScriptedTransport::new(vec![vec![notification(None, "a")]]);
+        let mut observation = observe(&transport);
+
+        assert_eq!(payload(&mut observation).as_deref(), Some("a"));
+        assert_eq!(payload(&mut observation), None);
+    }
+
+    #[test]
+    fn skips_stale_notifications() {
+        let transport = ScriptedTransport::new(vec![vec![
+            notification(Some(5), "a"),
+            notification(Some(4), "stale"),
+            notification(Some(6), "b"),
+        ]]);
+        let mut observation = observe(&transport);
+
+        assert_eq!(payload(&mut observation).as_deref(), Some("a"));
+        assert_eq!(payload(&mut observation).as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn retransmits_unanswered_registration() {
+        let transport =
+            ScriptedTransport::new(vec![vec![Event::Silence, notification(Some(1), "a")]]);
+        let mut observation = observe(&transport);
+
+        assert_eq!(payload(&mut observation).as_deref(), Some("a"));
+        let registrations = transport.registrations(1);
+        assert_eq!(registrations.len(), 2);
+        assert_eq!(registrations[0], registrations[1]);
+    }
+
+    #[test]
+    fn registers_again_on_new_session() {
+        let transport = ScriptedTransport::new(vec![
+            vec![notification(Some(5), "a")],
+            vec![notification(Some(1), "b")],
+        ]);
+        let mut observation = observe(&transport);
+
+        assert_eq!(payload(&mut observation).as_deref(), Some("a"));
+        // The new session starts counting again.
+        assert_eq!(payload(&mut observation).as_deref(), Some("b"));
+        assert_eq!(transport.registrations(2).len(), 1);
+    }
+
+    #[test]
+    fn notification_observer_yields_only_new_notifications() {
+        let reboot = r#"{ "9015": 1003, "9002": 1700000000, "9017": ["reason=2"] }"#;
+        let lost = r#"{ "9015": 1004, "9002": 1700000100, "9017": ["deviceId=65537"] }"#;
+        let login = r#"{ "9015": 1005, "9002": 1700000200, "9017": [] }"#;
+        let firmware = r#"{ "9015": 1001, "9002": 1700000300, "9017": [] }"#;
+        let transport = ScriptedTransport::new(vec![vec![
+            notification(Some(1), &format!("[{}]", reboot)),
+            notification(Some(2), &format!("[{}, {}]", reboot, lost)),
+            notification(Some(3), &format!("[{}]", lost)),
+            notification(Some(4), &format!("[{}, {}, {}]", lost, firmware, login)),
+        ]]);
+        let gateway = TradfriGateway::from_transport(transport);
+        let mut observer = NotificationObserver::new(&gateway).unwrap();
+
+        let notification = observer.next().unwrap().unwrap();
+        assert_eq!(notification.event(), 1004);
+        assert_eq!(notification.details().devices, [65537]);
+        // Several new notifications are yielded oldest first.
+        let events: Vec<u32> = observer
+            .by_ref()
+            .take(2)
+            .map(|notification| notification.unwrap().event())
+            .collect();
+        assert_eq!(events, [1005, 1001]);
+        assert_eq!(observer.notifications.seen.map(|seen| seen.len()), Some(3));
+    }
+
+    #[test]
+    fn fails_when_gateway_refuses_registration() {
+        let transport = ScriptedTransport::new(vec![vec![Event::Reset]]);
+        let mut observation = observe(&transport);
+
+        assert!(matches!(
+            observation.next_notification(),
+            Err(TradfriGatewayError::CoapError(CoapError::Rejected))
+        ));
+        assert_eq!(payload(&mut observation), None);
+    }
+
+    #[test]
+    fn fails_when_no_new_session_can_be_opened() {
+        let transport = ScriptedTransport::new(vec![vec![notification(Some(1), "a")]]);
+        let mut observation = observe(&transport);
+
+        assert_eq!(payload(&mut observation).as_deref(), Some("a"));
+        assert!(matches!(
+            observation.next_notification(),
+            Err(TradfriGatewayError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn decodes_observe_sequence_numbers() {
+        assert_eq!(decode_uint(&[]), 0);
+        assert_eq!(decode_uint(&[7]), 7);
+        assert_eq!(decode_uint(&[0x01, 0x00]), 256);
+        assert_eq!(decode_uint(&[0xff, 0xff, 0xff]), (1 << 24) - 1);
+    }
+
+    #[test]
+    fn orders_sequence_numbers_across_wrap() {
+        let now = Instant::now();
+        let max = (1 << 24) - 1;
+
+        assert!(is_fresh((5, now), 6, now));
+        assert!(!is_fresh((6, now), 5, now));
+        assert!(!is_fresh((5, now), 5, now));
+
+        // The sequence number wrapped around after 2^24 notifications.
+        assert!(is_fresh((max, now), 0, now));
+        assert!(is_fresh((max - 10, now), 3, now));
+        assert!(!is_fresh((3, now), max - 10, now));
+
+        // Numbers more than 2^23 apart are taken as having wrapped.
+        assert!(!is_fresh((0, now), (1 << 23) + 1, now));
+        assert!(is_fresh((0, now), (1 << 23) - 1, now));
+    }
+
+    #[test]
+    fn accepts_any_sequence_number_after_freshness_period() {
+        let then = Instant::now();
+        let later = then + OBSERVE_FRESHNESS + Duration::from_secs(1);
+
+        assert!(!is_fresh((6, then), 5, then + OBSERVE_FRESHNESS));
+        assert!(is_fresh((6, then), 5, later));
+    }
+}
@@ -0,0 +1,257 @@
+use chrono::{NaiveTime, Timelike};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+
+use crate::GatewayInfo;
+
+/// Gateway attributes of the daily window for automatic updates, in minutes
+/// after midnight in the time zone of the gateway.
+pub(crate) const UPDATE_WINDOW_START: &str = "9064";
+pub(crate) const UPDATE_WINDOW_END: &str = "9065";
+
+/// Firmware update state of the gateway or of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OtaUpdateState {
+    #[default]
+    UpToDate,
+    Available,
+    Downloading,
+    ReadyToInstall,
+    Installing,
+    /// A state this crate does not know yet.
+    Unknown(u8),
+}
+
+impl From<u8> for OtaUpdateState {
+    fn from(state: u8) -> Self {
+        match state {
+            0 => OtaUpdateState::UpToDate,
+            1 => OtaUpdateState::Available,
+            2 => OtaUpdateState::Downloading,
+            3 => OtaUpdateState::ReadyToInstall,
+            4 => OtaUpdateState::Installing,
+            other => OtaUpdateState::Unknown(other),
+        }
+    }
+}
+
+impl From<OtaUpdateState> for u8 {
+    fn from(state: OtaUpdateState) -> Self {
+        match state {
+            OtaUpdateState::UpToDate => 0,
+            OtaUpdateState::Available => 1,
+            OtaUpdateState::Downloading => 2,
+            OtaUpdateState::ReadyToInstall => 3,
+            OtaUpdateState::Installing => 4,
+            OtaUpdateState::Unknown(other) => other,
+        }
+    }
+}
+
+impl Serialize for OtaUpdateState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8((*self).into())
+    }
+}
+
+impl<'de> Deserialize<'de> for OtaUpdateState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(u8::deserialize(deserializer)?.into())
+    }
+}
+
+/// How urgent an available firmware update is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UpdatePriority {
+    Normal,
+    Critical,
+    Required,
+    /// Installed without waiting for the update window.
+    Forced,
+    Unknown(u8),
+}
+
+impl From<u8> for UpdatePriority {
+    fn from(priority: u8) -> Self {
+        match priority {
+            0 => UpdatePriority::Normal,
+            1 => UpdatePriority::Critical,
+            2 => UpdatePriority::Required,
+            5 => UpdatePriority::Forced,
+            other => UpdatePriority::Unknown(other),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for UpdatePriority {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(u8::deserialize(deserializer)?.into())
+    }
+}
+
+/// Firmware update status of the gateway, read with
+/// [`TradfriGateway::ota_status`](crate::TradfriGateway::ota_status) or
+/// followed with [`TradfriGateway::observe_ota`](crate::TradfriGateway::observe_ota).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtaStatus {
+    /// The installed firmware version.
+    pub firmware_version: String,
+    pub state: OtaUpdateState,
+    /// Progress of the download or installation in percent.
+    pub progress: Option<u8>,
+    pub priority: Option<UpdatePriority>,
+    /// Release notes of the available update.
+    pub release_notes_url: Option<String>,
+}
+
+impl From<GatewayInfo> for OtaStatus {
+    fn from(info: GatewayInfo) -> Self {
+        Self {
+            firmware_version: info.firmware_version,
+            state: info.ota_update_state,
+            progress: info.update_progress,
+            priority: info.update_priority,
+            release_notes_url: info.update_details_url,
+        }
+    }
+}
+
+/// Daily time window in which the gateway installs updates on its own, in
+/// the time zone of the gateway, see
+/// [`TradfriGateway::set_update_window`](crate::TradfriGateway::set_update_window).
+///
+/// A window whose end is before its start spans midnight.
+///
+/// ```
+/// use chrono::NaiveTime;
+/// use tradfri_gateway::UpdateWindow;
+///
+/// let window = UpdateWindow::new(
+///     NaiveTime::from_hms_opt(2, 0, 0).unwrap(),
+///     NaiveTime::from_hms_opt(5, 30, 0).unwrap(),
+/// );
+/// assert_eq!(window.to_string(), "02:00-05:30");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UpdateWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl UpdateWindow {
+    /// A window between two times, which the gateway keeps to the minute.
+    pub fn new(start: NaiveTime, end: NaiveTime) -> Self {
+        Self {
+            start: whole_minutes(start),
+            end: whole_minutes(end),
+        }
+    }
+
+    pub fn start(&self) -> NaiveTime {
+        self.start
+    }
+
+    pub fn end(&self) -> NaiveTime {
+        self.end
+    }
+
+    pub(crate) fn from_minutes(start: u16, end: u16) -> Option<Self> {
+        let time = |minutes: u16| {
+            NaiveTime::from_hms_opt(u32::from(minutes / 60), u32::from(minutes % 60), 0)
+        };
+        Some(Self::new(time(start)?, time(end)?))
+    }
+
+    pub(crate) fn update(&self) -> Value {
+        let minutes = |time: NaiveTime| time.hour() * 60 + time.minute();
+        json!({
+            UPDATE_WINDOW_START: minutes(self.start),
+            UPDATE_WINDOW_END: minutes(self.end),
+        })
+    }
+}
+
+impl std::fmt::Display for UpdateWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+fn whole_minutes(time: NaiveTime) -> NaiveTime {
+    NaiveTime::from_hms_opt(time.hour(), time.minute(), 0).unwrap_or(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(hour: u32, minute: u32, second: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, second).unwrap()
+    }
+
+    #[test]
+    fn maps_update_states_both_ways() {
+        for state in 0..=5u8 {
+            assert_eq!(u8::from(OtaUpdateState::from(state)), state);
+        }
+        assert_eq!(OtaUpdateState::from(3), OtaUpdateState::ReadyToInstall);
+        assert_eq!(OtaUpdateState::from(9), OtaUpdateState::Unknown(9));
+        assert_eq!(UpdatePriority::from(5), UpdatePriority::Forced);
+        assert_eq!(UpdatePriority::from(3), UpdatePriority::Unknown(3));
+    }
+
+    #[test]
+    fn reads_status_from_gateway_info() {
+        let info: GatewayInfo = serde_json::from_value(json!({
+            "9029": "1.19.32",
+            "9054": 2,
+            "9055": 40,
+            "9056": "https://example.com/release-notes",
+            "9066": 1,
+        }))
+        .unwrap();
+
+        let status = OtaStatus::from(info);
+        assert_eq!(status.firmware_version, "1.19.32");
+        assert_eq!(status.state, OtaUpdateState::Downloading);
+        assert_eq!(status.progress, Some(40));
+        assert_eq!(status.priority, Some(UpdatePriority::Critical));
+        assert_eq!(
+            status.release_notes_url.as_deref(),
+            Some("https://example.com/release-notes")
+        );
+    }
+
+    #[test]
+    fn status_defaults_to_up_to_date() {
+        let info: GatewayInfo = serde_json::from_value(json!({ "9029": "1.19.32" })).unwrap();
+
+        let status = OtaStatus::from(info);
+        assert_eq!(status.state, OtaUpdateState::UpToDate);
+        assert_eq!(status.progress, None);
+        assert_eq!(status.priority, None);
+    }
+
+    #[test]
+    fn keeps_update_window_to_whole_minutes() {
+        let window = UpdateWindow::new(time(2, 0, 59), time(5, 30, 1));
+
+        assert_eq!(window.start(), time(2, 0, 0));
+        assert_eq!(window.end(), time(5, 30, 0));
+        assert_eq!(window.update(), json!({ "9064": 120, "9065": 330 }));
+    }
+
+    #[test]
+    fn reads_update_window_from_minutes() {
+        let window = UpdateWindow::from_minutes(1380, 90).unwrap();
+
+        assert_eq!(window.to_string(), "23:00-01:30");
+        assert_eq!(window.update(), json!({ "9064": 1380, "9065": 90 }));
+        assert_eq!(UpdateWindow::from_minutes(0, 1440), None);
+    }
+}
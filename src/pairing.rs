@@ -0,0 +1,295 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{CoapError, ResponseCode, Secret};
+
+/// Length of the security code printed on the bottom of the gateway.
+const SECURITY_CODE_LEN: usize = 16;
+
+/// Longest identifier accepted for pairing.
+const MAX_IDENTIFIER_LEN: usize = 64;
+
+/// Options for pairing with a gateway, see
+/// [`TradfriGateway::pair_with`](crate::TradfriGateway::pair_with).
+///
+/// Pairing registers a random identifier, and tries again with a fresh one
+/// when the identifier is already in use or the gateway does not answer, up
+/// to three attempts by default.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use tradfri_gateway::{PairingOptions, TradfriGateway};
+///
+/// let options = PairingOptions::new()
+///     .with_timeout(Duration::from_secs(20))
+///     .with_attempts(5);
+/// let credentials =
+///     TradfriGateway::pair_with([192, 168, 1, 10], "Ab3dEf7hIj1lMn0p", &options).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairingOptions {
+    timeout: Duration,
+    attempts: u32,
+    identifier: Option<String>,
+}
+
+impl PairingOptions {
+    pub fn new() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            attempts: 3,
+            identifier: None,
+        }
+    }
+
+    /// Time to wait for the gateway in each attempt, 10 seconds by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Number of attempts, each with a fresh identifier, at least one.
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Identifier to register in the first attempt instead of a random one,
+    /// later attempts use random identifiers.
+    pub fn with_identifier(mut self, identifier: &str) -> Self {
+        self.identifier = Some(identifier.into());
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// The identifier of the first attempt, a new random one unless one has
+    /// been given.
+    pub(crate) fn first_identifier(&self) -> Result<String, PairingError> {
+        match &self.identifier {
+            Some(identifier) => validate_identifier(identifier).map(|_| identifier.clone()),
+            None => Ok(generate_identifier()),
+        }
+    }
+}
+
+impl Default for PairingOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PairingError {
+    #[error("Invalid security code, expected the 16 letters and digits printed on the gateway")]
+    InvalidSecurityCode,
+
+    #[error("Invalid identifier {0:?}, expected 1 to 64 printable ASCII characters")]
+    InvalidIdentifier(String),
+
+    #[error("The gateway rejected the security code")]
+    WrongSecurityCode,
+
+    #[error("The identifier {0:?} is already in use on the gateway")]
+    IdentityInUse(String),
+
+    #[error("Gateway did not answer the pairing request within {0:?}")]
+    Timeout(Duration),
+
+    #[error("Unexpected pairing response ({0}): {1}")]
+    UnexpectedResponse(ResponseCode, String),
+
+    #[error("Pairing failed: {0}")]
+    CoapError(#[from] CoapError),
+}
+
+impl PairingError {
+    /// Returns `true` for errors that may go away by pairing again with a
+    /// fresh identifier.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            PairingError::IdentityInUse(_) | PairingError::Timeout(_) => true,
+            PairingError::CoapError(error) => error.is_retryable(),
+            PairingError::InvalidSecurityCode
+            | PairingError::InvalidIdentifier(_)
+            | PairingError::WrongSecurityCode
+            | PairingError::UnexpectedResponse(..) => false,
+        }
+    }
+}
+
+/// The identifiers a pairing registers in turn, as configured in the
+/// [`PairingOptions`].
+pub(crate) struct PairingAttempts<'a> {
+    options: &'a PairingOptions,
+    identifier: String,
+    attempt: u32,
+}
+
+impl<'a> PairingAttempts<'a> {
+    pub(crate) fn new(options: &'a PairingOptions) -> Result<Self, PairingError> {
+        Ok(Self {
+            options,
+            identifier: options.first_identifier()?,
+            attempt: 1,
+        })
+    }
+
+    /// The identifier to register in the current attempt.
+    pub(crate) fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    /// Moves on to the next attempt with a fresh identifier, or returns the
+    /// error if retrying will not help or no attempts are left.
+    pub(crate) fn retry(&mut self, error: PairingError) -> Result<(), PairingError> {
+        if !error.is_retryable() || self.attempt >= self.options.attempts() {
+            return Err(error);
+        }
+
+        log::debug!("Pairing as {} failed, retrying: {}", self.identifier, error);
+        self.identifier = generate_identifier();
+        self.attempt += 1;
+        Ok(())
+    }
+}
+
+/// Checks the format of a security code, ignoring surrounding whitespace.
+pub(crate) fn security_code(code: &str) -> Result<Secret, PairingError> {
+    let code = code.trim();
+    if code.len() == SECURITY_CODE_LEN && code.bytes().all(|byte| byte.is_ascii_alphanumeric()) {
+        Ok(Secret::from(code))
+    } else {
+        Err(PairingError::InvalidSecurityCode)
+    }
+}
+
+fn validate_identifier(identifier: &str) -> Result<(), PairingError> {
+    let valid = (1..=MAX_IDENTIFIER_LEN).contains(&identifier.len())
+        && identifier.bytes().all(|byte| byte.is_ascii_graphic());
+    if valid {
+        Ok(())
+    } else {
+        Err(PairingError::InvalidIdentifier(identifier.into()))
+    }
+}
+
+/// A random identifier, such as `user-8c1f52a0d93e47b6`.
+pub(crate) fn generate_identifier() -> String {
+    let mut bytes = [0u8; 8];
+    if openssl::rand::rand_bytes(&mut bytes).is_err() {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        bytes = (nanos ^ u64::from(std::process::id()) << 32).to_be_bytes();
+    }
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("user-{}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_security_code_with_surrounding_whitespace() {
+        let code = security_code("  Ab3dEf7hIj1lMn0p\n").unwrap();
+
+        assert_eq!(code.expose(), b"Ab3dEf7hIj1lMn0p");
+    }
+
+    #[test]
+    fn rejects_malformed_security_codes() {
+        for code in [
+            "",
+            "Ab3dEf7hIj1lMn0",
+            "Ab3dEf7hIj1lMn0pq",
+            "Ab3d-Ef7h-Ij1l-Mn",
+            "Ab3dEf7hIj1lMn0ä",
+        ] {
+            assert!(
+                matches!(security_code(code), Err(PairingError::InvalidSecurityCode)),
+                "{:?}",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn validates_identifiers() {
+        assert!(validate_identifier("a").is_ok());
+        assert!(validate_identifier(&"a".repeat(MAX_IDENTIFIER_LEN)).is_ok());
+        assert!(validate_identifier("home-assistant_1").is_ok());
+
+        let too_long = "a".repeat(MAX_IDENTIFIER_LEN + 1);
+        for identifier in ["", "with space", "tab\t", "ünicode", too_long.as_str()] {
+            assert!(
+                matches!(
+                    validate_identifier(identifier),
+                    Err(PairingError::InvalidIdentifier(invalid)) if invalid == identifier
+                ),
+                "{:?}",
+                identifier
+            );
+        }
+    }
+
+    #[test]
+    fn generates_distinct_valid_identifiers() {
+        let identifier = generate_identifier();
+
+        assert!(identifier.starts_with("user-"));
+        assert_eq!(identifier.len(), 21);
+        assert!(validate_identifier(&identifier).is_ok());
+        assert_ne!(identifier, generate_identifier());
+    }
+
+    #[test]
+    fn uses_given_identifier_first() {
+        let options = PairingOptions::new().with_identifier("home-assistant");
+        assert_eq!(options.first_identifier().unwrap(), "home-assistant");
+
+        let options = PairingOptions::new().with_identifier("with space");
+        assert!(matches!(
+            options.first_identifier(),
+            Err(PairingError::InvalidIdentifier(_))
+        ));
+    }
+
+    #[test]
+    fn retries_retryable_errors_with_fresh_identifiers() {
+        let options = PairingOptions::new()
+            .with_identifier("home-assistant")
+            .with_attempts(2);
+        let mut attempts = PairingAttempts::new(&options).unwrap();
+        assert_eq!(attempts.identifier(), "home-assistant");
+
+        let in_use = PairingError::IdentityInUse("home-assistant".into());
+        assert!(attempts.retry(in_use).is_ok());
+        assert!(attempts.identifier().starts_with("user-"));
+
+        let timeout = PairingError::Timeout(Duration::from_secs(10));
+        assert!(matches!(
+            attempts.retry(timeout),
+            Err(PairingError::Timeout(_))
+        ));
+    }
+
+    #[test]
+    fn does_not_retry_wrong_security_code() {
+        let options = PairingOptions::new();
+        let mut attempts = PairingAttempts::new(&options).unwrap();
+
+        assert!(matches!(
+            attempts.retry(PairingError::WrongSecurityCode),
+            Err(PairingError::WrongSecurityCode)
+        ));
+    }
+}
@@ -0,0 +1,64 @@
+use coap::{CoAPRequest, CoAPResponse, Method};
+use serde_json::Value;
+
+use crate::{ResponseCode, TradfriGatewayError};
+
+/// Successful response to a raw resource request, such as
+/// [`TradfriGateway::get`](crate::TradfriGateway::get).
+///
+/// Error responses are returned as errors, like they are for the typed API.
+///
+/// ```
+/// use serde_json::json;
+/// use tradfri_gateway::{MemoryTransport, TradfriGateway};
+///
+/// let transport = MemoryTransport::new().with_resource("15011/15012", json!({"9029": "1.19.32"}));
+/// let gateway = TradfriGateway::from_transport(transport);
+///
+/// let response = gateway.get("15011/15012").unwrap();
+/// assert_eq!(response.code.to_string(), "2.05");
+/// assert_eq!(response.value["9029"], "1.19.32");
+///
+/// gateway.put("15011/15012", &json!({"9029": "1.21.31"})).unwrap();
+/// assert_eq!(gateway.get("15011/15012").unwrap().value["9029"], "1.21.31");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawResponse {
+    pub code: ResponseCode,
+    /// The JSON payload, `Value::Null` for responses without one. A payload
+    /// that is not JSON is returned as a string.
+    pub value: Value,
+}
+
+impl RawResponse {
+    pub(crate) fn new(response: &CoAPResponse) -> Self {
+        let payload = &response.message.payload;
+        let value = if payload.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(payload)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(payload).into_owned()))
+        };
+
+        Self {
+            code: ResponseCode::of(&response.message),
+            value,
+        }
+    }
+}
+
+/// Builds a request for a raw resource path, such as `15011/15012`.
+pub(crate) fn raw_request(
+    method: Method,
+    path: &str,
+    value: Option<&Value>,
+) -> Result<CoAPRequest, TradfriGatewayError> {
+    let mut req = CoAPRequest::new();
+    req.set_path(path);
+    req.set_method(method);
+    if let Some(value) = value {
+        req.message.payload = serde_json::to_vec(value)?;
+    }
+
+    Ok(req)
+}
@@ -0,0 +1,138 @@
+//! Requests of the typed gateway API, built in one place for the blocking
+//! [`TradfriGateway`](crate::TradfriGateway) and its async counterpart, which
+//! only differ in how they send them.
+
+use coap::{CoAPRequest, CoAPResponse, Method};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{DeviceUpdate, GroupUpdate, LightSetting, SceneUpdate, TradfriGatewayError};
+
+const DEVICES: &str = "15001";
+const GROUPS: &str = "15004";
+const SCENES: &str = "15005";
+const NOTIFICATIONS: &str = "15006";
+const GATEWAY: &str = "15011/15012";
+
+fn request(method: Method, path: &str) -> CoAPRequest {
+    let mut req = CoAPRequest::new();
+    req.set_path(path);
+    req.set_method(method);
+
+    req
+}
+
+fn request_with<T: Serialize>(
+    method: Method,
+    path: &str,
+    value: &T,
+) -> Result<CoAPRequest, TradfriGatewayError> {
+    let mut req = request(method, path);
+    req.message.payload = serde_json::to_vec(value)?;
+
+    Ok(req)
+}
+
+/// Reads the ids of a collection from its response.
+pub(crate) fn ids(response: &CoAPResponse) -> Result<Vec<u32>, TradfriGatewayError> {
+    Ok(serde_json::from_slice(&response.message.payload)?)
+}
+
+pub(crate) fn device_ids() -> CoAPRequest {
+    request(Method::Get, DEVICES)
+}
+
+pub(crate) fn device(id: u32) -> CoAPRequest {
+    request(Method::Get, &format!("{}/{}", DEVICES, id))
+}
+
+pub(crate) fn update_device(
+    id: u32,
+    update: &DeviceUpdate,
+) -> Result<CoAPRequest, TradfriGatewayError> {
+    request_with(Method::Put, &format!("{}/{}", DEVICES, id), update)
+}
+
+pub(crate) fn group_ids() -> CoAPRequest {
+    request(Method::Get, GROUPS)
+}
+
+pub(crate) fn group(id: u32) -> CoAPRequest {
+    request(Method::Get, &format!("{}/{}", GROUPS, id))
+}
+
+pub(crate) fn update_group(
+    id: u32,
+    update: &GroupUpdate,
+) -> Result<CoAPRequest, TradfriGatewayError> {
+    request_with(Method::Put, &format!("{}/{}", GROUPS, id), update)
+}
+
+pub(crate) fn scene_ids(group_id: u32) -> CoAPRequest {
+    request(Method::Get, &format!("{}/{}", SCENES, group_id))
+}
+
+pub(crate) fn scene(group_id: u32, id: u32) -> CoAPRequest {
+    request(Method::Get, &format!("{}/{}/{}", SCENES, group_id, id))
+}
+
+pub(crate) fn create_scene(
+    group_id: u32,
+    name: &str,
+    lights: Vec<LightSetting>,
+) -> Result<CoAPRequest, TradfriGatewayError> {
+    let scene = SceneUpdate {
+        name: Some(name.into()),
+        lights: Some(lights),
+        ..Default::default()
+    };
+
+    request_with(Method::Post, &format!("{}/{}", SCENES, group_id), &scene)
+}
+
+pub(crate) fn update_scene(
+    group_id: u32,
+    id: u32,
+    update: &SceneUpdate,
+) -> Result<CoAPRequest, TradfriGatewayError> {
+    request_with(
+        Method::Put,
+        &format!("{}/{}/{}", SCENES, group_id, id),
+        update,
+    )
+}
+
+pub(crate) fn delete_scene(group_id: u32, id: u32) -> CoAPRequest {
+    request(Method::Delete, &format!("{}/{}/{}", SCENES, group_id, id))
+}
+
+pub(crate) fn notifications() -> CoAPRequest {
+    request(Method::Get, NOTIFICATIONS)
+}
+
+pub(crate) fn gateway_info() -> CoAPRequest {
+    request(Method::Get, GATEWAY)
+}
+
+/// Merges the JSON into the details of the gateway, used for its settings.
+pub(crate) fn update_gateway(update: &Value) -> Result<CoAPRequest, TradfriGatewayError> {
+    request_with(Method::Put, GATEWAY, update)
+}
+
+pub(crate) fn reboot() -> CoAPRequest {
+    request(Method::Post, "15011/9030")
+}
+
+pub(crate) fn factory_reset() -> CoAPRequest {
+    request(Method::Post, "15011/9031")
+}
+
+/// Makes the gateway look for new firmware. Like rebooting, this is an
+/// action on a resource of its own rather than a gateway attribute.
+pub(crate) fn check_for_updates() -> CoAPRequest {
+    request(Method::Post, "15011/9032")
+}
+
+pub(crate) fn start_update() -> CoAPRequest {
+    request(Method::Post, "15011/9037")
+}
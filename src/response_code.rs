@@ -0,0 +1,78 @@
+use std::fmt;
+
+use coap::{
+    message::{header::class_to_code, packet::Packet},
+    CoAPResponse,
+};
+
+use crate::TradfriGatewayError;
+
+/// CoAP response code, shown as `class.detail`, e.g. `4.04`.
+///
+/// Error responses of the gateway are returned as the matching
+/// [`TradfriGatewayError`] variant, carrying the code and the diagnostic
+/// payload:
+///
+/// ```
+/// use tradfri_gateway::{MemoryTransport, TradfriGateway, TradfriGatewayError};
+///
+/// let gateway = TradfriGateway::from_transport(MemoryTransport::new());
+///
+/// match gateway.device(65537) {
+///     Err(TradfriGatewayError::NotFound(code, _)) => assert_eq!(code.to_string(), "4.04"),
+///     other => panic!("unexpected result: {:?}", other.map(|_| ())),
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResponseCode {
+    pub class: u8,
+    pub detail: u8,
+}
+
+impl ResponseCode {
+    pub(crate) fn of(message: &Packet) -> Self {
+        let code = class_to_code(&message.header.code);
+
+        Self {
+            class: code >> 5,
+            detail: code & 0x1f,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.class == 2
+    }
+}
+
+impl fmt::Display for ResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:02}", self.class, self.detail)
+    }
+}
+
+/// Passes successful responses through and turns error responses into the
+/// matching [`TradfriGatewayError`], carrying the code and the diagnostic
+/// payload the gateway sent along.
+pub(crate) fn check_response(response: CoAPResponse) -> Result<CoAPResponse, TradfriGatewayError> {
+    let code = ResponseCode::of(&response.message);
+    if code.is_success() {
+        return Ok(response);
+    }
+
+    Err(response_error(code, &response.message.payload))
+}
+
+/// Turns a response code that is not a success into the matching error.
+pub(crate) fn response_error(code: ResponseCode, payload: &[u8]) -> TradfriGatewayError {
+    let payload = String::from_utf8_lossy(payload).into_owned();
+
+    match (code.class, code.detail) {
+        (4, 1) | (4, 3) => TradfriGatewayError::Unauthorized(code, payload),
+        (4, 4) => TradfriGatewayError::NotFound(code, payload),
+        (4, 5) => TradfriGatewayError::MethodNotAllowed(code, payload),
+        (4, _) => TradfriGatewayError::BadRequest(code, payload),
+        (5, 3) => TradfriGatewayError::ServiceUnavailable(code, payload),
+        (5, _) => TradfriGatewayError::ServerError(code, payload),
+        _ => TradfriGatewayError::UnexpectedResponse(code, payload),
+    }
+}
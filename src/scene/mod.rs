@@ -0,0 +1,264 @@
+use coap::CoAPResponse;
+
+use crate::{GroupUpdate, ResponseCode, TradfriGateway, TradfriGatewayError};
+
+mod parse;
+use chrono::{DateTime, Utc};
+pub use parse::*;
+
+mod update;
+pub use update::*;
+
+/// A scene of a group, called a mood in the IKEA app.
+///
+/// Scenes are stored per group and list the state each light of the group
+/// takes when the scene is activated. They are read with
+/// [`TradfriGateway::scenes`] and [`TradfriGateway::scene`], and created with
+/// [`TradfriGateway::create_scene`]. Like a [`Light`](crate::Light), a scene
+/// is generic over the gateway handle it sends its requests with.
+///
+/// ```no_run
+/// use tradfri_gateway::{LightSetting, SceneUpdate, TradfriGateway};
+///
+/// let gateway = TradfriGateway::from_identifier_and_session_key_and_addr(
+///     [192, 168, 1, 10],
+///     "identifier",
+///     "session key",
+/// );
+/// let mut scene = gateway
+///     .create_scene(131073, "Reading", vec![LightSetting::new(65537, true)])
+///     .unwrap();
+/// scene
+///     .edit(&SceneUpdate {
+///         lights: Some(vec![LightSetting::new(65537, true).with_brightness(200)]),
+///         ..Default::default()
+///     })
+///     .unwrap();
+/// scene.activate().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Scene<G = TradfriGateway> {
+    gateway: G,
+    group_id: u32,
+    id: u32,
+    name: String,
+    index: u32,
+    predefined: bool,
+    creation_date: DateTime<Utc>,
+    lights: Vec<LightSetting>,
+}
+
+impl<G> Scene<G> {
+    pub fn new(gateway: G, group_id: u32, bytes: &[u8]) -> Result<Self, SceneError> {
+        let parsed: SceneParsed = match serde_json::from_slice(bytes) {
+            Ok(d) => d,
+            Err(error) => {
+                return Err(SceneError::SerdeError(
+                    error,
+                    String::from_utf8_lossy(bytes).to_string(),
+                ))
+            }
+        };
+
+        Ok(Self {
+            gateway,
+            group_id,
+            id: parsed.id,
+            name: parsed.name,
+            index: parsed.index,
+            predefined: parsed.predefined,
+            creation_date: DateTime::from_timestamp(parsed.creation_date.into(), 0).unwrap(),
+            lights: parsed.lights,
+        })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Id of the group the scene belongs to.
+    pub fn group_id(&self) -> u32 {
+        self.group_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Position of the scene in the list of the IKEA app.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Whether the scene is one of the moods the gateway comes with.
+    pub fn is_predefined(&self) -> bool {
+        self.predefined
+    }
+
+    pub fn creation_date(&self) -> DateTime<Utc> {
+        self.creation_date
+    }
+
+    /// The state each light takes when the scene is activated.
+    pub fn lights(&self) -> &[LightSetting] {
+        &self.lights
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn gateway(&self) -> &G {
+        &self.gateway
+    }
+
+    /// Takes over the state of the scene as read again from the gateway.
+    pub(crate) fn refresh(&mut self, scene: Scene<G>) {
+        self.name = scene.name;
+        self.index = scene.index;
+        self.predefined = scene.predefined;
+        self.creation_date = scene.creation_date;
+        self.lights = scene.lights;
+    }
+}
+
+impl Scene {
+    /// Puts the lights of the group in the states of the scene.
+    pub fn activate(&self) -> Result<(), SceneError> {
+        let update = GroupUpdate::activate_scene(self.id);
+
+        self.gateway.update_group(self.group_id, &update)?;
+        Ok(())
+    }
+
+    pub fn edit(&mut self, update: &SceneUpdate) -> Result<(), SceneError> {
+        self.gateway.update_scene(self.group_id, self.id, update)?;
+        self.update()
+    }
+
+    /// Removes the scene from the gateway.
+    pub fn delete(self) -> Result<(), SceneError> {
+        self.gateway.delete_scene(self.group_id, self.id)?;
+        Ok(())
+    }
+
+    pub fn update(&mut self) -> Result<(), SceneError> {
+        let scene = self.gateway.scene(self.group_id, self.id)?;
+        self.refresh(scene);
+
+        Ok(())
+    }
+}
+
+/// Reads the id of a scene from the `2.01 Created` response of the gateway to
+/// creating it.
+pub(crate) fn created_id(response: &CoAPResponse) -> Result<u32, TradfriGatewayError> {
+    serde_json::from_slice::<serde_json::Value>(&response.message.payload)
+        .ok()
+        .and_then(|created| created.get("9003")?.as_u64())
+        .and_then(|id| u32::try_from(id).ok())
+        .ok_or_else(|| {
+            TradfriGatewayError::UnexpectedResponse(
+                ResponseCode::of(&response.message),
+                String::from_utf8_lossy(&response.message.payload).to_string(),
+            )
+        })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SceneError {
+    #[error("Serde error: {0}, raw data: {1}")]
+    SerdeError(#[source] serde_json::Error, String),
+
+    #[error("Tradfri gateway error: {0}")]
+    TradfriGatewayError(#[from] TradfriGatewayError),
+}
+
+#[cfg(test)]
+mod tests {
+    use coap::message::{
+        header::{MessageClass, ResponseType},
+        packet::Packet,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    fn parse(value: serde_json::Value) -> Result<Scene<()>, SceneError> {
+        Scene::new((), 131073, &serde_json::to_vec(&value).unwrap())
+    }
+
+    fn created(payload: &[u8]) -> CoAPResponse {
+        let mut message = Packet::new();
+        message.header.code = MessageClass::Response(ResponseType::Created);
+        message.payload = payload.to_vec();
+        CoAPResponse { message }
+    }
+
+    #[test]
+    fn parses_scene() {
+        let scene = parse(json!({
+            "9001": "RELAX",
+            "9002": 1700000000,
+            "9003": 196608,
+            "9057": 2,
+            "9068": 1,
+            "15013": [
+                { "9003": 65537, "5850": 1, "5851": 254, "5706": "f1e0b5" },
+                { "9003": 65538, "5850": 0, "5711": 454 },
+            ],
+        }))
+        .unwrap();
+
+        assert_eq!(scene.id(), 196608);
+        assert_eq!(scene.group_id(), 131073);
+        assert_eq!(scene.name(), "RELAX");
+        assert_eq!(scene.index(), 2);
+        assert!(scene.is_predefined());
+        assert_eq!(scene.creation_date().timestamp(), 1700000000);
+        assert_eq!(
+            scene.lights(),
+            [
+                LightSetting::new(65537, true)
+                    .with_brightness(254)
+                    .with_color_hex("f1e0b5"),
+                LightSetting::new(65538, false).with_color_temperature(454),
+            ]
+        );
+    }
+
+    #[test]
+    fn defaults_missing_index_flag_and_lights() {
+        let scene =
+            parse(json!({ "9001": "Evening", "9002": 1700000000, "9003": 196609 })).unwrap();
+
+        assert_eq!(scene.index(), 0);
+        assert!(!scene.is_predefined());
+        assert!(scene.lights().is_empty());
+    }
+
+    #[test]
+    fn keeps_raw_data_of_invalid_scene() {
+        match parse(json!({ "9001": "Evening" })) {
+            Err(SceneError::SerdeError(_, raw)) => assert_eq!(raw, r#"{"9001":"Evening"}"#),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_created_id() {
+        assert_eq!(created_id(&created(br#"{"9003":196610}"#)).unwrap(), 196610);
+    }
+
+    #[test]
+    fn fails_without_created_id() {
+        for payload in [
+            &b""[..],
+            b"{}",
+            b"{\"9003\":\"196610\"}",
+            b"{\"9003\":8589934592}",
+        ] {
+            assert!(matches!(
+                created_id(&created(payload)),
+                Err(TradfriGatewayError::UnexpectedResponse(..))
+            ));
+        }
+    }
+}
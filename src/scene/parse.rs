@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+use crate::serialization::{bool_from_int, int_from_bool};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SceneParsed {
+    #[serde(rename = "9001")]
+    pub name: String,
+    #[serde(rename = "9002")]
+    pub creation_date: u32,
+    #[serde(rename = "9003")]
+    pub id: u32,
+    #[serde(rename = "9057", default)]
+    pub index: u32,
+    #[serde(rename = "9068", default, deserialize_with = "bool_from_int")]
+    pub predefined: bool,
+    #[serde(rename = "15013", default)]
+    pub lights: Vec<LightSetting>,
+}
+
+/// The state a scene puts one light in.
+///
+/// ```
+/// use tradfri_gateway::LightSetting;
+///
+/// let setting = LightSetting::new(65537, true)
+///     .with_brightness(128)
+///     .with_color_hex("f1e0b5");
+/// assert_eq!(setting.brightness, Some(128));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LightSetting {
+    /// Id of the light.
+    #[serde(rename = "9003")]
+    pub id: u32,
+    #[serde(
+        rename = "5850",
+        deserialize_with = "bool_from_int",
+        serialize_with = "int_from_bool"
+    )]
+    pub on: bool,
+    #[serde(rename = "5851", default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u8>,
+    #[serde(rename = "5706", default, skip_serializing_if = "Option::is_none")]
+    pub color_hex: Option<String>,
+    #[serde(rename = "5711", default, skip_serializing_if = "Option::is_none")]
+    pub color_temperature: Option<u32>,
+}
+
+impl LightSetting {
+    pub fn new(id: u32, on: bool) -> Self {
+        Self {
+            id,
+            on,
+            brightness: None,
+            color_hex: None,
+            color_temperature: None,
+        }
+    }
+
+    pub fn with_brightness(mut self, brightness: u8) -> Self {
+        self.brightness = Some(brightness);
+        self
+    }
+
+    pub fn with_color_hex(mut self, color_hex: &str) -> Self {
+        self.color_hex = Some(color_hex.into());
+        self
+    }
+
+    pub fn with_color_temperature(mut self, color_temperature: u32) -> Self {
+        self.color_temperature = Some(color_temperature);
+        self
+    }
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+use crate::LightSetting;
+
+/// Changes to a scene, fields left at `None` are kept as they are.
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
+pub struct SceneUpdate {
+    #[serde(rename = "9001", skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Position of the scene in the list of the IKEA app.
+    #[serde(rename = "9057", skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+    /// The new states of the lights of the scene.
+    #[serde(rename = "15013", skip_serializing_if = "Option::is_none")]
+    pub lights: Option<Vec<LightSetting>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn serializes_only_changed_fields() {
+        let update = SceneUpdate {
+            index: Some(3),
+            lights: Some(vec![
+                LightSetting::new(65537, true).with_brightness(128),
+                LightSetting::new(65538, false),
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            serde_json::to_value(update).unwrap(),
+            json!({
+                "9057": 3,
+                "15013": [
+                    { "9003": 65537, "5850": 1, "5851": 128 },
+                    { "9003": 65538, "5850": 0 },
+                ],
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(SceneUpdate::default()).unwrap(),
+            json!({})
+        );
+    }
+}
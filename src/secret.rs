@@ -0,0 +1,178 @@
+use std::fmt;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroize;
+
+/// A secret, such as a session key or the security code of a gateway.
+///
+/// It prints as `[REDACTED]` with both `Debug` and `Display`, so a gateway
+/// or its credentials can be logged without leaking keys, and its memory is
+/// wiped when it is dropped. The value is only available through
+/// [`Secret::expose`] and [`Secret::expose_str`].
+///
+/// ```
+/// use tradfri_gateway::Secret;
+///
+/// let key = Secret::from("5Gb3dBlRUVy7DDqQ");
+/// assert_eq!(format!("{:?}", key), "[REDACTED]");
+/// assert_eq!(key.expose_str(), Some("5Gb3dBlRUVy7DDqQ"));
+/// ```
+#[derive(Clone, Default)]
+pub struct Secret(Vec<u8>);
+
+impl Secret {
+    pub fn new<B: Into<Vec<u8>>>(bytes: B) -> Self {
+        Self(bytes.into())
+    }
+
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The secret as a string, `None` if it is not UTF-8.
+    pub fn expose_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.0).ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Compares in constant time for secrets of equal length.
+impl PartialEq for Secret {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && openssl::memcmp::eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Secret {}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(secret: &str) -> Self {
+        Self::new(secret)
+    }
+}
+
+/// Takes over the string without copying it.
+impl From<String> for Secret {
+    fn from(secret: String) -> Self {
+        Self(secret.into_bytes())
+    }
+}
+
+impl From<&[u8]> for Secret {
+    fn from(secret: &[u8]) -> Self {
+        Self::new(secret)
+    }
+}
+
+impl From<Vec<u8>> for Secret {
+    fn from(secret: Vec<u8>) -> Self {
+        Self(secret)
+    }
+}
+
+/// Serialized as a string, for storing credentials. Secrets that are not
+/// UTF-8 can not be serialized.
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.expose_str() {
+            Some(secret) => serializer.serialize_str(secret),
+            None => Err(serde::ser::Error::custom("secret is not UTF-8")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_string(SecretVisitor)
+    }
+}
+
+struct SecretVisitor;
+
+impl<'de> de::Visitor<'de> for SecretVisitor {
+    type Value = Secret;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_str<E: de::Error>(self, secret: &str) -> Result<Secret, E> {
+        Ok(Secret::from(secret))
+    }
+
+    fn visit_string<E: de::Error>(self, secret: String) -> Result<Secret, E> {
+        Ok(Secret::from(secret))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_debug_and_display() {
+        for secret in [
+            Secret::from("5Gb3dBlRUVy7DDqQ"),
+            Secret::from(&[0xff, 0x00, 0x7f][..]),
+            Secret::default(),
+        ] {
+            assert_eq!(format!("{:?}", secret), "[REDACTED]");
+            assert_eq!(format!("{:#?}", secret), "[REDACTED]");
+            assert_eq!(secret.to_string(), "[REDACTED]");
+        }
+    }
+
+    #[test]
+    fn redacts_inside_other_values() {
+        let secrets = (
+            Some(Secret::from("5Gb3dBlRUVy7DDqQ")),
+            vec![Secret::from("k3y")],
+        );
+
+        assert_eq!(format!("{:?}", secrets), "(Some([REDACTED]), [[REDACTED]])");
+    }
+
+    #[test]
+    fn compares_values() {
+        assert_eq!(Secret::from("k3y"), Secret::from(b"k3y".to_vec()));
+        assert_ne!(Secret::from("k3y"), Secret::from("k3z"));
+        assert_ne!(Secret::from("k3y"), Secret::from("k3y!"));
+    }
+
+    #[test]
+    fn serializes_as_string() {
+        let secret: Secret = serde_json::from_str(r#""5Gb3dBlRUVy7DDqQ""#).unwrap();
+
+        assert_eq!(secret.expose_str(), Some("5Gb3dBlRUVy7DDqQ"));
+        assert_eq!(
+            serde_json::to_string(&secret).unwrap(),
+            r#""5Gb3dBlRUVy7DDqQ""#
+        );
+        assert!(serde_json::to_string(&Secret::from(&[0xff][..])).is_err());
+    }
+}
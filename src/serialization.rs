@@ -1,3 +1,4 @@
+use chrono::{DateTime, FixedOffset, Utc};
 use serde::{de, Deserialize, Deserializer, Serializer};
 
 pub(crate) fn option_bool_from_int<'de, D>(deserializer: D) -> Result<Option<bool>, D::Error>
@@ -28,6 +29,13 @@ where
     }
 }
 
+pub(crate) fn int_from_bool<S>(value: &bool, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_u8(u8::from(*value))
+}
+
 pub(crate) fn bool_from_int<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: Deserializer<'de>,
@@ -41,3 +49,33 @@ where
         )),
     }
 }
+
+pub(crate) fn option_datetime_from_timestamp<'de, D>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<i64>::deserialize(deserializer)?
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0)))
+}
+
+/// Reads an ISO 8601 time, keeping its UTC offset. Times that do not parse
+/// are read as `None`.
+pub(crate) fn option_datetime_from_rfc3339<'de, D>(
+    deserializer: D,
+) -> Result<Option<DateTime<FixedOffset>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?
+        .and_then(|time| DateTime::parse_from_rfc3339(&time).ok()))
+}
+
+/// Reads any non-zero number as `true`.
+pub(crate) fn bool_from_nonzero<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(u64::deserialize(deserializer)? != 0)
+}
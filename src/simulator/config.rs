@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Devices, groups and scenes served by a [`Simulator`](crate::Simulator),
+/// along with the codes it accepts.
+///
+/// The configuration can be built in code or deserialized from JSON:
+///
+/// ```json
+/// {
+///     "security_code": "SimulatorCode001",
+///     "lights": [{ "id": 65537, "name": "Kitchen ceiling", "on": true }],
+///     "groups": [{ "id": 131073, "name": "Kitchen", "lights": [65537] }],
+///     "scenes": [{ "id": 196608, "group": 131073, "name": "Dinner",
+///                  "lights": [{ "id": 65537, "on": true, "brightness": 80 }] }]
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulatorConfig {
+    /// The security code printed on the underside of a real gateway, used to
+    /// pair new clients.
+    pub security_code: String,
+    pub lights: Vec<SimulatedLight>,
+    pub groups: Vec<SimulatedGroup>,
+    pub scenes: Vec<SimulatedScene>,
+    /// Identifiers and session keys of clients that have already been paired.
+    pub clients: HashMap<String, String>,
+}
+
+impl SimulatorConfig {
+    pub fn new(security_code: &str) -> Self {
+        Self {
+            security_code: security_code.into(),
+            ..Default::default()
+        }
+    }
+
+    /// A small home with three lights in two rooms and a scene, used by the
+    /// `tradfri-simulator` binary when no configuration is given.
+    pub fn demo() -> Self {
+        Self::new("SimulatorCode001")
+            .with_light(SimulatedLight::new(65537, "Kitchen ceiling").with_on(true))
+            .with_light(SimulatedLight::new(65538, "Kitchen table"))
+            .with_light(SimulatedLight::new(65539, "Living room floor lamp"))
+            .with_group(SimulatedGroup::new(131073, "Kitchen", &[65537, 65538]))
+            .with_group(SimulatedGroup::new(131074, "Living room", &[65539]))
+            .with_scene(
+                SimulatedScene::new(196608, 131073, "Dinner")
+                    .with_light(SceneLight::new(65537, false, 0))
+                    .with_light(SceneLight::new(65538, true, 120)),
+            )
+    }
+
+    pub fn with_light(mut self, light: SimulatedLight) -> Self {
+        self.lights.push(light);
+        self
+    }
+
+    pub fn with_group(mut self, group: SimulatedGroup) -> Self {
+        self.groups.push(group);
+        self
+    }
+
+    pub fn with_scene(mut self, scene: SimulatedScene) -> Self {
+        self.scenes.push(scene);
+        self
+    }
+
+    /// Accepts a client that has already been paired, so that it can connect
+    /// with its identifier and session key directly.
+    pub fn with_client(mut self, identifier: &str, session_key: &str) -> Self {
+        self.clients
+            .insert(identifier.to_string(), session_key.to_string());
+        self
+    }
+}
+
+/// A white spectrum bulb.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimulatedLight {
+    pub id: u32,
+    pub name: String,
+    #[serde(default)]
+    pub on: bool,
+    #[serde(default = "full_brightness")]
+    pub brightness: u8,
+    /// Unreachable lights are listed, but flagged as not reachable.
+    #[serde(default = "reachable")]
+    pub reachable: bool,
+}
+
+impl SimulatedLight {
+    pub fn new(id: u32, name: &str) -> Self {
+        Self {
+            id,
+            name: name.into(),
+            on: false,
+            brightness: full_brightness(),
+            reachable: true,
+        }
+    }
+
+    pub fn with_on(mut self, on: bool) -> Self {
+        self.on = on;
+        self
+    }
+
+    pub fn with_brightness(mut self, brightness: u8) -> Self {
+        self.brightness = brightness;
+        self
+    }
+
+    pub fn with_reachable(mut self, reachable: bool) -> Self {
+        self.reachable = reachable;
+        self
+    }
+}
+
+/// A group of lights, switching the group switches all of its lights.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimulatedGroup {
+    pub id: u32,
+    pub name: String,
+    /// Ids of the lights in the group.
+    #[serde(default)]
+    pub lights: Vec<u32>,
+}
+
+impl SimulatedGroup {
+    pub fn new(id: u32, name: &str, lights: &[u32]) -> Self {
+        Self {
+            id,
+            name: name.into(),
+            lights: lights.to_vec(),
+        }
+    }
+}
+
+/// A scene, or mood, of a group, activating it applies its light settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimulatedScene {
+    pub id: u32,
+    /// Id of the group the scene belongs to.
+    pub group: u32,
+    pub name: String,
+    #[serde(default)]
+    pub lights: Vec<SceneLight>,
+}
+
+impl SimulatedScene {
+    pub fn new(id: u32, group: u32, name: &str) -> Self {
+        Self {
+            id,
+            group,
+            name: name.into(),
+            lights: Vec::new(),
+        }
+    }
+
+    pub fn with_light(mut self, light: SceneLight) -> Self {
+        self.lights.push(light);
+        self
+    }
+}
+
+/// The state a scene puts one light in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SceneLight {
+    pub id: u32,
+    pub on: bool,
+    #[serde(default = "full_brightness")]
+    pub brightness: u8,
+}
+
+impl SceneLight {
+    pub fn new(id: u32, on: bool, brightness: u8) -> Self {
+        Self { id, on, brightness }
+    }
+}
+
+fn full_brightness() -> u8 {
+    254
+}
+
+fn reachable() -> bool {
+    true
+}
@@ -0,0 +1,129 @@
+//! A simulated TRÅDFRI gateway for end-to-end tests and demos without
+//! hardware.
+
+mod config;
+pub use config::*;
+
+mod server;
+mod state;
+
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use serde_json::Value;
+
+use self::state::GatewayState;
+use crate::udp_dtls::DtlsListener;
+
+/// A simulated gateway serving the lights, groups and scenes of a
+/// [`SimulatorConfig`] over CoAP secured with DTLS-PSK, like a real gateway.
+///
+/// Clients pair with the security code of the configuration, using the same
+/// key exchange as a real gateway, or connect with the identifier and session
+/// key of an already paired client. [`TradfriGateway`](crate::TradfriGateway)
+/// connects to the simulator unchanged:
+///
+/// ```no_run
+/// use tradfri_gateway::{Simulator, SimulatorConfig, TradfriGateway};
+///
+/// let simulator = Simulator::bind("127.0.0.1:5684", SimulatorConfig::demo()).unwrap();
+/// let gateway = TradfriGateway::from_gateway_code_and_addr([127, 0, 0, 1], "SimulatorCode001").unwrap();
+/// for group in gateway.groups().unwrap() {
+///     group.unwrap().on().unwrap();
+/// }
+/// ```
+///
+/// Observations are not simulated, observe requests are answered once with the
+/// current state. The simulator runs on background threads until it is
+/// dropped.
+#[derive(Debug)]
+pub struct Simulator {
+    local_addr: SocketAddr,
+    state: Arc<GatewayState>,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Simulator {
+    /// Starts a simulator on the CoAPS port of all IPv4 interfaces.
+    pub fn start(config: SimulatorConfig) -> Result<Self, SimulatorError> {
+        Self::bind(("0.0.0.0", 5684), config)
+    }
+
+    /// Starts a simulator on the given address.
+    ///
+    /// Clients always connect to port 5684, other ports are only useful when
+    /// the simulator is reached through a port forward.
+    pub fn bind<A: ToSocketAddrs>(
+        address: A,
+        config: SimulatorConfig,
+    ) -> Result<Self, SimulatorError> {
+        let listener = DtlsListener::bind(address)?;
+        listener.set_read_timeout(Some(server::POLL_INTERVAL))?;
+        let local_addr = listener.local_addr()?;
+
+        let state = Arc::new(GatewayState::new(&config));
+        let acceptor = server::acceptor(state.clients())?;
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let state = state.clone();
+            let running = running.clone();
+            thread::Builder::new()
+                .name("tradfri-simulator".into())
+                .spawn(move || server::serve(listener, acceptor, state, running))?
+        };
+
+        Ok(Self {
+            local_addr,
+            state,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Returns the current JSON of a resource, such as `15001/65537` for a
+    /// light, to check what clients have changed.
+    pub fn resource(&self, path: &str) -> Option<Value> {
+        self.state.resource(path)
+    }
+
+    /// Stops the simulator and waits for it and its sessions to shut down,
+    /// which takes a fraction of a second. The address can be bound again
+    /// right away.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SimulatorError {
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("DTLS error: {0}")]
+    DtlsError(#[from] crate::udp_dtls::Error),
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use coap::{
+    message::{header::MessageClass, packet::Packet},
+    MessageType,
+};
+
+use super::state::{Clients, GatewayState};
+use crate::{
+    tradfri_coap::GATEWAY_CIPHER,
+    udp_dtls::{DtlsAcceptor, DtlsListener, DtlsStream, HandshakeError, PeerChannel, Protocol},
+};
+
+/// How often blocked threads check whether the simulator has been stopped.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time allowed for a client to complete the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sessions without any traffic for this long are dropped.
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Number of recent responses kept to answer retransmitted requests.
+const RECENT_RESPONSES: usize = 16;
+
+const BUF_SIZE: usize = 8192;
+
+/// Accepts clients until the simulator is stopped, running every session on a
+/// thread of its own, and then waits for the sessions to end.
+pub(crate) fn serve(
+    mut listener: DtlsListener,
+    acceptor: DtlsAcceptor,
+    state: Arc<GatewayState>,
+    running: Arc<AtomicBool>,
+) {
+    let mut sessions: Vec<JoinHandle<()>> = Vec::new();
+
+    while running.load(Ordering::Relaxed) {
+        sessions.retain(|session| !session.is_finished());
+
+        let mut channel = match listener.accept() {
+            Ok(channel) => channel,
+            Err(error) if is_timeout(&error) => continue,
+            Err(error) => {
+                log::debug!("Failed to receive datagram: {}", error);
+                continue;
+            }
+        };
+        channel.set_read_timeout(Some(POLL_INTERVAL));
+
+        let acceptor = acceptor.clone();
+        let state = state.clone();
+        let running = running.clone();
+        sessions.push(thread::spawn(move || {
+            run_session(&acceptor, channel, &state, &running)
+        }));
+    }
+
+    // The sessions share the socket, which is only closed once they are done.
+    for session in sessions {
+        let _ = session.join();
+    }
+}
+
+/// Creates the acceptor for the PSK cipher suite of the gateway, accepting the
+/// identities of the given clients.
+pub(crate) fn acceptor(clients: Clients) -> crate::udp_dtls::Result<DtlsAcceptor> {
+    DtlsAcceptor::builder()
+        .add_cipher(GATEWAY_CIPHER)
+        .min_protocol_version(Some(Protocol::Dtlsv12))
+        .max_protocol_version(Some(Protocol::Dtlsv12))
+        .psk_server_callback(move |identity| {
+            clients
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .get(identity)
+                .cloned()
+        })
+        .build()
+}
+
+fn run_session(
+    acceptor: &DtlsAcceptor,
+    channel: PeerChannel,
+    state: &GatewayState,
+    running: &AtomicBool,
+) {
+    let peer = channel.peer_addr();
+    let mut stream = match accept(acceptor, channel, running) {
+        Ok(stream) => stream,
+        Err(error) => {
+            log::debug!("Handshake with {} failed: {}", peer, error);
+            return;
+        }
+    };
+    let identity = stream.psk_identity().unwrap_or_default().to_vec();
+    log::debug!(
+        "Session with {} established as {}",
+        peer,
+        String::from_utf8_lossy(&identity)
+    );
+
+    let mut session = Session {
+        identity,
+        recent: VecDeque::new(),
+        message_id: 0,
+    };
+    let mut last_activity = Instant::now();
+    let mut buf = [0u8; BUF_SIZE];
+    while running.load(Ordering::Relaxed) {
+        let len = match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(error) if is_timeout(&error) => {
+                if last_activity.elapsed() > SESSION_IDLE_TIMEOUT {
+                    break;
+                }
+                continue;
+            }
+            Err(error) => {
+                log::debug!("Session with {} failed: {}", peer, error);
+                break;
+            }
+        };
+        last_activity = Instant::now();
+
+        let request = match Packet::from_bytes(&buf[..len]) {
+            Ok(request) => request,
+            Err(error) => {
+                log::debug!("Ignoring malformed message from {}: {:?}", peer, error);
+                continue;
+            }
+        };
+        if let Some(response) = session.respond(state, request) {
+            if let Err(error) = stream.write_all(&response) {
+                log::debug!("Failed to answer {}: {}", peer, error);
+                break;
+            }
+        }
+    }
+
+    let _ = stream.shutdown();
+}
+
+fn accept(
+    acceptor: &DtlsAcceptor,
+    channel: PeerChannel,
+    running: &AtomicBool,
+) -> Result<DtlsStream<PeerChannel>, String> {
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    let mut result = acceptor.accept(channel.peer_addr(), channel);
+    loop {
+        match result {
+            Ok(stream) => return Ok(stream),
+            // Reading timed out, trying again lets OpenSSL retransmit its last
+            // flight once its own timer has run out.
+            Err(HandshakeError::WouldBlock(mid_handshake)) => {
+                if Instant::now() > deadline || !running.load(Ordering::Relaxed) {
+                    return Err("handshake timed out".into());
+                }
+                result = mid_handshake.handshake();
+            }
+            Err(HandshakeError::Failure(error)) => return Err(error.to_string()),
+        }
+    }
+}
+
+/// CoAP state of one DTLS session.
+struct Session {
+    identity: Vec<u8>,
+    /// Responses to recent confirmable requests by message id.
+    recent: VecDeque<(u16, Vec<u8>)>,
+    message_id: u16,
+}
+
+impl Session {
+    /// Returns the encoded answer to a message, if it needs one.
+    fn respond(&mut self, state: &GatewayState, request: Packet) -> Option<Vec<u8>> {
+        let message_type = request.header.get_type();
+        let message_id = request.header.get_message_id();
+
+        if !matches!(request.header.code, MessageClass::Request(_)) {
+            // A confirmable empty message is a ping, answered with a reset.
+            return match (message_type, &request.header.code) {
+                (MessageType::Confirmable, MessageClass::Empty) => {
+                    let mut reset = Packet::new();
+                    reset.header.set_type(MessageType::Reset);
+                    reset.header.code = MessageClass::Empty;
+                    reset.header.set_message_id(message_id);
+                    reset.to_bytes().ok()
+                }
+                _ => None,
+            };
+        }
+
+        let confirmable = message_type == MessageType::Confirmable;
+        if confirmable {
+            if let Some((_, response)) = self.recent.iter().find(|(id, _)| *id == message_id) {
+                return Some(response.clone());
+            }
+        }
+
+        let token = request.get_token().clone();
+        let mut response = state.handle(&self.identity, request);
+        response.set_token(token);
+        if confirmable {
+            response.header.set_message_id(message_id);
+        } else {
+            self.message_id = self.message_id.wrapping_add(1);
+            response.header.set_type(MessageType::NonConfirmable);
+            response.header.set_message_id(self.message_id);
+        }
+
+        let bytes = match response.to_bytes() {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                log::debug!("Failed to encode response: {:?}", error);
+                return None;
+            }
+        };
+        if confirmable {
+            if self.recent.len() == RECENT_RESPONSES {
+                self.recent.pop_front();
+            }
+            self.recent.push_back((message_id, bytes.clone()));
+        }
+
+        Some(bytes)
+    }
+}
+
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
@@ -0,0 +1,464 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use coap::{
+    message::{
+        header::{MessageClass, ResponseType},
+        packet::Packet,
+    },
+    CoAPRequest, MessageType, Method,
+};
+use serde_json::{json, Value};
+
+use super::{SimulatedGroup, SimulatedLight, SimulatedScene, SimulatorConfig};
+use crate::{MemoryTransport, Transport};
+
+/// Identity used by clients that pair with the security code.
+pub(crate) const PAIRING_IDENTITY: &[u8] = b"Client_identity";
+
+const FIRMWARE_VERSION: &str = "1.21.31";
+
+/// Id the gateway gives to the first scene.
+const FIRST_SCENE_ID: u32 = 196608;
+
+/// Identities and keys accepted during the DTLS handshake, shared with the PSK
+/// server callback.
+pub(crate) type Clients = Arc<Mutex<HashMap<Vec<u8>, Vec<u8>>>>;
+
+/// Resources of the simulated gateway, and the gateway behaviour on top of
+/// plain storage: pairing, groups switching their lights and scenes.
+#[derive(Debug)]
+pub(crate) struct GatewayState {
+    resources: MemoryTransport,
+    clients: Clients,
+    /// Serializes requests, as a group update touches several resources.
+    lock: Mutex<()>,
+}
+
+impl GatewayState {
+    pub fn new(config: &SimulatorConfig) -> Self {
+        let now = unix_time();
+        let mut resources = MemoryTransport::new()
+            .without_recording()
+            .with_resource("15005", json!([]))
+            .with_resource("15006", json!([]))
+            .with_resource(
+                "15011/15012",
+                json!({
+                    "9023": "pool.ntp.org",
+                    "9029": FIRMWARE_VERSION,
+                    "9054": 0,
+                    "9059": now,
+                    "9060": chrono::Utc::now().to_rfc3339(),
+                    "9061": 0,
+                    "9066": 5,
+                    "9069": now,
+                    "9071": 1,
+                    "9081": "7e0f0f0f0f0f0f0f",
+                    "9082": true,
+                    "9083": "911-22-333",
+                }),
+            );
+
+        for light in &config.lights {
+            resources = resources.with_device(light_resource(light, now));
+        }
+        for group in &config.groups {
+            resources = resources.with_group(group_resource(group, now));
+            add_id(&resources, "15005", group.id);
+            resources.set_resource(&format!("15005/{}", group.id), json!([]));
+        }
+        for scene in &config.scenes {
+            add_scene(&resources, scene, now);
+        }
+
+        let mut clients: HashMap<Vec<u8>, Vec<u8>> = config
+            .clients
+            .iter()
+            .map(|(identity, key)| (identity.as_bytes().to_vec(), key.as_bytes().to_vec()))
+            .collect();
+        clients.insert(
+            PAIRING_IDENTITY.to_vec(),
+            config.security_code.as_bytes().to_vec(),
+        );
+
+        Self {
+            resources,
+            clients: Arc::new(Mutex::new(clients)),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn clients(&self) -> Clients {
+        self.clients.clone()
+    }
+
+    pub fn resource(&self, path: &str) -> Option<Value> {
+        self.resources.resource(path)
+    }
+
+    /// Answers one request from a client authenticated as `identity`.
+    pub fn handle(&self, identity: &[u8], request: Packet) -> Packet {
+        let _lock = self
+            .lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let request = CoAPRequest {
+            message: request,
+            response: None,
+            source: None,
+        };
+        let path = request.get_path();
+        let method = request.get_method().clone();
+
+        let (status, payload) = if identity == PAIRING_IDENTITY {
+            match (&method, path.as_str()) {
+                (Method::Post, "15011/9063") => self.pair(&request.message.payload),
+                _ => (ResponseType::Unauthorized, None),
+            }
+        } else {
+            self.request(&method, &path, request)
+        };
+
+        let mut response = Packet::new();
+        response.header.set_type(MessageType::Acknowledgement);
+        response.header.code = MessageClass::Response(status);
+        if let Some(payload) = payload {
+            response.payload = payload.to_string().into_bytes();
+        }
+
+        response
+    }
+
+    fn request(
+        &self,
+        method: &Method,
+        path: &str,
+        request: CoAPRequest,
+    ) -> (ResponseType, Option<Value>) {
+        match (method, path) {
+            // Nothing to restart, the simulated state is kept.
+            (Method::Post, "15011/9030") => {
+                self.notify(json!({
+                    "9015": 1003,
+                    "9002": unix_time(),
+                    "9017": ["reason=2"],
+                    "9014": 0,
+                }));
+                return (ResponseType::Changed, None);
+            }
+            (Method::Post, "15011/9031") => {
+                self.forget_clients();
+                return (ResponseType::Changed, None);
+            }
+            // The simulated firmware is always up to date.
+            (Method::Post, "15011/9032") | (Method::Post, "15011/9037") => {
+                return (ResponseType::Changed, None)
+            }
+            _ => {}
+        }
+
+        let scene_path: Vec<u32> = path
+            .strip_prefix("15005/")
+            .map(|path| path.split('/').filter_map(|id| id.parse().ok()).collect())
+            .unwrap_or_default();
+        if let (Method::Post, [group_id]) = (method, scene_path.as_slice()) {
+            return self.create_scene(*group_id, &request.message.payload);
+        }
+
+        let update: Option<Value> = serde_json::from_slice(&request.message.payload).ok();
+
+        let response = match self.resources.request(request) {
+            Ok(response) => response,
+            Err(_) => return (ResponseType::BadRequest, None),
+        };
+        let status = match response.message.header.code {
+            MessageClass::Response(status) => status,
+            _ => ResponseType::InternalServerError,
+        };
+        let payload = serde_json::from_slice(&response.message.payload).ok();
+
+        let group_id = path
+            .strip_prefix("15004/")
+            .and_then(|id| id.parse::<u32>().ok());
+        if let (Method::Put, ResponseType::Changed, Some(group_id), Some(update)) =
+            (method, &status, group_id, update)
+        {
+            self.update_group_lights(group_id, &update);
+        }
+
+        (status, payload)
+    }
+
+    /// Stores a new scene of a group under the next free scene id, the way
+    /// the gateway does when a mood is created.
+    fn create_scene(&self, group_id: u32, payload: &[u8]) -> (ResponseType, Option<Value>) {
+        let mut scene = match serde_json::from_slice::<Value>(payload) {
+            Ok(Value::Object(scene)) => scene,
+            _ => return (ResponseType::BadRequest, None),
+        };
+        let group_path = format!("15005/{}", group_id);
+        let scenes = match self.resources.resource(&group_path) {
+            Some(Value::Array(scenes)) => scenes,
+            _ => return (ResponseType::NotFound, None),
+        };
+
+        // Scene ids are unique across all groups.
+        let id = self
+            .resources
+            .resource("15005")
+            .and_then(|groups| groups.as_array().cloned())
+            .into_iter()
+            .flatten()
+            .filter_map(|group| self.resources.resource(&format!("15005/{}", group)))
+            .filter_map(|scenes| scenes.as_array().cloned())
+            .flatten()
+            .filter_map(|id| id.as_u64())
+            .max()
+            .map_or(FIRST_SCENE_ID, |id| id as u32 + 1);
+
+        scene.insert("9002".into(), unix_time().into());
+        scene.insert("9003".into(), id.into());
+        scene.entry("9057").or_insert_with(|| scenes.len().into());
+        scene.entry("9068").or_insert_with(|| 0.into());
+        self.resources
+            .set_resource(&format!("{}/{}", group_path, id), Value::Object(scene));
+        add_id(&self.resources, &group_path, id);
+
+        (ResponseType::Created, Some(json!({ "9003": id })))
+    }
+
+    /// Adds an entry to the notification list.
+    fn notify(&self, notification: Value) {
+        let mut notifications = match self.resources.resource("15006") {
+            Some(Value::Array(notifications)) => notifications,
+            _ => Vec::new(),
+        };
+        notifications.push(notification);
+        self.resources
+            .set_resource("15006", Value::Array(notifications));
+    }
+
+    /// Removes all paired clients, as a factory reset does. Sessions that
+    /// are already established keep working until they end.
+    fn forget_clients(&self) {
+        self.clients
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .retain(|identity, _| identity == PAIRING_IDENTITY);
+    }
+
+    /// Implements the key exchange of the `15011/9063` resource, registering
+    /// the requested identifier with a new session key.
+    fn pair(&self, payload: &[u8]) -> (ResponseType, Option<Value>) {
+        let identifier = match serde_json::from_slice::<Value>(payload)
+            .ok()
+            .and_then(|request| request.get("9090")?.as_str().map(String::from))
+        {
+            Some(identifier) if !identifier.is_empty() => identifier,
+            _ => return (ResponseType::BadRequest, None),
+        };
+
+        // Like the gateway, refuse to hand out a new key for an identifier
+        // that is already registered.
+        let mut clients = self
+            .clients
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if clients.contains_key(identifier.as_bytes()) {
+            return (ResponseType::BadRequest, None);
+        }
+
+        let session_key = generate_session_key();
+        clients.insert(identifier.into_bytes(), session_key.clone().into_bytes());
+
+        (
+            ResponseType::Created,
+            Some(json!({ "9091": session_key, "9029": FIRMWARE_VERSION })),
+        )
+    }
+
+    /// Applies a group update to the lights of the group, the way the gateway
+    /// switches all lights of a group or activates a scene.
+    fn update_group_lights(&self, group_id: u32, update: &Value) {
+        let lights: Vec<u64> = self
+            .resources
+            .resource(&format!("15004/{}", group_id))
+            .and_then(|group| {
+                group
+                    .pointer("/9018/15002/9003")?
+                    .as_array()
+                    .map(|ids| ids.iter().filter_map(Value::as_u64).collect())
+            })
+            .unwrap_or_default();
+
+        let mut bulb = serde_json::Map::new();
+        for key in ["5850", "5851"] {
+            if let Some(value) = update.get(key) {
+                bulb.insert(key.to_string(), value.clone());
+            }
+        }
+        if !bulb.is_empty() {
+            for light in &lights {
+                self.update_light(*light, Value::Object(bulb.clone()));
+            }
+        }
+
+        // Like the gateway, only applies a scene that comes with switching the
+        // group on.
+        let scene_id = update
+            .get("9039")
+            .and_then(Value::as_u64)
+            .filter(|_| update.get("5850").and_then(Value::as_u64) == Some(1));
+        if let Some(scene_id) = scene_id {
+            let settings = self
+                .resources
+                .resource(&format!("15005/{}/{}", group_id, scene_id))
+                .and_then(|scene| scene.get("15013").cloned());
+            for setting in settings
+                .as_ref()
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                let light = match setting.get("9003").and_then(Value::as_u64) {
+                    Some(light) => light,
+                    None => continue,
+                };
+                let mut bulb = setting.clone();
+                if let Some(bulb) = bulb.as_object_mut() {
+                    bulb.remove("9003");
+                }
+                self.update_light(light, bulb);
+            }
+        }
+    }
+
+    fn update_light(&self, id: u64, bulb: Value) {
+        let path = format!("15001/{}", id);
+        if let Some(mut light) = self.resources.resource(&path) {
+            if let Some(bulbs) = light.get_mut("3311").and_then(Value::as_array_mut) {
+                for existing in bulbs.iter_mut() {
+                    if let (Some(existing), Some(bulb)) =
+                        (existing.as_object_mut(), bulb.as_object())
+                    {
+                        existing.extend(bulb.clone());
+                    }
+                }
+            }
+            self.resources.set_resource(&path, light);
+        }
+    }
+}
+
+fn light_resource(light: &SimulatedLight, now: u64) -> Value {
+    json!({
+        "3": {
+            "0": "IKEA of Sweden",
+            "1": "TRADFRI bulb E27 WS opal 980lm",
+            "2": "",
+            "3": "2.3.093",
+            "6": 1,
+        },
+        "3311": [{
+            "5706": "f5faf6",
+            "5711": 250,
+            "5850": u8::from(light.on),
+            "5851": light.brightness,
+            "9003": 0,
+        }],
+        "5750": 2,
+        "9001": light.name,
+        "9002": now,
+        "9003": light.id,
+        "9019": u8::from(light.reachable),
+        "9020": now,
+        "9054": 0,
+    })
+}
+
+fn group_resource(group: &SimulatedGroup, now: u64) -> Value {
+    json!({
+        "5850": 0,
+        "5851": 0,
+        "9001": group.name,
+        "9002": now,
+        "9003": group.id,
+        "9018": { "15002": { "9003": group.lights } },
+        "9039": 0,
+    })
+}
+
+fn add_scene(resources: &MemoryTransport, scene: &SimulatedScene, now: u64) {
+    let groups_path = "15005";
+    let group_path = format!("15005/{}", scene.group);
+    let index = resources
+        .resource(&group_path)
+        .and_then(|scenes| scenes.as_array().map(Vec::len))
+        .unwrap_or_default();
+
+    add_id(resources, groups_path, scene.group);
+    add_id(resources, &group_path, scene.id);
+    resources.set_resource(
+        &format!("{}/{}", group_path, scene.id),
+        json!({
+            "9001": scene.name,
+            "9002": now,
+            "9003": scene.id,
+            "9057": index,
+            "9068": 0,
+            "15013": scene
+                .lights
+                .iter()
+                .map(|light| json!({
+                    "5850": u8::from(light.on),
+                    "5851": light.brightness,
+                    "9003": light.id,
+                }))
+                .collect::<Vec<_>>(),
+        }),
+    );
+}
+
+/// Lists an id in a collection resource, creating the collection if needed.
+fn add_id(resources: &MemoryTransport, path: &str, id: u32) {
+    let mut ids = resources.resource(path).unwrap_or_else(|| json!([]));
+    if let Some(list) = ids.as_array_mut() {
+        if !list
+            .iter()
+            .any(|existing| existing.as_u64() == Some(id.into()))
+        {
+            list.push(id.into());
+        }
+    }
+    resources.set_resource(path, ids);
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// A random alphanumeric key, like the 16 character keys of the gateway.
+fn generate_session_key() -> String {
+    const CHARACTERS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+    let mut bytes = [0u8; 16];
+    if openssl::rand::rand_bytes(&mut bytes).is_err() {
+        bytes = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+            .to_be_bytes();
+    }
+
+    bytes
+        .iter()
+        .map(|byte| char::from(CHARACTERS[usize::from(*byte) % CHARACTERS.len()]))
+        .collect()
+}
@@ -0,0 +1,254 @@
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use coap::{
+    message::{header::MessageClass, packet::Packet, response::CoAPResponse},
+    CoAPRequest, MessageType,
+};
+use tokio::net::UdpSocket;
+
+use super::{
+    blockwise::BlockwiseTransfer,
+    connection::{initial_message_id, initial_token, TF_PORT},
+    exchange::{Exchange, Received},
+    TradfriConnection, TransmissionParameters,
+};
+use crate::{
+    udp_dtls::{AsyncUdpChannel, DtlsStream, HandshakeError},
+    GatewayAddr,
+};
+
+/// Time to wait for the next handshake flight before letting OpenSSL
+/// retransmit its own, matching the initial DTLS retransmission timer.
+const HANDSHAKE_RETRANSMIT: Duration = Duration::from_secs(1);
+
+/// Non-blocking counterpart of [`TradfriConnection`] for use with tokio.
+///
+/// The socket never blocks. The handshake is resumed whenever OpenSSL stops
+/// with `WouldBlock`, and reads and writes wait for the readiness of the
+/// socket, so a dropped future leaves no thread behind.
+#[derive(Debug)]
+pub struct AsyncTradfriConnection {
+    socket: Arc<UdpSocket>,
+    stream: DtlsStream<AsyncUdpChannel>,
+    parameters: TransmissionParameters,
+    message_id: u16,
+    token: u32,
+}
+
+impl AsyncTradfriConnection {
+    pub async fn new_with_timeout<A: Into<GatewayAddr>>(
+        addr: A,
+        identity: &[u8],
+        key: &[u8],
+        timeout: Option<u64>,
+    ) -> super::Result<Self> {
+        let deadline = timeout.map(|timeout| Instant::now() + Duration::from_secs(timeout));
+
+        Self::handshake(addr.into(), identity, key, deadline).await
+    }
+
+    /// Handshakes with the gateway, giving up on a handshake that has not
+    /// completed by the deadline.
+    async fn handshake(
+        addr: GatewayAddr,
+        identity: &[u8],
+        key: &[u8],
+        deadline: Option<Instant>,
+    ) -> super::Result<Self> {
+        let connector = TradfriConnection::connector(identity, key)?;
+
+        let socket = Arc::new(UdpSocket::bind(addr.bind_addr()).await?);
+        socket.connect(addr.socket_addr(TF_PORT)).await?;
+
+        let channel = AsyncUdpChannel {
+            socket: socket.clone(),
+        };
+
+        let mut result = connector.connect("", channel);
+        let stream = loop {
+            match result {
+                Ok(stream) => break stream,
+                Err(HandshakeError::WouldBlock(mid_handshake)) => {
+                    let wait = match deadline {
+                        Some(deadline) if Instant::now() >= deadline => {
+                            return Err(HandshakeError::WouldBlock(mid_handshake).into())
+                        }
+                        Some(deadline) => HANDSHAKE_RETRANSMIT
+                            .min(deadline.saturating_duration_since(Instant::now())),
+                        None => HANDSHAKE_RETRANSMIT,
+                    };
+                    // Either the next flight arrives, or the DTLS timer runs out
+                    // and the handshake call below retransmits the last flight.
+                    let _ = tokio::time::timeout(wait, socket.readable()).await;
+                    result = mid_handshake.handshake();
+                }
+                Err(error) => return Err(error.into()),
+            }
+        };
+
+        Ok(Self {
+            socket,
+            stream,
+            parameters: TransmissionParameters::default(),
+            message_id: initial_message_id(),
+            token: initial_token(),
+        })
+    }
+
+    pub fn set_transmission_parameters(&mut self, parameters: TransmissionParameters) {
+        self.parameters = parameters;
+    }
+
+    /// Returns a new message id for a request sent on this connection.
+    pub fn next_message_id(&mut self) -> u16 {
+        self.message_id = self.message_id.wrapping_add(1);
+        self.message_id
+    }
+
+    fn next_token(&mut self) -> Vec<u8> {
+        self.token = self.token.wrapping_add(1);
+        self.token.to_be_bytes().to_vec()
+    }
+
+    /// Sends a request and waits for its complete response, transparently
+    /// handling block-wise transfers of large payloads in both directions.
+    ///
+    /// Fails with a timeout error if the response has not arrived within the
+    /// request timeout of the [`TransmissionParameters`].
+    pub async fn request(&mut self, req: CoAPRequest) -> super::Result<CoAPResponse> {
+        let deadline = Instant::now() + self.parameters.request_timeout;
+        self.request_until(req, deadline).await
+    }
+
+    /// Like [`AsyncTradfriConnection::request`], but with an explicit deadline.
+    pub async fn request_until(
+        &mut self,
+        req: CoAPRequest,
+        deadline: Instant,
+    ) -> super::Result<CoAPResponse> {
+        let mut transfer = BlockwiseTransfer::new(req);
+
+        loop {
+            let response = self.exchange(transfer.request().clone(), deadline).await?;
+            if let Some(response) = transfer.handle_response(response)? {
+                return Ok(response);
+            }
+        }
+    }
+
+    /// Sends one confirmable request, retransmitting it until it is
+    /// acknowledged, and waits for the matching response.
+    async fn exchange(
+        &mut self,
+        mut req: CoAPRequest,
+        deadline: Instant,
+    ) -> super::Result<CoAPResponse> {
+        let message_id = self.next_message_id();
+        let token = self.next_token();
+        let mut exchange = Exchange::new(&mut req, message_id, token, &self.parameters, deadline);
+        let bytes = req.message.to_bytes()?;
+        self.write_datagram(&bytes).await?;
+
+        let mut buf = [0u8; super::BUF_SIZE];
+        loop {
+            let wait = exchange.wait_time(Instant::now())?;
+            let len = match tokio::time::timeout(wait, self.read_datagram(&mut buf)).await {
+                Ok(len) => len?,
+                Err(_) => {
+                    if exchange.on_timeout(Instant::now())? {
+                        self.write_datagram(&bytes).await?;
+                    }
+                    continue;
+                }
+            };
+
+            let packet = match Packet::from_bytes(&buf[0..len]) {
+                Ok(packet) => packet,
+                Err(error) => {
+                    log::debug!("Ignoring malformed message: {:?}", error);
+                    continue;
+                }
+            };
+
+            match exchange.handle(packet)? {
+                Received::Response(response, ack) => {
+                    if let Some(message_id) = ack {
+                        self.send_empty(MessageType::Acknowledgement, message_id)?;
+                    }
+                    return Ok(response);
+                }
+                Received::Reject(message_id) => {
+                    self.send_empty(MessageType::Reset, message_id)?;
+                }
+                Received::Ignore => (),
+            }
+        }
+    }
+
+    /// Sends one message right away, failing instead of waiting if the socket
+    /// can not take it, which for a datagram socket is all but unheard of.
+    pub fn send(&mut self, message: &Packet) -> super::Result<usize> {
+        Ok(self.stream.write(&message.to_bytes()?)?)
+    }
+
+    /// Waits for the next message until the timeout runs out, `None` if none
+    /// arrived. Malformed messages are skipped.
+    pub async fn receive_timeout(&mut self, timeout: Duration) -> super::Result<Option<Packet>> {
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0u8; super::BUF_SIZE];
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let len = match tokio::time::timeout(remaining, self.read_datagram(&mut buf)).await {
+                Ok(len) => len?,
+                Err(_) => return Ok(None),
+            };
+
+            match Packet::from_bytes(&buf[0..len]) {
+                Ok(message) => return Ok(Some(message)),
+                Err(error) => log::debug!("Ignoring malformed message: {:?}", error),
+            }
+        }
+    }
+
+    fn send_empty(&mut self, message_type: MessageType, message_id: u16) -> super::Result<()> {
+        let mut packet = Packet::new();
+        packet.header.set_type(message_type);
+        packet.header.code = MessageClass::Empty;
+        packet.header.set_message_id(message_id);
+
+        self.send(&packet)?;
+
+        Ok(())
+    }
+
+    async fn write_datagram(&mut self, bytes: &[u8]) -> io::Result<()> {
+        loop {
+            match self.stream.write(bytes) {
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    self.socket.writable().await?
+                }
+                result => return result.map(|_| ()),
+            }
+        }
+    }
+
+    /// Reads the next datagram once the socket is readable. Nothing is read
+    /// while waiting, so the future can be dropped without losing a message.
+    async fn read_datagram(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.stream.read(buf) {
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    self.socket.readable().await?
+                }
+                // The gateway closed the session with a close notify alert.
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                result => return result,
+            }
+        }
+    }
+}
@@ -1,46 +1,221 @@
 use {
-    super::TradfriConnection,
-    coap::{message::request::Method, CoAPRequest},
-    serde::Deserialize,
-    std::net::IpAddr,
+    super::{Error, TradfriConnection, TransmissionParameters},
+    crate::{pairing::PairingError, GatewayAddr, ResponseCode, Secret},
+    coap::{message::request::Method, CoAPRequest, CoAPResponse},
+    serde::{Deserialize, Serialize},
+    std::time::Duration,
 };
 
+/// Identity the gateway accepts with the security code as key, for pairing.
+const PAIRING_IDENTITY: &[u8] = b"Client_identity";
+
+#[derive(Debug, Serialize)]
+struct AuthRequest<'a> {
+    #[serde(rename = "9090")]
+    identifier: &'a str,
+}
+
 #[derive(Debug, Deserialize)]
 struct AuthResponse {
     #[serde(rename = "9091")]
-    pre_shared_key: String,
+    pre_shared_key: Secret,
 }
 
+/// Registers an identifier on the gateway in one attempt.
 pub struct TradfriAuthenticator;
 
 impl TradfriAuthenticator {
-    pub fn authenticate<A: Into<IpAddr>>(
+    pub fn authenticate<A: Into<GatewayAddr>>(
+        addr: A,
+        identifier: &str,
+        security_code: &Secret,
+        timeout: Duration,
+    ) -> Result<Secret, PairingError> {
+        let result = TradfriConnection::new_with_timeout(
+            addr,
+            PAIRING_IDENTITY,
+            security_code.expose(),
+            Some(Self::seconds(timeout)),
+        )
+        .and_then(|mut con| {
+            con.set_transmission_parameters(Self::parameters(timeout));
+            con.request(Self::request(identifier))
+        });
+
+        Self::pre_shared_key(result, identifier, timeout)
+    }
+
+    /// Non-blocking counterpart of [`TradfriAuthenticator::authenticate`].
+    #[cfg(feature = "async")]
+    pub async fn authenticate_async<A: Into<GatewayAddr>>(
         addr: A,
-        key_name: &str,
-        security_code: &str,
-        timeout: u64,
-    ) -> super::Result<String> {
-        let mut con = TradfriConnection::new_with_timeout(
+        identifier: &str,
+        security_code: &Secret,
+        timeout: Duration,
+    ) -> Result<Secret, PairingError> {
+        let result = match super::AsyncTradfriConnection::new_with_timeout(
             addr,
-            b"Client_identity",
-            security_code.as_bytes(),
-            Some(timeout),
-        )?;
+            PAIRING_IDENTITY,
+            security_code.expose(),
+            Some(Self::seconds(timeout)),
+        )
+        .await
+        {
+            Ok(mut con) => {
+                con.set_transmission_parameters(Self::parameters(timeout));
+                con.request(Self::request(identifier)).await
+            }
+            Err(error) => Err(error),
+        };
+
+        Self::pre_shared_key(result, identifier, timeout)
+    }
 
+    fn seconds(timeout: Duration) -> u64 {
+        timeout.as_secs().max(1)
+    }
+
+    fn parameters(timeout: Duration) -> TransmissionParameters {
+        TransmissionParameters {
+            request_timeout: timeout,
+            ..Default::default()
+        }
+    }
+
+    fn request(identifier: &str) -> CoAPRequest {
         let mut req = CoAPRequest::new();
         req.set_path("15011/9063");
         req.set_method(Method::Post);
-        req.message.set_payload(
-            format!("{{\"9090\": \"{}\"}}", key_name)
-                .as_bytes()
-                .to_owned(),
-        );
+        // Serializing a struct of one string can not fail.
+        req.message
+            .set_payload(serde_json::to_vec(&AuthRequest { identifier }).unwrap_or_default());
+
+        req
+    }
+
+    /// Classifies the outcome of the exchange. The gateway answers a
+    /// registered identifier with 4.00 Bad Request.
+    fn pre_shared_key(
+        result: super::Result<CoAPResponse>,
+        identifier: &str,
+        timeout: Duration,
+    ) -> Result<Secret, PairingError> {
+        let response = match result {
+            Ok(response) => response,
+            Err(Error::PskRejected(_)) => return Err(PairingError::WrongSecurityCode),
+            Err(error) if error.is_timeout() || matches!(error, Error::Unreachable) => {
+                return Err(PairingError::Timeout(timeout))
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let code = ResponseCode::of(&response.message);
+        let payload = &response.message.payload;
+        let unexpected =
+            || PairingError::UnexpectedResponse(code, String::from_utf8_lossy(payload).into());
+
+        match (code.class, code.detail) {
+            (2, _) => serde_json::from_slice::<AuthResponse>(payload)
+                .map(|content| content.pre_shared_key)
+                .map_err(|_| unexpected()),
+            (4, 0) => Err(PairingError::IdentityInUse(identifier.into())),
+            _ => Err(unexpected()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coap::message::{
+        header::{MessageClass, ResponseType},
+        packet::Packet,
+    };
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn response(status: ResponseType, payload: &str) -> crate::tradfri_coap::Result<CoAPResponse> {
+        let mut message = Packet::new();
+        message.header.code = MessageClass::Response(status);
+        message.payload = payload.as_bytes().to_vec();
+
+        Ok(CoAPResponse { message })
+    }
+
+    fn pre_shared_key(
+        result: crate::tradfri_coap::Result<CoAPResponse>,
+    ) -> Result<Secret, PairingError> {
+        TradfriAuthenticator::pre_shared_key(result, "home-assistant", TIMEOUT)
+    }
+
+    #[test]
+    fn reads_key_of_created_identifier() {
+        let key = pre_shared_key(response(
+            ResponseType::Created,
+            r#"{"9091":"k3y","9029":"1.19.32"}"#,
+        ))
+        .unwrap();
 
-        con.send(req)?;
+        assert_eq!(key.expose(), b"k3y");
+    }
+
+    #[test]
+    fn redacts_key_of_response() {
+        let response: AuthResponse =
+            serde_json::from_str(r#"{"9091":"5Gb3dBlRUVy7DDqQ","9029":"1.19.32"}"#).unwrap();
+
+        assert_eq!(response.pre_shared_key.expose(), b"5Gb3dBlRUVy7DDqQ");
+        assert!(!format!("{:?}", response).contains("5Gb3dBlRUVy7DDqQ"));
+    }
+
+    #[test]
+    fn reports_identifier_in_use() {
+        assert!(matches!(
+            pre_shared_key(response(ResponseType::BadRequest, "")),
+            Err(PairingError::IdentityInUse(identifier)) if identifier == "home-assistant"
+        ));
+    }
+
+    #[test]
+    fn reports_unexpected_responses() {
+        assert!(matches!(
+            pre_shared_key(response(ResponseType::Created, r#"{"9029":"1.19.32"}"#)),
+            Err(PairingError::UnexpectedResponse(code, _)) if code.class == 2
+        ));
+        assert!(matches!(
+            pre_shared_key(response(ResponseType::Unauthorized, "denied")),
+            Err(PairingError::UnexpectedResponse(code, payload))
+                if (code.class, code.detail) == (4, 1) && payload == "denied"
+        ));
+    }
+
+    #[test]
+    fn classifies_connection_errors() {
+        assert!(matches!(
+            pre_shared_key(Err(Error::PskRejected(None))),
+            Err(PairingError::WrongSecurityCode)
+        ));
+        assert!(matches!(
+            pre_shared_key(Err(Error::Unreachable)),
+            Err(PairingError::Timeout(TIMEOUT))
+        ));
+        assert!(matches!(
+            pre_shared_key(Err(Error::Timeout)),
+            Err(PairingError::Timeout(TIMEOUT))
+        ));
+        assert!(matches!(
+            pre_shared_key(Err(Error::Rejected)),
+            Err(PairingError::CoapError(_))
+        ));
+    }
 
-        let response = con.receive()?;
-        let content: AuthResponse = serde_json::from_slice(&response.message.payload)?;
+    #[test]
+    fn asks_to_register_identifier() {
+        let req = TradfriAuthenticator::request("home-assistant");
 
-        Ok(content.pre_shared_key)
+        assert_eq!(req.get_method(), &Method::Post);
+        assert_eq!(req.get_path(), "15011/9063");
+        assert_eq!(req.message.payload, br#"{"9090":"home-assistant"}"#);
     }
 }
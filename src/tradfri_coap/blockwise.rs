@@ -0,0 +1,378 @@
+use coap::{
+    message::header::{MessageClass, ResponseType},
+    CoAPOption, CoAPRequest, CoAPResponse, Method,
+};
+
+/// Size exponent used when splitting large request payloads, 2^(6+4) = 1024
+/// bytes per block.
+const REQUEST_BLOCK_SZX: u8 = 6;
+
+/// Decoded value of a Block1 or Block2 option (RFC 7959 section 2.2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BlockOption {
+    pub num: u32,
+    pub more: bool,
+    pub szx: u8,
+}
+
+impl BlockOption {
+    pub fn decode(value: &[u8]) -> Self {
+        let raw = value
+            .iter()
+            .take(3)
+            .fold(0u32, |acc, byte| (acc << 8) | u32::from(*byte));
+
+        Self {
+            num: raw >> 4,
+            more: raw & 0x08 != 0,
+            szx: (raw & 0x07) as u8,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let raw = (self.num << 4) | if self.more { 0x08 } else { 0 } | u32::from(self.szx & 0x07);
+        let bytes = raw.to_be_bytes();
+        let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+
+        bytes[skip..].to_vec()
+    }
+
+    pub fn size(&self) -> usize {
+        1 << (self.szx + 4)
+    }
+
+    fn from_message(message: &coap::message::packet::Packet, option: CoAPOption) -> Option<Self> {
+        message
+            .get_option(option)
+            .and_then(|values| values.front())
+            .map(|value| Self::decode(value))
+    }
+}
+
+/// Drives one request through block-wise transfers, splitting a large request
+/// payload into Block1 requests and collecting a Block2 response payload.
+///
+/// The connection sends [`BlockwiseTransfer::request`] and passes every
+/// response to [`BlockwiseTransfer::handle_response`] until it returns the
+/// complete response.
+pub(crate) struct BlockwiseTransfer {
+    original: CoAPRequest,
+    body: Vec<u8>,
+    request: CoAPRequest,
+    block1: Option<BlockOption>,
+    payload: Vec<u8>,
+    etag: Option<Vec<u8>>,
+}
+
+impl BlockwiseTransfer {
+    pub fn new(req: CoAPRequest) -> Self {
+        let body = req.message.payload.clone();
+        let block1 = BlockOption {
+            num: 0,
+            more: false,
+            szx: REQUEST_BLOCK_SZX,
+        };
+
+        let mut transfer = Self {
+            request: req.clone(),
+            original: req,
+            body,
+            block1: None,
+            payload: Vec::new(),
+            etag: None,
+        };
+        if transfer.body.len() > block1.size() {
+            transfer.prepare_block1(block1);
+        }
+
+        transfer
+    }
+
+    /// The request to send next.
+    pub fn request(&self) -> &CoAPRequest {
+        &self.request
+    }
+
+    /// Handles a response to the current request, returns the complete
+    /// response once the transfer is done.
+    pub fn handle_response(
+        &mut self,
+        mut response: CoAPResponse,
+    ) -> super::Result<Option<CoAPResponse>> {
+        let message = &response.message;
+
+        if let Some(sent) = self.block1 {
+            let continued = message.header.code == MessageClass::Response(ResponseType::Continue);
+            if continued && sent.more {
+                // The gateway may ask for smaller blocks than the ones sent.
+                let szx = BlockOption::from_message(message, CoAPOption::Block1)
+                    .map_or(sent.szx, |acked| acked.szx.min(sent.szx));
+                let offset = (sent.num as usize + 1) * sent.size();
+                let size = 1 << (szx + 4);
+                self.prepare_block1(BlockOption {
+                    num: (offset / size) as u32,
+                    more: false,
+                    szx,
+                });
+                return Ok(None);
+            }
+        }
+
+        let block2 = match BlockOption::from_message(message, CoAPOption::Block2) {
+            Some(block2) => block2,
+            None if self.payload.is_empty() => return Ok(Some(response)),
+            None => {
+                return Err(super::Error::UnexpectedResponse(
+                    "Block-wise response ended without a Block2 option".into(),
+                ))
+            }
+        };
+
+        let etag = message
+            .get_option(CoAPOption::ETag)
+            .and_then(|values| values.front())
+            .cloned();
+        if block2.num == 0 {
+            self.etag = etag;
+        } else if etag != self.etag {
+            return Err(super::Error::UnexpectedResponse(
+                "Resource changed during block-wise transfer".into(),
+            ));
+        }
+
+        let offset = block2.num as usize * block2.size();
+        if offset != self.payload.len() {
+            return Err(super::Error::UnexpectedResponse(format!(
+                "Unexpected block {} in block-wise transfer",
+                block2.num
+            )));
+        }
+        self.payload.extend_from_slice(&message.payload);
+
+        if !block2.more {
+            response.message.payload = std::mem::take(&mut self.payload);
+            response.message.clear_option(CoAPOption::Block2);
+            return Ok(Some(response));
+        }
+
+        // Later blocks are fetched with GET, the request body has already
+        // been delivered.
+        let mut request = self.original.clone();
+        request.set_method(Method::Get);
+        request.message.payload = Vec::new();
+        request.message.add_option(
+            CoAPOption::Block2,
+            BlockOption {
+                num: block2.num + 1,
+                more: false,
+                szx: block2.szx,
+            }
+            .encode(),
+        );
+        self.block1 = None;
+        self.request = request;
+
+        Ok(None)
+    }
+
+    fn prepare_block1(&mut self, mut block1: BlockOption) {
+        let start = (block1.num as usize * block1.size()).min(self.body.len());
+        let end = (start + block1.size()).min(self.body.len());
+        block1.more = end < self.body.len();
+
+        let mut request = self.original.clone();
+        request.message.payload = self.body[start..end].to_vec();
+        request
+            .message
+            .add_option(CoAPOption::Block1, block1.encode());
+
+        self.block1 = Some(block1);
+        self.request = request;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use coap::{message::packet::Packet, MessageType};
+
+    use super::{super::Error, *};
+
+    fn response(code: ResponseType, payload: &[u8]) -> CoAPResponse {
+        let mut message = Packet::new();
+        message.header.set_type(MessageType::Acknowledgement);
+        message.header.code = MessageClass::Response(code);
+        message.payload = payload.to_vec();
+
+        CoAPResponse { message }
+    }
+
+    fn with_option(
+        mut response: CoAPResponse,
+        option: CoAPOption,
+        block: BlockOption,
+    ) -> CoAPResponse {
+        response.message.add_option(option, block.encode());
+        response
+    }
+
+    fn block(num: u32, more: bool, szx: u8) -> BlockOption {
+        BlockOption { num, more, szx }
+    }
+
+    fn sent_block(transfer: &BlockwiseTransfer, option: CoAPOption) -> Option<BlockOption> {
+        BlockOption::from_message(&transfer.request().message, option)
+    }
+
+    fn get(path: &str) -> CoAPRequest {
+        let mut req = CoAPRequest::new();
+        req.set_method(Method::Get);
+        req.set_path(path);
+        req
+    }
+
+    #[test]
+    fn encodes_block_options_in_as_few_bytes_as_possible() {
+        assert_eq!(block(0, false, 0).encode(), Vec::<u8>::new());
+        assert_eq!(block(0, true, 6).encode(), [0x0e]);
+        assert_eq!(block(1, false, 2).encode(), [0x12]);
+        assert_eq!(block(16, true, 6).encode(), [0x01, 0x0e]);
+        assert_eq!(block(4096, false, 6).encode(), [0x01, 0x00, 0x06]);
+    }
+
+    #[test]
+    fn decodes_encoded_block_options() {
+        for option in [
+            block(0, false, 0),
+            block(0, true, 6),
+            block(15, true, 2),
+            block(300, false, 6),
+            block((1 << 20) - 1, true, 6),
+        ] {
+            assert_eq!(BlockOption::decode(&option.encode()), option);
+        }
+        assert_eq!(BlockOption::decode(&[]), block(0, false, 0));
+        assert_eq!(block(0, false, 2).size(), 64);
+        assert_eq!(block(0, false, 6).size(), 1024);
+    }
+
+    #[test]
+    fn passes_through_responses_without_block2() {
+        let mut transfer = BlockwiseTransfer::new(get("15001"));
+
+        let done = transfer
+            .handle_response(response(ResponseType::Content, b"[65537]"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(done.message.payload, b"[65537]");
+    }
+
+    #[test]
+    fn reassembles_block2_responses() {
+        let mut transfer = BlockwiseTransfer::new(get("15001/65537"));
+        let first = with_option(
+            response(ResponseType::Content, &[b'a'; 16]),
+            CoAPOption::Block2,
+            block(0, true, 0),
+        );
+
+        assert!(transfer.handle_response(first).unwrap().is_none());
+        assert_eq!(
+            sent_block(&transfer, CoAPOption::Block2),
+            Some(block(1, false, 0))
+        );
+        assert_eq!(transfer.request().get_path(), "15001/65537");
+
+        let last = with_option(
+            response(ResponseType::Content, b"bc"),
+            CoAPOption::Block2,
+            block(1, false, 0),
+        );
+        let done = transfer.handle_response(last).unwrap().unwrap();
+
+        let mut expected = vec![b'a'; 16];
+        expected.extend_from_slice(b"bc");
+        assert_eq!(done.message.payload, expected);
+        assert_eq!(
+            BlockOption::from_message(&done.message, CoAPOption::Block2),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_order_blocks() {
+        let mut transfer = BlockwiseTransfer::new(get("15001/65537"));
+        let first = with_option(
+            response(ResponseType::Content, &[0; 16]),
+            CoAPOption::Block2,
+            block(0, true, 0),
+        );
+        transfer.handle_response(first).unwrap();
+
+        let skipped = with_option(
+            response(ResponseType::Content, &[0; 16]),
+            CoAPOption::Block2,
+            block(2, true, 0),
+        );
+        assert!(matches!(
+            transfer.handle_response(skipped),
+            Err(Error::UnexpectedResponse(_))
+        ));
+    }
+
+    #[test]
+    fn splits_large_request_payloads_and_follows_smaller_block_size() {
+        let mut req = CoAPRequest::new();
+        req.set_method(Method::Put);
+        req.set_path("15005/131073");
+        req.message.payload = vec![b'x'; 2500];
+        let mut transfer = BlockwiseTransfer::new(req);
+
+        assert_eq!(
+            sent_block(&transfer, CoAPOption::Block1),
+            Some(block(0, true, REQUEST_BLOCK_SZX))
+        );
+        assert_eq!(transfer.request().message.payload.len(), 1024);
+
+        // The gateway asks for 512 byte blocks, the next one starts where the
+        // first 1024 bytes ended.
+        let continued = with_option(
+            response(ResponseType::Continue, &[]),
+            CoAPOption::Block1,
+            block(0, true, 5),
+        );
+        assert!(transfer.handle_response(continued).unwrap().is_none());
+        assert_eq!(
+            sent_block(&transfer, CoAPOption::Block1),
+            Some(block(2, true, 5))
+        );
+        assert_eq!(transfer.request().message.payload.len(), 512);
+
+        let continued = with_option(
+            response(ResponseType::Continue, &[]),
+            CoAPOption::Block1,
+            block(2, true, 5),
+        );
+        transfer.handle_response(continued).unwrap();
+        let continued = with_option(
+            response(ResponseType::Continue, &[]),
+            CoAPOption::Block1,
+            block(3, true, 5),
+        );
+        transfer.handle_response(continued).unwrap();
+        assert_eq!(
+            sent_block(&transfer, CoAPOption::Block1),
+            Some(block(4, false, 5))
+        );
+        assert_eq!(transfer.request().message.payload.len(), 2500 - 4 * 512);
+
+        let done = transfer
+            .handle_response(response(ResponseType::Changed, &[]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            done.message.header.code,
+            MessageClass::Response(ResponseType::Changed)
+        );
+    }
+}
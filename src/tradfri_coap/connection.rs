@@ -1,77 +1,289 @@
 use std::sync::{Arc, Mutex};
 
 use {
-    crate::udp_dtls::{ConnectorIdentity, DtlsConnector, DtlsStream, PskIdentity, UdpChannel},
+    super::{
+        blockwise::BlockwiseTransfer,
+        exchange::{Exchange, Received, TransmissionParameters},
+    },
+    crate::{
+        udp_dtls::{ConnectorIdentity, DtlsConnector, DtlsStream, PskIdentity, UdpChannel},
+        GatewayAddr,
+    },
     coap::{
-        message::{packet::Packet, response::CoAPResponse},
+        message::{
+            header::{MessageClass, MessageType},
+            packet::Packet,
+            response::CoAPResponse,
+        },
         CoAPRequest,
     },
     std::{
         io::{self, Read, Write},
-        net::{IpAddr, SocketAddr, UdpSocket},
-        time::Duration,
+        net::UdpSocket,
+        time::{Duration, Instant},
     },
 };
 
-const TF_PORT: u16 = 5684;
+pub(crate) const TF_PORT: u16 = 5684;
+
+/// Cipher suite of the gateway.
+pub(crate) const GATEWAY_CIPHER: &str = "PSK-AES128-CCM8:@SECLEVEL=0";
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct TradfriConnection {
     stream: Arc<Mutex<DtlsStream<UdpChannel>>>,
+    read_timeout: Option<Duration>,
+    parameters: TransmissionParameters,
+    message_id: u16,
+    token: u32,
 }
 
 impl TradfriConnection {
-    pub fn new<A: Into<IpAddr>>(addr: A, identity: &[u8], key: &[u8]) -> super::Result<Self> {
-        Self::new_with_timeout(addr, identity, key, None)
-    }
-
-    pub fn new_with_timeout<A: Into<IpAddr>>(
+    pub fn new_with_timeout<A: Into<GatewayAddr>>(
         addr: A,
         identity: &[u8],
         key: &[u8],
         timeout: Option<u64>,
     ) -> super::Result<Self> {
-        let connector = DtlsConnector::builder()
-            .danger_accept_invalid_certs(true)
-            .danger_accept_invalid_hostnames(true)
-            .use_sni(false)
-            .add_cipher("PSK-AES128-CCM8")
-            .identity(ConnectorIdentity::Psk(PskIdentity::new(identity, key)))
-            .min_protocol_version(Some(crate::udp_dtls::Protocol::Dtlsv12))
-            .max_protocol_version(Some(crate::udp_dtls::Protocol::Dtlsv12))
-            .build()?;
+        let connector = Self::connector(identity, key)?;
 
         let addr = addr.into();
-        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
-        socket.set_nonblocking(false).unwrap();
-        if let Some(timeout) = timeout {
-            socket.set_read_timeout(Some(Duration::from_secs(timeout)))?;
-            socket.set_write_timeout(Some(Duration::from_secs(timeout)))?;
-        }
+        let read_timeout = timeout.map(Duration::from_secs);
+        let socket = UdpSocket::bind(addr.bind_addr())?;
+        socket.set_nonblocking(false)?;
+        socket.set_read_timeout(read_timeout)?;
+        socket.set_write_timeout(read_timeout)?;
 
         let client_channel = UdpChannel {
             socket,
-            remote_addr: SocketAddr::new(addr, TF_PORT),
+            remote_addr: addr.socket_addr(TF_PORT),
         };
 
         Ok(Self {
             stream: Arc::new(Mutex::new(connector.connect("", client_channel)?)),
+            read_timeout,
+            parameters: TransmissionParameters::default(),
+            message_id: initial_message_id(),
+            token: initial_token(),
         })
     }
 
-    pub fn send(&mut self, req: CoAPRequest) -> super::Result<usize> {
-        Ok(self.write(&req.message.to_bytes()?)?)
+    /// Creates a connector for the PSK cipher suite used by the gateway.
+    ///
+    /// OpenSSL 3 only enables the suite, with its short 8 byte tag, at security
+    /// level 0.
+    pub(crate) fn connector(identity: &[u8], key: &[u8]) -> super::Result<DtlsConnector> {
+        Ok(DtlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .use_sni(false)
+            .add_cipher(GATEWAY_CIPHER)
+            .identity(ConnectorIdentity::Psk(PskIdentity::new(identity, key)))
+            .min_protocol_version(Some(crate::udp_dtls::Protocol::Dtlsv12))
+            .max_protocol_version(Some(crate::udp_dtls::Protocol::Dtlsv12))
+            .build()?)
     }
 
-    pub fn receive(&mut self) -> super::Result<CoAPResponse> {
+    pub fn set_transmission_parameters(&mut self, parameters: TransmissionParameters) {
+        self.parameters = parameters;
+    }
+
+    /// Returns a new message id for a request sent on this connection.
+    pub fn next_message_id(&mut self) -> u16 {
+        self.message_id = self.message_id.wrapping_add(1);
+        self.message_id
+    }
+
+    fn next_token(&mut self) -> Vec<u8> {
+        self.token = self.token.wrapping_add(1);
+        self.token.to_be_bytes().to_vec()
+    }
+
+    /// Sends a request and waits for its complete response, transparently
+    /// handling block-wise transfers of large payloads in both directions.
+    ///
+    /// Fails with a timeout error if the response has not arrived within the
+    /// request timeout of the [`TransmissionParameters`].
+    pub fn request(&mut self, req: CoAPRequest) -> super::Result<CoAPResponse> {
+        let deadline = Instant::now() + self.parameters.request_timeout;
+        self.request_until(req, deadline)
+    }
+
+    /// Like [`TradfriConnection::request`], but with an explicit deadline.
+    pub fn request_until(
+        &mut self,
+        req: CoAPRequest,
+        deadline: Instant,
+    ) -> super::Result<CoAPResponse> {
+        let mut transfer = BlockwiseTransfer::new(req);
+
+        loop {
+            let response = self.exchange(transfer.request().clone(), deadline)?;
+            if let Some(response) = transfer.handle_response(response)? {
+                return Ok(response);
+            }
+        }
+    }
+
+    /// Sends one confirmable request, retransmitting it until it is
+    /// acknowledged, and waits for the matching response.
+    fn exchange(&mut self, mut req: CoAPRequest, deadline: Instant) -> super::Result<CoAPResponse> {
+        let message_id = self.next_message_id();
+        let token = self.next_token();
+        let mut exchange = Exchange::new(&mut req, message_id, token, &self.parameters, deadline);
+        let bytes = req.message.to_bytes()?;
+        self.write_all(&bytes)?;
+
+        let result = self.await_response(&mut exchange, &bytes);
+        self.set_read_timeout(self.read_timeout)?;
+
+        result
+    }
+
+    fn await_response(
+        &mut self,
+        exchange: &mut Exchange,
+        bytes: &[u8],
+    ) -> super::Result<CoAPResponse> {
         let mut buf = [0u8; super::BUF_SIZE];
-        let len = self.read(&mut buf)?;
-        let packet = Packet::from_bytes(&buf[0..len])?;
 
-        Ok(CoAPResponse { message: packet })
+        loop {
+            self.set_read_timeout(Some(exchange.wait_time(Instant::now())?))?;
+
+            let len = match self.read_datagram(&mut buf) {
+                Ok(len) => len,
+                Err(error)
+                    if error.kind() == io::ErrorKind::WouldBlock
+                        || error.kind() == io::ErrorKind::TimedOut =>
+                {
+                    if exchange.on_timeout(Instant::now())? {
+                        self.write_all(bytes)?;
+                    }
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+
+            let packet = match Packet::from_bytes(&buf[0..len]) {
+                Ok(packet) => packet,
+                Err(error) => {
+                    log::debug!("Ignoring malformed message: {:?}", error);
+                    continue;
+                }
+            };
+
+            match exchange.handle(packet)? {
+                Received::Response(response, ack) => {
+                    if let Some(message_id) = ack {
+                        self.acknowledge(message_id)?;
+                    }
+                    return Ok(response);
+                }
+                Received::Reject(message_id) => {
+                    self.reset(message_id)?;
+                }
+                Received::Ignore => (),
+            }
+        }
+    }
+
+    /// Reads the next datagram, failing once the session has ended.
+    fn read_datagram(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.read(buf) {
+            // The gateway closed the session with a close notify alert.
+            Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+            result => result,
+        }
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> super::Result<()> {
+        let stream = self.stream.lock().unwrap();
+        stream.get_ref().socket.set_read_timeout(timeout)?;
+
+        Ok(())
+    }
+
+    pub fn send(&mut self, message: &Packet) -> super::Result<usize> {
+        Ok(self.write(&message.to_bytes()?)?)
+    }
+
+    /// Waits for the next message until the timeout runs out, `None` if none
+    /// arrived. Malformed messages are skipped.
+    pub fn receive_timeout(&mut self, timeout: Duration) -> super::Result<Option<CoAPResponse>> {
+        let deadline = Instant::now() + timeout;
+        let mut buf = [0u8; super::BUF_SIZE];
+
+        let result = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break Ok(None);
+            }
+            self.set_read_timeout(Some(remaining))?;
+
+            let len = match self.read_datagram(&mut buf) {
+                Ok(len) => len,
+                Err(error)
+                    if error.kind() == io::ErrorKind::WouldBlock
+                        || error.kind() == io::ErrorKind::TimedOut =>
+                {
+                    break Ok(None)
+                }
+                Err(error) => break Err(error.into()),
+            };
+
+            match Packet::from_bytes(&buf[0..len]) {
+                Ok(message) => break Ok(Some(CoAPResponse { message })),
+                Err(error) => log::debug!("Ignoring malformed message: {:?}", error),
+            }
+        };
+        self.set_read_timeout(self.read_timeout)?;
+
+        result
+    }
+
+    /// Sends an empty acknowledgement for a confirmable message, such as an
+    /// observe notification sent by the gateway.
+    pub fn acknowledge(&mut self, message_id: u16) -> super::Result<usize> {
+        self.send_empty(MessageType::Acknowledgement, message_id)
+    }
+
+    /// Rejects a message that can not be processed, which also tells the
+    /// gateway to stop sending notifications for an unknown observation.
+    pub fn reset(&mut self, message_id: u16) -> super::Result<usize> {
+        self.send_empty(MessageType::Reset, message_id)
+    }
+
+    fn send_empty(&mut self, message_type: MessageType, message_id: u16) -> super::Result<usize> {
+        let mut packet = Packet::new();
+        packet.header.set_type(message_type);
+        packet.header.code = MessageClass::Empty;
+        packet.header.set_message_id(message_id);
+
+        Ok(self.write(&packet.to_bytes()?)?)
     }
 }
 
+/// Picks a random starting point for message ids, so that a new session does
+/// not reuse the ids of a previous one (RFC 7252 section 4.4).
+pub(crate) fn initial_message_id() -> u16 {
+    let mut bytes = [0u8; 2];
+    if openssl::rand::rand_bytes(&mut bytes).is_err() {
+        return 0;
+    }
+
+    u16::from_be_bytes(bytes)
+}
+
+/// Picks a random starting point for request tokens.
+pub(crate) fn initial_token() -> u32 {
+    let mut bytes = [0u8; 4];
+    if openssl::rand::rand_bytes(&mut bytes).is_err() {
+        return 0;
+    }
+
+    u32::from_be_bytes(bytes)
+}
+
 impl Read for TradfriConnection {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.stream.lock().unwrap().read(buf)
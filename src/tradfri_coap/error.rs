@@ -1,40 +1,208 @@
-use crate::impl_from;
+use std::{fmt, io};
 
-#[derive(Debug)]
-pub struct Error {
-    cause: String,
+use coap::message::packet::{PackageError, ParseError};
+use openssl::ssl::SslRef;
+
+use crate::udp_dtls::{self, HandshakeError};
+
+/// OpenSSL reasons for the alerts a DTLS server sends when it does not accept
+/// the identity or the pre-shared key of the client.
+const REJECTED_PSK_ALERTS: [i32; 4] = [
+    1020, // bad record mac
+    1040, // handshake failure
+    1051, // decrypt error
+    1115, // unknown psk identity
+];
+
+/// Error of the connection to the gateway.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The gateway did not accept the identity or the pre-shared key, either by
+    /// failing the handshake with an alert, or by not answering once the key
+    /// had been used.
+    #[error("The gateway rejected the identity or pre-shared key")]
+    PskRejected(#[source] Option<udp_dtls::Error>),
+
+    /// Nothing answered the handshake within the timeout.
+    #[error("The gateway did not answer the handshake")]
+    Unreachable,
+
+    /// The gateway did not answer a request within the request deadline.
+    #[error("Request timed out")]
+    Timeout,
+
+    /// The handshake failed for another reason than the credentials.
+    #[error("DTLS handshake failed: {0}")]
+    Handshake(#[source] udp_dtls::Error),
+
+    /// The DTLS session could not be set up or broke down.
+    #[error("DTLS error: {0}")]
+    Dtls(#[from] udp_dtls::Error),
+
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+
+    /// The gateway answered a request with a reset.
+    #[error("Request rejected by the gateway")]
+    Rejected,
+
+    /// The gateway answered in a way that does not fit the exchange, such as
+    /// a block-wise transfer that skipped blocks.
+    #[error("Unexpected response: {0}")]
+    UnexpectedResponse(String),
+
+    #[error("Malformed CoAP message: {0}")]
+    Parse(ParseError),
+
+    #[error("CoAP message could not be encoded: {0}")]
+    Encode(PackageError),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
 }
 
 impl Error {
-    pub fn new<C: Into<String>>(cause: C) -> Self {
-        Self {
-            cause: cause.into(),
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Error::Timeout)
+    }
+
+    /// Returns `true` for errors that may go away by trying again, such as
+    /// timeouts and network errors, and `false` for errors that will repeat,
+    /// such as rejected credentials.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Unreachable
+            | Error::Timeout
+            | Error::Io(_)
+            | Error::Rejected
+            | Error::UnexpectedResponse(_) => true,
+            Error::PskRejected(_)
+            | Error::Handshake(_)
+            | Error::Dtls(_)
+            | Error::Parse(_)
+            | Error::Encode(_)
+            | Error::Json(_) => false,
+        }
+    }
+
+    /// Classifies a handshake that stopped waiting for the gateway.
+    ///
+    /// Once the client has sent its `Finished` message the gateway has seen
+    /// the identity and needs the key to answer. A gateway with another key
+    /// can not read the message and drops it, so the handshake stalls there.
+    fn stalled_handshake(ssl: &SslRef) -> Self {
+        Self::stalled_in(ssl.state_string_long())
+    }
+
+    /// Classifies a handshake stalled in the OpenSSL state with the given
+    /// description.
+    fn stalled_in(state: &str) -> Self {
+        if state.contains("finished") {
+            Error::PskRejected(None)
+        } else {
+            Error::Unreachable
+        }
+    }
+
+    fn failed_handshake(error: udp_dtls::Error) -> Self {
+        let rejected = match &error {
+            udp_dtls::Error::Ssl(error, _) => error.ssl_error().is_some_and(|stack| {
+                rejects_psk(stack.errors().iter().map(|error| error.reason_code()))
+            }),
+            _ => false,
+        };
+
+        if rejected {
+            Error::PskRejected(Some(error))
+        } else {
+            Error::Handshake(error)
         }
     }
 }
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.cause)
+/// Whether any of the OpenSSL error reasons is an alert rejecting the PSK.
+fn rejects_psk(reasons: impl IntoIterator<Item = i32>) -> bool {
+    reasons
+        .into_iter()
+        .any(|reason| REJECTED_PSK_ALERTS.contains(&reason))
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::Parse(error)
     }
 }
 
-impl std::error::Error for Error {
-    fn description(&self) -> &str {
-        &self.cause
+impl From<PackageError> for Error {
+    fn from(error: PackageError) -> Self {
+        Error::Encode(error)
     }
 }
 
-impl_from!(crate::udp_dtls::Error);
-impl_from!(coap::message::packet::PackageError);
-impl_from!(coap::message::packet::ParseError);
-impl_from!(std::io::Error);
-impl_from!(serde_json::Error);
+impl<S: fmt::Debug> From<HandshakeError<S>> for Error {
+    fn from(error: HandshakeError<S>) -> Self {
+        match error {
+            HandshakeError::Failure(error) => Self::failed_handshake(error),
+            HandshakeError::WouldBlock(stream) => Self::stalled_handshake(stream.as_ref().ssl()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::{
+        error::ErrorStack,
+        ssl::{Ssl, SslContext, SslMethod},
+    };
+
+    use super::*;
+
+    #[test]
+    fn recognizes_psk_rejection_alerts() {
+        for reason in REJECTED_PSK_ALERTS {
+            assert!(rejects_psk([reason]), "{}", reason);
+        }
+        assert!(rejects_psk([1042, 1115]));
+
+        // Unexpected message, bad certificate and no shared cipher.
+        assert!(!rejects_psk([1010, 1042, 193]));
+        assert!(!rejects_psk([]));
+    }
+
+    #[test]
+    fn rejects_psk_when_stalled_after_finished() {
+        let error = Error::stalled_in("SSLv3/TLS write finished");
 
-impl From<crate::udp_dtls::HandshakeError<crate::udp_dtls::UdpChannel>> for Error {
-    fn from(err: crate::udp_dtls::HandshakeError<crate::udp_dtls::UdpChannel>) -> Self {
-        Self {
-            cause: format!("{:?}", err),
+        assert!(matches!(error, Error::PskRejected(None)));
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn is_unreachable_when_stalled_before_finished() {
+        for state in [
+            "before SSL initialization",
+            "SSLv3/TLS write client hello",
+            "DTLS1 read hello verify request",
+        ] {
+            let error = Error::stalled_in(state);
+            assert!(matches!(error, Error::Unreachable), "{}", state);
+            assert!(error.is_retryable());
         }
     }
+
+    #[test]
+    fn is_unreachable_when_handshake_never_started() {
+        let context = SslContext::builder(SslMethod::dtls()).unwrap().build();
+        let ssl = Ssl::new(&context).unwrap();
+
+        assert!(matches!(Error::stalled_handshake(&ssl), Error::Unreachable));
+    }
+
+    #[test]
+    fn keeps_other_handshake_failures() {
+        let error = Error::failed_handshake(udp_dtls::Error::Normal(ErrorStack::get()));
+
+        assert!(matches!(error, Error::Handshake(_)));
+        assert!(!error.is_retryable());
+    }
 }
@@ -0,0 +1,409 @@
+use std::time::{Duration, Instant};
+
+use coap::{
+    message::{header::MessageClass, packet::Packet},
+    CoAPRequest, CoAPResponse, MessageType, Method,
+};
+
+/// Transmission parameters for confirmable requests (RFC 7252 section 4.8).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransmissionParameters {
+    /// Initial time to wait for an acknowledgement before retransmitting.
+    pub ack_timeout: Duration,
+    /// Random factor the initial acknowledgement timeout is scaled with, to
+    /// avoid retransmissions from several clients in lockstep.
+    pub ack_random_factor: f32,
+    /// Number of retransmissions before giving up on an unacknowledged request.
+    pub max_retransmit: u32,
+    /// Overall time allowed for a request, including retransmissions and
+    /// waiting for a separate response.
+    pub request_timeout: Duration,
+}
+
+impl Default for TransmissionParameters {
+    fn default() -> Self {
+        Self {
+            ack_timeout: Duration::from_secs(2),
+            ack_random_factor: 1.5,
+            max_retransmit: 4,
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// What the connection should do with a received message.
+pub(crate) enum Received {
+    /// The response to the request, acknowledge it first if a message id is
+    /// given.
+    Response(CoAPResponse, Option<u16>),
+    /// An unrelated confirmable message that should be rejected with a reset.
+    Reject(u16),
+    /// Nothing to do, keep waiting.
+    Ignore,
+}
+
+/// State of one confirmable request, matching incoming messages on message id
+/// and token and deciding when to retransmit.
+pub(crate) struct Exchange {
+    message_id: u16,
+    token: Vec<u8>,
+    deadline: Instant,
+    retransmit_at: Instant,
+    interval: Duration,
+    retransmissions: u32,
+    max_retransmit: u32,
+    acknowledged: bool,
+}
+
+impl Exchange {
+    /// Marks the request as confirmable with the given message id and token
+    /// and starts the retransmission timer.
+    pub fn new(
+        req: &mut CoAPRequest,
+        message_id: u16,
+        token: Vec<u8>,
+        parameters: &TransmissionParameters,
+        deadline: Instant,
+    ) -> Self {
+        req.message.header.set_type(MessageType::Confirmable);
+        req.message.header.set_message_id(message_id);
+        req.message.set_token(token.clone());
+
+        let interval = parameters
+            .ack_timeout
+            .mul_f32(1.0 + (parameters.ack_random_factor - 1.0).max(0.0) * random_fraction());
+
+        Self {
+            message_id,
+            token,
+            deadline,
+            retransmit_at: Instant::now() + interval,
+            interval,
+            retransmissions: 0,
+            max_retransmit: parameters.max_retransmit,
+            acknowledged: false,
+        }
+    }
+
+    /// Time to wait for the next message before [`Exchange::on_timeout`] has
+    /// to be called, fails once the deadline has passed.
+    pub fn wait_time(&self, now: Instant) -> super::Result<Duration> {
+        if now >= self.deadline {
+            return Err(super::Error::Timeout);
+        }
+
+        let until = if self.acknowledged {
+            self.deadline
+        } else {
+            self.retransmit_at.min(self.deadline)
+        };
+
+        Ok(until
+            .saturating_duration_since(now)
+            .max(Duration::from_millis(1)))
+    }
+
+    /// Called when waiting timed out, returns `true` if the request should be
+    /// sent again.
+    pub fn on_timeout(&mut self, now: Instant) -> super::Result<bool> {
+        if now >= self.deadline {
+            return Err(super::Error::Timeout);
+        }
+        if self.acknowledged || now < self.retransmit_at {
+            return Ok(false);
+        }
+        if self.retransmissions >= self.max_retransmit {
+            return Err(super::Error::Timeout);
+        }
+
+        self.retransmissions += 1;
+        self.interval *= 2;
+        self.retransmit_at = now + self.interval;
+
+        Ok(true)
+    }
+
+    pub fn handle(&mut self, message: Packet) -> super::Result<Received> {
+        let message_type = message.header.get_type();
+        let message_id = message.header.get_message_id();
+        let own_message = message_id == self.message_id;
+
+        match message_type {
+            MessageType::Reset if own_message => Err(super::Error::Rejected),
+            MessageType::Acknowledgement if own_message => {
+                self.acknowledged = true;
+                if message.header.code == MessageClass::Empty {
+                    // The response will follow in a separate message.
+                    Ok(Received::Ignore)
+                } else if message.get_token() == &self.token {
+                    Ok(Received::Response(CoAPResponse { message }, None))
+                } else {
+                    Err(super::Error::UnexpectedResponse(
+                        "Response with unexpected token".into(),
+                    ))
+                }
+            }
+            MessageType::Confirmable | MessageType::NonConfirmable
+                if message.get_token() == &self.token
+                    && message.header.code != MessageClass::Empty =>
+            {
+                self.acknowledged = true;
+                let ack = (message_type == MessageType::Confirmable).then_some(message_id);
+                Ok(Received::Response(CoAPResponse { message }, ack))
+            }
+            MessageType::Confirmable => Ok(Received::Reject(message_id)),
+            _ => Ok(Received::Ignore),
+        }
+    }
+}
+
+/// Whether a request may be sent again on a new session after the previous
+/// attempt failed. The gateway may already have acted on a request whose
+/// response got lost, which is harmless for reads, updates and deletes, but
+/// would for example create a second scene or reboot the gateway twice for a
+/// POST.
+pub(crate) fn is_idempotent(req: &CoAPRequest) -> bool {
+    matches!(req.get_method(), Method::Get | Method::Put | Method::Delete)
+}
+
+/// Random value in the range `0.0..1.0`.
+fn random_fraction() -> f32 {
+    let mut bytes = [0u8; 2];
+    if openssl::rand::rand_bytes(&mut bytes).is_err() {
+        return 0.5;
+    }
+
+    f32::from(u16::from_be_bytes(bytes)) / f32::from(u16::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use coap::message::header::ResponseType;
+
+    use super::{super::Error, *};
+
+    const MESSAGE_ID: u16 = 0x1234;
+    const TOKEN: [u8; 4] = [1, 2, 3, 4];
+
+    /// Parameters without random scaling, so the retransmission times are
+    /// exact.
+    fn parameters() -> TransmissionParameters {
+        TransmissionParameters {
+            ack_timeout: Duration::from_secs(2),
+            ack_random_factor: 1.0,
+            max_retransmit: 2,
+            request_timeout: Duration::from_secs(30),
+        }
+    }
+
+    fn exchange(deadline: Duration) -> Exchange {
+        let mut req = CoAPRequest::new();
+        req.set_method(Method::Get);
+        req.set_path("15001");
+
+        Exchange::new(
+            &mut req,
+            MESSAGE_ID,
+            TOKEN.to_vec(),
+            &parameters(),
+            Instant::now() + deadline,
+        )
+    }
+
+    fn message(message_type: MessageType, message_id: u16, token: &[u8]) -> Packet {
+        let mut message = Packet::new();
+        message.header.set_type(message_type);
+        message.header.set_message_id(message_id);
+        message.header.code = MessageClass::Response(ResponseType::Content);
+        message.set_token(token.to_vec());
+        message
+    }
+
+    fn empty(message_type: MessageType, message_id: u16) -> Packet {
+        let mut message = Packet::new();
+        message.header.set_type(message_type);
+        message.header.set_message_id(message_id);
+        message.header.code = MessageClass::Empty;
+        message
+    }
+
+    #[test]
+    fn marks_request_as_confirmable() {
+        let mut req = CoAPRequest::new();
+        Exchange::new(
+            &mut req,
+            MESSAGE_ID,
+            TOKEN.to_vec(),
+            &parameters(),
+            Instant::now() + Duration::from_secs(30),
+        );
+
+        assert_eq!(req.message.header.get_type(), MessageType::Confirmable);
+        assert_eq!(req.message.header.get_message_id(), MESSAGE_ID);
+        assert_eq!(req.message.get_token(), &TOKEN.to_vec());
+    }
+
+    #[test]
+    fn scales_initial_interval_by_random_factor() {
+        let parameters = TransmissionParameters::default();
+        let mut req = CoAPRequest::new();
+
+        for _ in 0..20 {
+            let exchange = Exchange::new(
+                &mut req,
+                MESSAGE_ID,
+                TOKEN.to_vec(),
+                &parameters,
+                Instant::now() + parameters.request_timeout,
+            );
+            assert!(exchange.interval >= parameters.ack_timeout);
+            assert!(exchange.interval <= parameters.ack_timeout.mul_f32(1.5));
+        }
+    }
+
+    #[test]
+    fn doubles_interval_until_retransmissions_run_out() {
+        let mut exchange = exchange(Duration::from_secs(30));
+        let first = exchange.retransmit_at;
+
+        assert!(!exchange
+            .on_timeout(first - Duration::from_millis(1))
+            .unwrap());
+        assert!(exchange.on_timeout(first).unwrap());
+        assert_eq!(exchange.retransmit_at, first + Duration::from_secs(4));
+
+        let second = exchange.retransmit_at;
+        assert!(exchange.on_timeout(second).unwrap());
+        assert_eq!(exchange.retransmit_at, second + Duration::from_secs(8));
+
+        assert!(matches!(
+            exchange.on_timeout(exchange.retransmit_at),
+            Err(Error::Timeout)
+        ));
+    }
+
+    #[test]
+    fn waits_until_retransmission_or_deadline() {
+        let mut exchange = exchange(Duration::from_secs(5));
+        let now = exchange.retransmit_at - Duration::from_secs(1);
+
+        assert_eq!(exchange.wait_time(now).unwrap(), Duration::from_secs(1));
+
+        // Once acknowledged, only the deadline is left to wait for.
+        exchange
+            .handle(empty(MessageType::Acknowledgement, MESSAGE_ID))
+            .unwrap();
+        let wait = exchange.wait_time(now).unwrap();
+        assert_eq!(wait, exchange.deadline - now);
+        assert!(!exchange.on_timeout(exchange.retransmit_at).unwrap());
+
+        assert!(matches!(
+            exchange.wait_time(exchange.deadline),
+            Err(Error::Timeout)
+        ));
+        assert!(matches!(
+            exchange.on_timeout(exchange.deadline),
+            Err(Error::Timeout)
+        ));
+    }
+
+    #[test]
+    fn never_waits_for_zero_time_before_the_deadline() {
+        let exchange = exchange(Duration::from_secs(30));
+
+        assert_eq!(
+            exchange.wait_time(exchange.retransmit_at).unwrap(),
+            Duration::from_millis(1)
+        );
+    }
+
+    #[test]
+    fn matches_piggybacked_response_on_message_id_and_token() {
+        let mut exchange = exchange(Duration::from_secs(30));
+
+        let received = exchange
+            .handle(message(MessageType::Acknowledgement, MESSAGE_ID, &TOKEN))
+            .unwrap();
+        assert!(matches!(received, Received::Response(_, None)));
+
+        let mut exchange = self::exchange(Duration::from_secs(30));
+        assert!(matches!(
+            exchange.handle(message(MessageType::Acknowledgement, MESSAGE_ID, &[9])),
+            Err(Error::UnexpectedResponse(_))
+        ));
+    }
+
+    #[test]
+    fn ignores_acknowledgements_of_other_messages() {
+        let mut exchange = exchange(Duration::from_secs(30));
+
+        let received = exchange
+            .handle(message(
+                MessageType::Acknowledgement,
+                MESSAGE_ID + 1,
+                &TOKEN,
+            ))
+            .unwrap();
+        assert!(matches!(received, Received::Ignore));
+        assert!(!exchange.acknowledged);
+    }
+
+    #[test]
+    fn matches_separate_response_on_token() {
+        let mut exchange = exchange(Duration::from_secs(30));
+
+        let received = exchange
+            .handle(empty(MessageType::Acknowledgement, MESSAGE_ID))
+            .unwrap();
+        assert!(matches!(received, Received::Ignore));
+
+        let received = exchange
+            .handle(message(MessageType::Confirmable, 0x4321, &TOKEN))
+            .unwrap();
+        assert!(matches!(received, Received::Response(_, Some(0x4321))));
+
+        let received = exchange
+            .handle(message(MessageType::NonConfirmable, 0x4322, &TOKEN))
+            .unwrap();
+        assert!(matches!(received, Received::Response(_, None)));
+    }
+
+    #[test]
+    fn rejects_unrelated_confirmable_messages() {
+        let mut exchange = exchange(Duration::from_secs(30));
+
+        let received = exchange
+            .handle(message(MessageType::Confirmable, 0x4321, &[9]))
+            .unwrap();
+        assert!(matches!(received, Received::Reject(0x4321)));
+
+        let received = exchange
+            .handle(message(MessageType::NonConfirmable, 0x4322, &[9]))
+            .unwrap();
+        assert!(matches!(received, Received::Ignore));
+    }
+
+    #[test]
+    fn fails_when_request_is_reset() {
+        let mut exchange = exchange(Duration::from_secs(30));
+
+        assert!(matches!(
+            exchange.handle(empty(MessageType::Reset, MESSAGE_ID)),
+            Err(Error::Rejected)
+        ));
+    }
+
+    #[test]
+    fn only_resends_idempotent_methods() {
+        let mut req = CoAPRequest::new();
+        for (method, idempotent) in [
+            (Method::Get, true),
+            (Method::Put, true),
+            (Method::Delete, true),
+            (Method::Post, false),
+        ] {
+            req.set_method(method);
+            assert_eq!(is_idempotent(&req), idempotent);
+        }
+    }
+}
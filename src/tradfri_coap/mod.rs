@@ -1,12 +1,24 @@
+#[cfg(feature = "async")]
+mod async_connection;
 mod authenticator;
+mod blockwise;
 mod connection;
 mod error;
-mod macros;
+mod exchange;
 mod result;
 
 pub(crate) const BUF_SIZE: usize = 8192;
 
+pub use {error::Error, exchange::TransmissionParameters};
+
+#[cfg(feature = "async")]
+pub(crate) use async_connection::AsyncTradfriConnection;
+#[cfg(feature = "simulator")]
+pub(crate) use connection::GATEWAY_CIPHER;
+
 pub(crate) use {
-    authenticator::TradfriAuthenticator, connection::TradfriConnection, error::Error,
+    authenticator::TradfriAuthenticator,
+    connection::{initial_message_id, TradfriConnection, TF_PORT},
+    exchange::is_idempotent,
     result::Result,
 };
@@ -0,0 +1,175 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use coap::{message::packet::Packet, CoAPRequest, CoAPResponse};
+
+use super::{ObserveChannel, Transport};
+use crate::{
+    tradfri_coap::{self, is_idempotent, TradfriConnection, TransmissionParameters},
+    GatewayAddr, Secret, TradfriGatewayError,
+};
+
+/// Seconds to wait for the gateway to complete a handshake, or to answer on an
+/// established session before the session is considered lost and a new
+/// handshake is made.
+pub(crate) const SESSION_TIMEOUT: u64 = 10;
+
+/// Transport to a real gateway over a DTLS session authenticated with the
+/// identifier and session key.
+///
+/// The session is set up on the first request and transparently
+/// re-established if the gateway drops it, for example after a reboot.
+/// Requests from different threads take turns on the session, and a request
+/// that needs a new session makes the handshake in its turn: the others wait
+/// for it, up to 10 seconds, rather than each making a handshake of their own.
+/// Observers have sessions of their own and are not held up.
+///
+/// A request that fails on a lost session is sent again on the new session,
+/// except for POST requests. The gateway may already have acted on those, so
+/// they fail and the caller decides whether to try again.
+#[derive(Debug)]
+pub struct DtlsTransport {
+    address: GatewayAddr,
+    identifier: String,
+    session_key: Secret,
+    parameters: TransmissionParameters,
+    session: Mutex<Option<TradfriConnection>>,
+}
+
+impl DtlsTransport {
+    pub fn new<A: Into<GatewayAddr>, K: Into<Secret>>(
+        address: A,
+        identifier: &str,
+        session_key: K,
+    ) -> Self {
+        Self {
+            address: address.into(),
+            identifier: identifier.into(),
+            session_key: session_key.into(),
+            parameters: TransmissionParameters::default(),
+            session: Mutex::new(None),
+        }
+    }
+
+    /// Sets the overall time allowed for each request to the gateway, after
+    /// which it fails with [`TradfriGatewayError::Timeout`].
+    ///
+    /// Defaults to 30 seconds.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.parameters.request_timeout = timeout;
+        self
+    }
+
+    /// Sets the CoAP retransmission parameters used for requests.
+    pub fn with_transmission_parameters(mut self, parameters: TransmissionParameters) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    /// Sets up the session now rather than on the first request, to find out
+    /// whether the gateway answers at the address.
+    pub(crate) fn connect(&self) -> Result<(), TradfriGatewayError> {
+        let mut session = self
+            .session
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if session.is_none() {
+            let mut connection = self.create_session()?;
+            connection.set_transmission_parameters(self.parameters);
+            *session = Some(connection);
+        }
+
+        Ok(())
+    }
+
+    fn create_session(&self) -> Result<TradfriConnection, TradfriGatewayError> {
+        Ok(TradfriConnection::new_with_timeout(
+            self.address,
+            self.identifier.as_bytes(),
+            self.session_key.expose(),
+            Some(SESSION_TIMEOUT),
+        )?)
+    }
+}
+
+impl Transport for DtlsTransport {
+    /// Sends a request over the shared session, handshaking a new session if
+    /// there is none yet or if the current one stopped working.
+    fn request(&self, req: CoAPRequest) -> Result<CoAPResponse, TradfriGatewayError> {
+        let deadline = Instant::now() + self.parameters.request_timeout;
+        let mut session = self.session.lock().unwrap_or_else(|poisoned| {
+            // A thread panicked mid request, the session may be out of sync.
+            let mut session = poisoned.into_inner();
+            *session = None;
+            session
+        });
+
+        if let Some(connection) = session.as_mut() {
+            // An established session that stops answering is most likely gone,
+            // for example after a gateway reboot, so try a new handshake well
+            // before the request deadline.
+            let session_deadline =
+                deadline.min(Instant::now() + Duration::from_secs(SESSION_TIMEOUT));
+            match connection.request_until(req.clone(), session_deadline) {
+                Ok(response) => return Ok(response),
+                Err(error) => {
+                    *session = None;
+                    if !is_idempotent(&req) {
+                        log::debug!("Gateway session lost, not resending: {}", error);
+                        return Err(request_error(error, &self.parameters));
+                    }
+                    log::debug!("Gateway session lost, reconnecting: {}", error);
+                }
+            }
+
+            if Instant::now() >= deadline {
+                return Err(TradfriGatewayError::Timeout(
+                    self.parameters.request_timeout,
+                ));
+            }
+        }
+
+        // Handshake while holding the lock, requests waiting for their turn
+        // need the new session as well.
+        let mut connection = self.create_session()?;
+        connection.set_transmission_parameters(self.parameters);
+        let connection = session.insert(connection);
+        let result = connection.request_until(req, deadline);
+        if result.is_err() {
+            *session = None;
+        }
+
+        result.map_err(|error| request_error(error, &self.parameters))
+    }
+
+    fn open_observe_channel(&self) -> Result<Box<dyn ObserveChannel>, TradfriGatewayError> {
+        Ok(Box::new(self.create_session()?))
+    }
+}
+
+/// Reports a request that ran out of time with the request timeout, other
+/// failures as they are.
+pub(crate) fn request_error(
+    error: tradfri_coap::Error,
+    parameters: &TransmissionParameters,
+) -> TradfriGatewayError {
+    if error.is_timeout() {
+        TradfriGatewayError::Timeout(parameters.request_timeout)
+    } else {
+        error.into()
+    }
+}
+
+impl ObserveChannel for TradfriConnection {
+    fn send(&mut self, message: &Packet) -> Result<(), TradfriGatewayError> {
+        TradfriConnection::send(self, message)?;
+
+        Ok(())
+    }
+
+    fn receive(&mut self, timeout: Duration) -> Result<Option<Packet>, TradfriGatewayError> {
+        Ok(TradfriConnection::receive_timeout(self, timeout)?.map(|response| response.message))
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use coap::{
+    message::{
+        header::{MessageClass, ResponseType},
+        packet::Packet,
+    },
+    CoAPRequest, CoAPResponse, MessageType, Method,
+};
+use serde_json::{json, Value};
+
+use super::Transport;
+use crate::TradfriGatewayError;
+
+/// A request received by a [`MemoryTransport`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub path: String,
+    /// The JSON payload, `None` for requests without one.
+    pub payload: Option<Value>,
+}
+
+/// In-memory transport serving canned JSON resources by path, for testing code
+/// that uses [`TradfriGateway`](crate::TradfriGateway) without a gateway.
+///
+/// Requests behave like they do on the gateway:
+///
+/// * `GET` returns the resource, or `4.04 Not Found`.
+/// * `PUT` merges the payload into the resource, objects key by key and arrays
+///   element by element, and answers `2.04 Changed`.
+/// * `POST` to a collection, a resource listing ids such as `15005/<group>`,
+///   stores the payload as a new item under the next free id, lists the id and
+///   answers `2.01 Created` with the id. The item gets its id and creation time
+///   under `9003` and `9002`, like on the gateway.
+/// * `POST` to any other path stores the payload as the resource and answers
+///   `2.01 Created`. A `POST` without payload is a command, such as a reboot,
+///   and answers `2.04 Changed`.
+/// * `DELETE` removes the resource and its id from the collection listing it,
+///   and answers `2.02 Deleted`.
+///
+/// Every request is recorded and can be inspected with
+/// [`MemoryTransport::requests`].
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use serde_json::json;
+/// use tradfri_gateway::{MemoryTransport, TradfriGateway};
+///
+/// let transport = Arc::new(MemoryTransport::new().with_group(json!({
+///     "5850": 0, "5851": 0, "9001": "Kitchen", "9002": 1600000000, "9003": 131073, "9018": {}
+/// })));
+/// let gateway = TradfriGateway::from_transport(transport.clone());
+///
+/// let mut group = gateway.group(131073).unwrap();
+/// group.on().unwrap();
+/// assert_eq!(transport.resource("15004/131073").unwrap()["5850"], 1);
+///
+/// transport.set_resource("15005/131073", json!([]));
+/// let scene = gateway.create_scene(131073, "Evening", Vec::new()).unwrap();
+/// assert_eq!(scene.id(), 196608);
+/// assert_eq!(transport.resource("15005/131073").unwrap(), json!([196608]));
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryTransport {
+    resources: Mutex<BTreeMap<String, Value>>,
+    requests: Mutex<Vec<RecordedRequest>>,
+    /// Skips recording requests, for long running users such as the simulator.
+    unrecorded: bool,
+}
+
+impl MemoryTransport {
+    /// Creates a transport with empty device and group collections.
+    pub fn new() -> Self {
+        Self::default()
+            .with_resource("15001", Value::Array(Vec::new()))
+            .with_resource("15004", Value::Array(Vec::new()))
+    }
+
+    /// Adds a resource, or replaces it if the path is already taken.
+    pub fn with_resource(self, path: &str, value: Value) -> Self {
+        self.set_resource(path, value);
+        self
+    }
+
+    /// Adds a device under `15001/<id>` and lists its id in `15001`, the id is
+    /// read from the `9003` key of the device.
+    pub fn with_device(self, device: Value) -> Self {
+        self.add_item("15001", device);
+        self
+    }
+
+    /// Adds a group under `15004/<id>` and lists its id in `15004`, the id is
+    /// read from the `9003` key of the group.
+    pub fn with_group(self, group: Value) -> Self {
+        self.add_item("15004", group);
+        self
+    }
+
+    pub fn set_resource(&self, path: &str, value: Value) {
+        self.resources().insert(normalize(path), value);
+    }
+
+    pub fn resource(&self, path: &str) -> Option<Value> {
+        self.resources().get(&normalize(path)).cloned()
+    }
+
+    /// All requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    #[cfg(feature = "simulator")]
+    pub(crate) fn without_recording(mut self) -> Self {
+        self.unrecorded = true;
+        self
+    }
+
+    fn add_item(&self, collection: &str, item: Value) {
+        let id = item.get("9003").and_then(Value::as_u64).unwrap_or_default();
+
+        let mut resources = self.resources();
+        resources.insert(format!("{}/{}", collection, id), item);
+
+        let ids = resources
+            .entry(collection.to_string())
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(ids) = ids {
+            if !ids.iter().any(|existing| existing.as_u64() == Some(id)) {
+                ids.push(id.into());
+            }
+        }
+    }
+
+    fn resources(&self) -> MutexGuard<'_, BTreeMap<String, Value>> {
+        self.resources
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn handle(
+        &self,
+        method: &Method,
+        path: String,
+        payload: Option<Value>,
+    ) -> (ResponseType, Option<Value>) {
+        let mut resources = self.resources();
+
+        match (method, payload) {
+            (Method::Get, _) => match resources.get(&path) {
+                Some(value) => (ResponseType::Content, Some(value.clone())),
+                None => (ResponseType::NotFound, None),
+            },
+            (Method::Put, Some(payload)) => match resources.get_mut(&path) {
+                Some(value) => {
+                    merge(value, payload);
+                    (ResponseType::Changed, None)
+                }
+                None => (ResponseType::NotFound, None),
+            },
+            (Method::Post, Some(payload)) => match resources.get(&path) {
+                Some(Value::Array(_)) => create_item(&mut resources, &path, payload),
+                _ => {
+                    resources.insert(path, payload);
+                    (ResponseType::Created, None)
+                }
+            },
+            (Method::Post, None) => (ResponseType::Changed, None),
+            (Method::Put, None) => (ResponseType::BadRequest, None),
+            (Method::Delete, _) => match resources.remove(&path) {
+                Some(_) => {
+                    remove_from_collection(&mut resources, &path);
+                    (ResponseType::Deleted, None)
+                }
+                None => (ResponseType::NotFound, None),
+            },
+            (Method::UnKnown, _) => (ResponseType::MethodNotAllowed, None),
+        }
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn request(&self, req: CoAPRequest) -> Result<CoAPResponse, TradfriGatewayError> {
+        let method = req.get_method().clone();
+        let path = normalize(&req.get_path());
+        let payload = if req.message.payload.is_empty() {
+            None
+        } else {
+            Some(serde_json::from_slice(&req.message.payload)?)
+        };
+
+        if !self.unrecorded {
+            self.requests
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .push(RecordedRequest {
+                    method: method.clone(),
+                    path: path.clone(),
+                    payload: payload.clone(),
+                });
+        }
+
+        let (status, body) = self.handle(&method, path, payload);
+
+        let mut message = Packet::new();
+        message.header.set_type(MessageType::Acknowledgement);
+        message
+            .header
+            .set_message_id(req.message.header.get_message_id());
+        message.header.code = MessageClass::Response(status);
+        message.set_token(req.message.get_token().clone());
+        if let Some(body) = body {
+            message.payload = serde_json::to_vec(&body)?;
+        }
+
+        Ok(CoAPResponse { message })
+    }
+}
+
+/// Stores `item` in the collection at `path` under the id after the highest
+/// one listed there, or the first id the gateway uses for that kind of item.
+fn create_item(
+    resources: &mut BTreeMap<String, Value>,
+    path: &str,
+    item: Value,
+) -> (ResponseType, Option<Value>) {
+    let mut item = match item {
+        Value::Object(item) => item,
+        _ => return (ResponseType::BadRequest, None),
+    };
+    let ids = match resources.get_mut(path) {
+        Some(Value::Array(ids)) => ids,
+        _ => return (ResponseType::NotFound, None),
+    };
+
+    let id = ids
+        .iter()
+        .filter_map(Value::as_u64)
+        .max()
+        .map_or_else(|| first_id(path), |id| id + 1);
+    ids.push(id.into());
+
+    let created_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    item.insert("9003".into(), id.into());
+    item.entry("9002").or_insert_with(|| created_at.into());
+    resources.insert(format!("{}/{}", path, id), Value::Object(item));
+
+    (ResponseType::Created, Some(json!({ "9003": id })))
+}
+
+/// Removes the id of a deleted item, such as `15005/131073/196608`, from the
+/// collection it is listed in.
+fn remove_from_collection(resources: &mut BTreeMap<String, Value>, path: &str) {
+    let (collection, id) = match path.rsplit_once('/') {
+        Some((collection, id)) => (collection, id.parse::<u64>().ok()),
+        None => return,
+    };
+
+    if let (Some(Value::Array(ids)), Some(id)) = (resources.get_mut(collection), id) {
+        ids.retain(|existing| existing.as_u64() != Some(id));
+    }
+}
+
+/// The id the gateway gives the first item of a collection.
+fn first_id(path: &str) -> u64 {
+    match path.split('/').next() {
+        Some("15001") => 65536,
+        Some("15004") => 131072,
+        Some("15005") => 196608,
+        _ => 1,
+    }
+}
+
+fn normalize(path: &str) -> String {
+    path.trim_matches('/').to_string()
+}
+
+/// Applies a partial update the way the gateway does, keeping keys and array
+/// elements the update does not mention.
+fn merge(target: &mut Value, update: Value) {
+    match (target, update) {
+        (Value::Object(target), Value::Object(update)) => {
+            for (key, value) in update {
+                match target.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(target), Value::Array(update)) => {
+            for (index, value) in update.into_iter().enumerate() {
+                match target.get_mut(index) {
+                    Some(existing) => merge(existing, value),
+                    None => target.push(value),
+                }
+            }
+        }
+        (target, update) => *target = update,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{FactoryResetConfirmation, TradfriGateway};
+
+    fn request(method: Method, path: &str) -> CoAPRequest {
+        let mut req = CoAPRequest::new();
+        req.set_method(method);
+        req.set_path(path);
+        req
+    }
+
+    fn status(response: &CoAPResponse) -> MessageClass {
+        response.message.header.code.clone()
+    }
+
+    #[test]
+    fn answers_commands_without_payload() {
+        let transport = MemoryTransport::new();
+
+        let response = transport
+            .request(request(Method::Post, "15011/9030"))
+            .unwrap();
+
+        assert_eq!(
+            status(&response),
+            MessageClass::Response(ResponseType::Changed)
+        );
+        assert_eq!(transport.resource("15011/9030"), None);
+    }
+
+    #[test]
+    fn removes_deleted_item_from_its_collection() {
+        let transport = MemoryTransport::new()
+            .with_resource("15005/131073", json!([196608, 196609]))
+            .with_resource("15005/131073/196608", json!({ "9003": 196608 }));
+
+        let response = transport
+            .request(request(Method::Delete, "15005/131073/196608"))
+            .unwrap();
+
+        assert_eq!(
+            status(&response),
+            MessageClass::Response(ResponseType::Deleted)
+        );
+        assert_eq!(transport.resource("15005/131073/196608"), None);
+        assert_eq!(transport.resource("15005/131073"), Some(json!([196609])));
+    }
+
+    #[test]
+    fn creates_items_in_collections() {
+        let transport = MemoryTransport::new().with_resource("15005/131073", json!([]));
+
+        let mut req = request(Method::Post, "15005/131073");
+        req.message.payload = br#"{"9001":"Evening"}"#.to_vec();
+        let response = transport.request(req.clone()).unwrap();
+        transport.request(req).unwrap();
+
+        assert_eq!(
+            serde_json::from_slice::<Value>(&response.message.payload).unwrap(),
+            json!({ "9003": 196608 })
+        );
+        assert_eq!(
+            transport.resource("15005/131073"),
+            Some(json!([196608, 196609]))
+        );
+        let scene = transport.resource("15005/131073/196609").unwrap();
+        assert_eq!(scene["9001"], "Evening");
+        assert_eq!(scene["9003"], 196609);
+        assert!(scene["9002"].is_u64());
+    }
+
+    #[test]
+    fn supports_gateway_commands() {
+        let transport = std::sync::Arc::new(
+            MemoryTransport::new()
+                .with_resource("15011/15012", json!({ "9029": "1.19.32", "9081": "7e0f" })),
+        );
+        let gateway = TradfriGateway::from_transport(transport.clone());
+
+        gateway.reboot().unwrap();
+        gateway
+            .factory_reset(&FactoryResetConfirmation::for_gateway("7e0f"))
+            .unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+mod dtls;
+pub use dtls::*;
+
+mod memory;
+pub use memory::*;
+
+use std::{fmt::Debug, time::Duration};
+
+use coap::{message::packet::Packet, CoAPRequest, CoAPResponse};
+
+use crate::TradfriGatewayError;
+
+/// Carries CoAP requests from a [`TradfriGateway`](crate::TradfriGateway) to
+/// the gateway and returns the responses.
+///
+/// [`DtlsTransport`] talks to a real gateway and is what the `from_*`
+/// constructors of [`TradfriGateway`](crate::TradfriGateway) use.
+/// [`MemoryTransport`] serves canned resources instead, which allows code
+/// built on top of the gateway to be tested without one. Other
+/// implementations can be used with
+/// [`TradfriGateway::from_transport`](crate::TradfriGateway::from_transport).
+pub trait Transport: Debug + Send + Sync {
+    /// Sends a request and returns its response, whatever the response code.
+    fn request(&self, req: CoAPRequest) -> Result<CoAPResponse, TradfriGatewayError>;
+
+    /// Opens a channel of its own for observing resources, see
+    /// [`ObserveChannel`].
+    ///
+    /// Transports that can not observe resources keep the default, which fails
+    /// with [`TradfriGatewayError::Unsupported`].
+    fn open_observe_channel(&self) -> Result<Box<dyn ObserveChannel>, TradfriGatewayError> {
+        Err(TradfriGatewayError::Unsupported("observing resources"))
+    }
+}
+
+/// Message level channel used by observations.
+///
+/// Notifications arrive whenever the gateway decides to send them, so the
+/// observation sends and receives single CoAP messages on a channel that is
+/// not shared with regular requests. An observation that stops hearing from
+/// the gateway, or gets an error from its channel, drops the channel and opens
+/// a new one.
+pub trait ObserveChannel: Send + Sync {
+    /// Sends one message without waiting for an answer.
+    fn send(&mut self, message: &Packet) -> Result<(), TradfriGatewayError>;
+
+    /// Blocks until the next message arrives, `None` if none arrived within
+    /// the timeout.
+    fn receive(&mut self, timeout: Duration) -> Result<Option<Packet>, TradfriGatewayError>;
+}
+
+/// Allows keeping a handle to a transport given to a gateway, for example to
+/// inspect a [`MemoryTransport`] afterwards.
+impl<T: Transport + ?Sized> Transport for std::sync::Arc<T> {
+    fn request(&self, req: CoAPRequest) -> Result<CoAPResponse, TradfriGatewayError> {
+        (**self).request(req)
+    }
+
+    fn open_observe_channel(&self) -> Result<Box<dyn ObserveChannel>, TradfriGatewayError> {
+        (**self).open_observe_channel()
+    }
+}
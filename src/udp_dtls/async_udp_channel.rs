@@ -0,0 +1,29 @@
+use std::io::{Read, Result, Write};
+use std::sync::Arc;
+
+use tokio::net::UdpSocket;
+
+/// Non-blocking wrapper to read from and send data to a connected UDP socket.
+///
+/// Reads and writes never wait, they fail with `WouldBlock` instead so that the
+/// caller can await the readiness of the shared socket and try again.
+#[derive(Debug, Clone)]
+pub struct AsyncUdpChannel {
+    pub socket: Arc<UdpSocket>,
+}
+
+impl Read for AsyncUdpChannel {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.socket.try_recv(buf)
+    }
+}
+
+impl Write for AsyncUdpChannel {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.socket.try_send(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
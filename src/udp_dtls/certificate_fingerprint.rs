@@ -1,4 +1,4 @@
-use core::fmt::Write;
+use core::fmt;
 
 #[derive(Clone, Copy, Debug)]
 pub enum SignatureAlgorithm {
@@ -12,23 +12,21 @@ pub struct CertificateFingerprint {
     pub signature_algorithm: SignatureAlgorithm,
 }
 
-impl ToString for CertificateFingerprint {
-    fn to_string(&self) -> String {
-        let mut s = match self.signature_algorithm {
+impl fmt::Display for CertificateFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.signature_algorithm {
             SignatureAlgorithm::Sha1 => "sha-1 ",
             SignatureAlgorithm::Sha256 => "sha-256 ",
-        }
-        .to_string();
-
-        for b in &self.bytes {
-            s.write_fmt(format_args!("{:02X}:", b)).unwrap();
-        }
+        })?;
 
-        if !s.is_empty() {
-            s.pop(); //remove last ':'
+        for (i, b) in self.bytes.iter().enumerate() {
+            if i > 0 {
+                f.write_str(":")?;
+            }
+            write!(f, "{:02X}", b)?;
         }
 
-        s
+        Ok(())
     }
 }
 
@@ -0,0 +1,188 @@
+use super::{
+    openssl::try_set_supported_protocols, DtlsAcceptorBuilder, DtlsStream, Error, HandshakeError,
+    Protocol,
+};
+use log::debug;
+use openssl::{
+    error::ErrorStack,
+    ex_data::Index,
+    hash::MessageDigest,
+    memcmp,
+    pkey::PKey,
+    sign::Signer,
+    ssl::{Ssl, SslContext, SslContextBuilder, SslMethod, SslOptions, SslRef, SslVerifyMode},
+};
+use std::{
+    fmt, io,
+    net::SocketAddr,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+/// Length of the secret the cookies are derived from.
+const COOKIE_SECRET_LEN: usize = 32;
+
+/// Period in which a cookie is issued. Cookies of the current and the previous
+/// period are accepted, so a cookie is valid for one to two periods.
+const COOKIE_PERIOD: Duration = Duration::from_secs(60);
+
+/// Acceptor of DTLS sessions from UDP clients.
+#[derive(Clone)]
+pub struct DtlsAcceptor {
+    context: SslContext,
+}
+
+impl DtlsAcceptor {
+    /// Creates a new `DtlsAcceptor`.
+    ///
+    /// The `DtlsAcceptor` will use the settings from the given builder.
+    ///
+    /// The following propperties will be applied from the builder:
+    /// - Sets minimal/maximal protocol version
+    /// - Sets the PSK server callback
+    /// - Sets the allowed ciphers
+    /// - Enables the cookie exchange with cookies bound to the client address
+    ///   and expiring after one to two minutes
+    pub fn new(builder: &DtlsAcceptorBuilder) -> Result<DtlsAcceptor, Error> {
+        let mut context = SslContextBuilder::new(SslMethod::dtls())?;
+        context.set_verify(SslVerifyMode::NONE);
+
+        if let Some(ref callback) = builder.psk_callback {
+            let callback = callback.clone();
+
+            context.set_psk_server_callback(move |_, identity, psk| {
+                let key = match identity.and_then(|identity| callback(identity)) {
+                    Some(key) => key,
+                    None => {
+                        debug!("psk_server_callback: unknown identity {:?}", identity);
+                        return Err(ErrorStack::get());
+                    }
+                };
+
+                if key.len() > psk.len() {
+                    debug!("psk_server_callback error: key of {} bytes", key.len());
+                    return Err(ErrorStack::get());
+                }
+                psk[..key.len()].copy_from_slice(&key);
+
+                Ok(key.len())
+            });
+        }
+
+        if !builder.cipher_list.is_empty() {
+            context.set_cipher_list(&builder.cipher_list.join(":"))?;
+        }
+
+        try_set_supported_protocols(builder.min_protocol, builder.max_protocol, &mut context)?;
+
+        if builder.cookie_exchange {
+            let mut secret = [0u8; COOKIE_SECRET_LEN];
+            openssl::rand::rand_bytes(&mut secret)?;
+            let key = PKey::hmac(&secret)?;
+            let verify_key = key.clone();
+            let epoch = Instant::now();
+
+            context.set_options(SslOptions::COOKIE_EXCHANGE);
+            context.set_cookie_generate_cb(move |ssl, cookie| {
+                let mac = cookie_mac(&key, ssl, cookie_period(epoch))?;
+                let len = mac.len().min(cookie.len());
+                cookie[..len].copy_from_slice(&mac[..len]);
+                Ok(len)
+            });
+            context.set_cookie_verify_cb(move |ssl, cookie| {
+                let period = cookie_period(epoch);
+                [Some(period), period.checked_sub(1)]
+                    .into_iter()
+                    .flatten()
+                    .any(|period| match cookie_mac(&verify_key, ssl, period) {
+                        Ok(mac) => mac.len() == cookie.len() && memcmp::eq(&mac, cookie),
+                        Err(_) => false,
+                    })
+            });
+        }
+
+        Ok(DtlsAcceptor {
+            context: context.build(),
+        })
+    }
+
+    /// Returns a new builder for a `DtlsAcceptor` from which you can create the `DtlsAcceptor`.
+    pub fn builder() -> DtlsAcceptorBuilder {
+        DtlsAcceptorBuilder {
+            psk_callback: None,
+            min_protocol: Some(Protocol::Dtlsv10),
+            max_protocol: None,
+            cipher_list: vec![],
+            cookie_exchange: true,
+        }
+    }
+
+    /// Accepts a DTLS session from the client at the given address.
+    ///
+    /// The address binds the cookie of the cookie exchange to the client, the
+    /// stream should read the datagrams of that client only, such as a
+    /// [`PeerChannel`](super::PeerChannel) of a
+    /// [`DtlsListener`](super::DtlsListener).
+    ///
+    /// If the stream is nonblocking, or reads time out, and a `WouldBlock`
+    /// error is returned during the handshake, a `HandshakeError::WouldBlock`
+    /// error will be returned which can be used to restart the handshake when
+    /// the stream is ready again. Restarting the handshake also retransmits
+    /// the last flight once the DTLS timer has run out.
+    pub fn accept<S>(&self, peer: SocketAddr, stream: S) -> Result<DtlsStream<S>, HandshakeError<S>>
+    where
+        S: io::Read + io::Write + fmt::Debug,
+    {
+        let mut ssl = Ssl::new(&self.context)?;
+        ssl.set_ex_data(peer_index()?, peer);
+
+        let stream = ssl.accept(stream)?;
+        Ok(DtlsStream::from(stream))
+    }
+}
+
+impl fmt::Debug for DtlsAcceptor {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("DtlsAcceptor").finish_non_exhaustive()
+    }
+}
+
+impl AsRef<SslContext> for DtlsAcceptor {
+    fn as_ref(&self) -> &SslContext {
+        &self.context
+    }
+}
+
+/// Index of the client address in the ex data of a session.
+fn peer_index() -> Result<Index<Ssl, SocketAddr>, ErrorStack> {
+    static INDEX: OnceLock<Index<Ssl, SocketAddr>> = OnceLock::new();
+
+    if let Some(index) = INDEX.get() {
+        return Ok(*index);
+    }
+    let index = Ssl::new_ex_index()?;
+    Ok(*INDEX.get_or_init(|| index))
+}
+
+/// Number of the cookie period at the current time.
+fn cookie_period(epoch: Instant) -> u64 {
+    epoch.elapsed().as_secs() / COOKIE_PERIOD.as_secs()
+}
+
+/// Derives the cookie of a client from its address and the period the cookie
+/// is issued in.
+fn cookie_mac(
+    key: &PKey<openssl::pkey::Private>,
+    ssl: &SslRef,
+    period: u64,
+) -> Result<Vec<u8>, ErrorStack> {
+    let peer = ssl
+        .ex_data(peer_index()?)
+        .map(|peer| peer.to_string())
+        .unwrap_or_default();
+
+    let mut signer = Signer::new(MessageDigest::sha256(), key)?;
+    signer.update(&period.to_be_bytes())?;
+    signer.update(peer.as_bytes())?;
+    signer.sign_to_vec()
+}
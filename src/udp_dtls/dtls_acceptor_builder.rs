@@ -0,0 +1,89 @@
+use std::{fmt, sync::Arc};
+
+use super::{DtlsAcceptor, Protocol, Result};
+
+/// Looks up the pre-shared key of a client identity, `None` rejects the client.
+pub type PskServerCallback = dyn Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync;
+
+/// A builder for `DtlsAcceptor`s.
+///
+/// With this builder you can configure the following DTLS properties:
+/// - The callback resolving client identities to pre-shared keys
+/// - Configuring min/max supported DTLS versions
+/// - The allowed ciphers
+/// - Enabling the cookie exchange (HelloVerifyRequest)
+pub struct DtlsAcceptorBuilder {
+    pub(crate) psk_callback: Option<Arc<PskServerCallback>>,
+    pub(crate) min_protocol: Option<Protocol>,
+    pub(crate) max_protocol: Option<Protocol>,
+    pub(crate) cipher_list: Vec<String>,
+    pub(crate) cookie_exchange: bool,
+}
+
+impl DtlsAcceptorBuilder {
+    /// Sets the callback resolving the identity a client presents to its
+    /// pre-shared key.
+    ///
+    /// # Hint
+    /// You should specify one of the PSK_* ciphers, i.e. PSK-AES128-CCM8
+    pub fn psk_server_callback<F>(&mut self, callback: F) -> &mut DtlsAcceptorBuilder
+    where
+        F: Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.psk_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Sets the minimum supported protocol version.
+    ///
+    /// A value of `None` enables support for the oldest protocols supported by the implementation.
+    ///
+    /// Defaults to `Some(Protocol::Dtlsv10)`.
+    pub fn min_protocol_version(&mut self, protocol: Option<Protocol>) -> &mut DtlsAcceptorBuilder {
+        self.min_protocol = protocol;
+        self
+    }
+
+    /// Sets the maximum supported protocol version.
+    ///
+    /// A value of `None` enables support for the newest protocols supported by the implementation.
+    ///
+    /// Defaults to `None`.
+    pub fn max_protocol_version(&mut self, protocol: Option<Protocol>) -> &mut DtlsAcceptorBuilder {
+        self.max_protocol = protocol;
+        self
+    }
+
+    /// Adds cipher name to the list of allowed ciphers.
+    pub fn add_cipher<C: Into<String>>(&mut self, cipher: C) -> &mut DtlsAcceptorBuilder {
+        self.cipher_list.push(cipher.into());
+        self
+    }
+
+    /// Controls the cookie exchange, where the server answers the first client
+    /// hello with a HelloVerifyRequest and only continues once the client has
+    /// echoed the cookie, proving that it can receive at its address.
+    ///
+    /// Defaults to `true`.
+    pub fn cookie_exchange(&mut self, cookie_exchange: bool) -> &mut DtlsAcceptorBuilder {
+        self.cookie_exchange = cookie_exchange;
+        self
+    }
+
+    /// Creates a new `DtlsAcceptor` with the settings from this builder.
+    pub fn build(&self) -> Result<DtlsAcceptor> {
+        DtlsAcceptor::new(self)
+    }
+}
+
+impl fmt::Debug for DtlsAcceptorBuilder {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("DtlsAcceptorBuilder")
+            .field("psk_callback", &self.psk_callback.is_some())
+            .field("min_protocol", &self.min_protocol)
+            .field("max_protocol", &self.max_protocol)
+            .field("cipher_list", &self.cipher_list)
+            .field("cookie_exchange", &self.cookie_exchange)
+            .finish()
+    }
+}
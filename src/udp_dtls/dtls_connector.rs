@@ -1,5 +1,5 @@
 use super::{
-    openssl::{init_trust, try_set_supported_protocols},
+    openssl::{load_trust, try_set_supported_protocols},
     ConnectorIdentity, DtlsConnectorBuilder, DtlsStream, Error, HandshakeError, Protocol,
 };
 use log::debug;
@@ -27,9 +27,8 @@ impl DtlsConnector {
     /// - Sets the certificate and private key
     /// - Adds the root certificates to the certificate store.
     pub fn new(builder: &DtlsConnectorBuilder) -> Result<DtlsConnector, Error> {
-        init_trust();
-
         let mut connector = SslConnector::builder(SslMethod::dtls()).unwrap();
+        load_trust(&mut connector);
 
         if let Some(ref identity) = builder.identity {
             match identity {
@@ -42,7 +41,7 @@ impl DtlsConnector {
                             return Err(ErrorStack::get());
                         }
 
-                        if let Err(err) = psk.write_all(&identity_.1) {
+                        if let Err(err) = psk.write_all(identity_.1.expose()) {
                             debug!("psk_client_callback error (psk): {:?}", err);
                             return Err(ErrorStack::get());
                         }
@@ -99,13 +98,9 @@ impl DtlsConnector {
     ///
     /// The domain is ignored if both SNI and hostname verification are
     /// disabled.
-    pub fn connect<S: fmt::Debug>(
-        &self,
-        domain: &str,
-        stream: S,
-    ) -> Result<DtlsStream<S>, HandshakeError<S>>
+    pub fn connect<S>(&self, domain: &str, stream: S) -> Result<DtlsStream<S>, HandshakeError<S>>
     where
-        S: io::Read + io::Write,
+        S: io::Read + io::Write + fmt::Debug,
     {
         let mut ssl = self
             .connector
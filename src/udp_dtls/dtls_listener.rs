@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+/// DTLS record content type of handshake messages, the only records that may
+/// start a new session.
+const HANDSHAKE_CONTENT_TYPE: u8 = 22;
+
+const BUF_SIZE: usize = 8192;
+
+/// Time after which a client that sent nothing is forgotten, unless set with
+/// [`DtlsListener::set_peer_idle_timeout`].
+const PEER_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Demultiplexes the datagrams received on one UDP socket into a
+/// [`PeerChannel`] per client address, for a DTLS server.
+///
+/// [`DtlsListener::accept`] receives datagrams, forwarding those of known
+/// clients to their channels, until a datagram starting a handshake arrives
+/// from a new client. The channel of that client is then returned, ready to be
+/// handed to [`DtlsAcceptor::accept`](super::DtlsAcceptor::accept), usually on
+/// a thread of its own.
+///
+/// A client gets a new channel once the previous channel for its address has
+/// been dropped. A client that sends nothing for the peer idle timeout is
+/// forgotten, reads from its channel then fail with `ConnectionAborted`.
+#[derive(Debug)]
+pub struct DtlsListener {
+    socket: UdpSocket,
+    peers: HashMap<SocketAddr, Peer>,
+    peer_idle_timeout: Duration,
+    last_eviction: Instant,
+}
+
+#[derive(Debug)]
+struct Peer {
+    sender: Sender<Vec<u8>>,
+    last_seen: Instant,
+}
+
+impl DtlsListener {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<DtlsListener> {
+        Ok(DtlsListener::from_socket(UdpSocket::bind(address)?))
+    }
+
+    pub fn from_socket(socket: UdpSocket) -> DtlsListener {
+        DtlsListener {
+            socket,
+            peers: HashMap::new(),
+            peer_idle_timeout: PEER_IDLE_TIMEOUT,
+            last_eviction: Instant::now(),
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Sets the time [`DtlsListener::accept`] waits for a datagram before it
+    /// fails with a `WouldBlock` or `TimedOut` error, `None` waits forever.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    /// Sets the time after which a client that sent nothing is forgotten.
+    ///
+    /// Defaults to 10 minutes.
+    pub fn set_peer_idle_timeout(&mut self, timeout: Duration) {
+        self.peer_idle_timeout = timeout;
+    }
+
+    /// Waits for a new client to start a handshake and returns its channel.
+    pub fn accept(&mut self) -> io::Result<PeerChannel> {
+        let mut buf = [0u8; BUF_SIZE];
+        loop {
+            self.evict_idle_peers();
+            let (len, peer) = self.socket.recv_from(&mut buf)?;
+            let mut datagram = buf[..len].to_vec();
+
+            if let Some(known) = self.peers.get_mut(&peer) {
+                known.last_seen = Instant::now();
+                match known.sender.send(datagram) {
+                    Ok(()) => continue,
+                    // The channel has been dropped, a new handshake may follow.
+                    Err(mpsc::SendError(returned)) => {
+                        self.peers.remove(&peer);
+                        datagram = returned;
+                    }
+                }
+            }
+
+            if datagram.first() != Some(&HANDSHAKE_CONTENT_TYPE) {
+                continue;
+            }
+
+            let (sender, receiver) = mpsc::channel();
+            let _ = sender.send(datagram);
+            let channel = PeerChannel {
+                socket: self.socket.try_clone()?,
+                peer,
+                receiver,
+                read_timeout: None,
+            };
+            self.peers.insert(
+                peer,
+                Peer {
+                    sender,
+                    last_seen: Instant::now(),
+                },
+            );
+
+            return Ok(channel);
+        }
+    }
+
+    /// Forgets the clients that sent nothing for the idle timeout. Runs at
+    /// most every half timeout, so a client is kept for up to one and a half
+    /// timeouts.
+    fn evict_idle_peers(&mut self) {
+        let now = Instant::now();
+        if now.duration_since(self.last_eviction) < self.peer_idle_timeout / 2 {
+            return;
+        }
+
+        self.last_eviction = now;
+        let timeout = self.peer_idle_timeout;
+        self.peers
+            .retain(|_, peer| now.duration_since(peer.last_seen) < timeout);
+    }
+}
+
+/// Reads the datagrams of one client of a [`DtlsListener`] and sends
+/// datagrams to it over the shared socket.
+#[derive(Debug)]
+pub struct PeerChannel {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    receiver: Receiver<Vec<u8>>,
+    read_timeout: Option<Duration>,
+}
+
+impl PeerChannel {
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Sets the time reads wait for a datagram before they fail with a
+    /// `WouldBlock` error, `None` waits forever.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+}
+
+impl Read for PeerChannel {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let datagram = match self.read_timeout {
+            Some(timeout) => self.receiver.recv_timeout(timeout),
+            None => self
+                .receiver
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match datagram {
+            Ok(datagram) => {
+                let len = datagram.len().min(buf.len());
+                buf[..len].copy_from_slice(&datagram[..len]);
+                Ok(len)
+            }
+            Err(RecvTimeoutError::Timeout) => Err(io::ErrorKind::WouldBlock.into()),
+            Err(RecvTimeoutError::Disconnected) => Err(io::ErrorKind::ConnectionAborted.into()),
+        }
+    }
+}
+
+impl Write for PeerChannel {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.socket.send_to(buf, self.peer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    const HELLO: [u8; 2] = [HANDSHAKE_CONTENT_TYPE, 1];
+
+    fn listener() -> (DtlsListener, SocketAddr) {
+        let listener = DtlsListener::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let address = listener.local_addr().unwrap();
+        (listener, address)
+    }
+
+    fn client(listener: SocketAddr) -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(listener).unwrap();
+        socket
+    }
+
+    fn read(channel: &mut PeerChannel) -> io::Result<Vec<u8>> {
+        let mut buf = [0u8; BUF_SIZE];
+        let len = channel.read(&mut buf)?;
+        Ok(buf[..len].to_vec())
+    }
+
+    #[test]
+    fn forwards_datagrams_of_known_clients() {
+        let (mut listener, address) = listener();
+        let first = client(address);
+        let second = client(address);
+
+        // Only a handshake starts a new client.
+        second.send(&[23, 0]).unwrap();
+        first.send(&HELLO).unwrap();
+        let mut channel = listener.accept().unwrap();
+        assert_eq!(channel.peer_addr(), first.local_addr().unwrap());
+        assert_eq!(read(&mut channel).unwrap(), HELLO);
+
+        first.send(&[23, 1]).unwrap();
+        second.send(&HELLO).unwrap();
+        let other = listener.accept().unwrap();
+        assert_eq!(other.peer_addr(), second.local_addr().unwrap());
+        assert_eq!(read(&mut channel).unwrap(), [23, 1]);
+    }
+
+    #[test]
+    fn forgets_idle_clients() {
+        let (mut listener, address) = listener();
+        listener.set_peer_idle_timeout(Duration::from_millis(50));
+        let idle = client(address);
+        let active = client(address);
+
+        idle.send(&HELLO).unwrap();
+        let mut channel = listener.accept().unwrap();
+        read(&mut channel).unwrap();
+
+        thread::sleep(Duration::from_millis(100));
+        active.send(&HELLO).unwrap();
+        listener.accept().unwrap();
+
+        assert_eq!(
+            read(&mut channel).unwrap_err().kind(),
+            io::ErrorKind::ConnectionAborted
+        );
+        assert!(!listener.peers.contains_key(&idle.local_addr().unwrap()));
+    }
+}
@@ -65,6 +65,17 @@ impl<S: io::Read + io::Write> DtlsStream<S> {
         Ok(self.0.ssl().peer_certificate().map(Certificate::from))
     }
 
+    /// Returns the PSK identity the client presented, on the server side of a
+    /// session using a PSK cipher.
+    ///
+    /// # Underlying SSL
+    /// This corresponds to [`SSL_get_psk_identity`].
+    ///
+    /// [`SSL_get_psk_identity`]: https://www.openssl.org/docs/manmaster/man3/SSL_get_psk_identity.html
+    pub fn psk_identity(&self) -> Option<&[u8]> {
+        self.0.ssl().psk_identity()
+    }
+
     /// Shuts down the session.
     ///
     /// The shutdown process consists of two steps. The first step sends a close notify message to
@@ -83,9 +94,7 @@ impl<S: io::Read + io::Write> DtlsStream<S> {
         match self.0.shutdown() {
             Ok(_) => Ok(()),
             Err(ref e) if e.code() == ssl::ErrorCode::ZERO_RETURN => Ok(()),
-            Err(e) => Err(e
-                .into_io_error()
-                .unwrap_or_else(|e| io::Error::new(io::ErrorKind::Other, e))),
+            Err(e) => Err(e.into_io_error().unwrap_or_else(io::Error::other)),
         }
     }
 }
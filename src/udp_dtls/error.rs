@@ -19,11 +19,11 @@ pub enum Error {
 }
 
 impl error::Error for Error {
-    fn cause(&self) -> Option<&dyn error::Error> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
-            Error::Normal(ref e) => error::Error::source(e),
-            Error::Ssl(ref e, _) => error::Error::source(e),
-            Error::SrtpProfile(ref e) => error::Error::source(e),
+            Error::Normal(ref e) => Some(e),
+            Error::Ssl(ref e, _) => Some(e),
+            Error::SrtpProfile(ref e) => Some(e),
         }
     }
 }
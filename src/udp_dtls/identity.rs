@@ -1,17 +1,19 @@
 use bytes::Bytes;
 
+use crate::Secret;
+
 /// Identity/key for client PSK authentication.
 ///
 /// Defaults to None
 ///
 /// # Hint
 /// You should specify one of the PSK_* ciphers, i.e. PSK-AES128-CCM8
-#[derive(Clone)]
-pub struct PskIdentity(pub(crate) Bytes, pub(crate) Bytes);
+#[derive(Clone, Debug)]
+pub struct PskIdentity(pub(crate) Bytes, pub(crate) Secret);
 
 impl PskIdentity {
     pub fn new(identity: &[u8], key: &[u8]) -> PskIdentity {
-        PskIdentity(Bytes::from(identity), Bytes::from(key))
+        PskIdentity(Bytes::from(identity), Secret::from(key))
     }
 }
 
@@ -19,3 +21,18 @@ impl PskIdentity {
 pub enum ConnectorIdentity {
     Psk(PskIdentity),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_key_in_debug() {
+        let identity = PskIdentity::new(b"home-assistant", b"5Gb3dBlRUVy7DDqQ");
+        let debug = format!("{:?}", identity);
+
+        assert!(debug.contains("home-assistant"));
+        assert!(debug.contains("[REDACTED]"));
+        assert!(!debug.contains("5Gb3dBlRUVy7DDqQ"));
+    }
+}
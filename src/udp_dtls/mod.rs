@@ -1,9 +1,17 @@
 //! An rusty abstraction over OpenSSL DTLS.
 
+#[cfg(feature = "async")]
+mod async_udp_channel;
 mod certificate;
 mod certificate_fingerprint;
+#[cfg(feature = "dtls-server")]
+mod dtls_acceptor;
+#[cfg(feature = "dtls-server")]
+mod dtls_acceptor_builder;
 mod dtls_connection_builder;
 mod dtls_connector;
+#[cfg(feature = "dtls-server")]
+mod dtls_listener;
 mod dtls_stream;
 mod error;
 mod identity;
@@ -12,13 +20,21 @@ mod openssl;
 mod protocol;
 mod udp_channel;
 
+#[cfg(feature = "async")]
+pub(crate) use self::async_udp_channel::AsyncUdpChannel;
 pub(crate) use self::certificate::Certificate;
 pub(crate) use self::certificate_fingerprint::{CertificateFingerprint, SignatureAlgorithm};
+#[cfg(feature = "dtls-server")]
+pub use self::dtls_acceptor::DtlsAcceptor;
+#[cfg(feature = "dtls-server")]
+pub use self::dtls_acceptor_builder::{DtlsAcceptorBuilder, PskServerCallback};
 pub(crate) use self::dtls_connection_builder::DtlsConnectorBuilder;
 pub(crate) use self::dtls_connector::DtlsConnector;
-pub(crate) use self::dtls_stream::DtlsStream;
-pub(crate) use self::error::{Error, HandshakeError, Result};
+#[cfg(feature = "dtls-server")]
+pub use self::dtls_listener::{DtlsListener, PeerChannel};
+pub use self::dtls_stream::DtlsStream;
+pub use self::error::{Error, HandshakeError, Result};
 pub(crate) use self::identity::{ConnectorIdentity, PskIdentity};
-pub(crate) use self::midhandshake_dtls_steam::MidHandshakeDtlsStream;
-pub(crate) use self::protocol::Protocol;
+pub use self::midhandshake_dtls_steam::MidHandshakeDtlsStream;
+pub use self::protocol::Protocol;
 pub(crate) use self::udp_channel::UdpChannel;
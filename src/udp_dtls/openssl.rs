@@ -1,8 +1,10 @@
+use log::debug;
 use openssl::{
     error::ErrorStack,
     ssl::{SslContextBuilder, SslOptions},
 };
-use std::sync::Once;
+use openssl_probe::ProbeResult;
+use std::sync::OnceLock;
 
 use super::Protocol;
 
@@ -34,7 +36,22 @@ pub fn try_set_supported_protocols(
     Ok(())
 }
 
-pub fn init_trust() {
-    static ONCE: Once = Once::new();
-    ONCE.call_once(openssl_probe::init_ssl_cert_env_vars);
+/// Loads the root certificates of the system into the given
+/// `SslContextBuilder`.
+///
+/// The vendored OpenSSL does not know where the system keeps them. They are
+/// probed once, honouring `SSL_CERT_FILE` and `SSL_CERT_DIR`, and passed to
+/// each context instead of being exported to the environment of the process.
+pub fn load_trust(ctx: &mut SslContextBuilder) {
+    static PROBE: OnceLock<ProbeResult> = OnceLock::new();
+    let probe = PROBE.get_or_init(openssl_probe::probe);
+
+    if probe.cert_file.is_none() && probe.cert_dir.is_none() {
+        return;
+    }
+    if let Err(err) =
+        ctx.load_verify_locations(probe.cert_file.as_deref(), probe.cert_dir.as_deref())
+    {
+        debug!("load_verify_locations error: {:?}", err);
+    }
 }
@@ -0,0 +1,246 @@
+//! End to end tests against the simulated gateway over DTLS.
+
+use std::{
+    env, fs, process,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use serde_json::json;
+use tradfri_gateway::{
+    CredentialStore, Device, DtlsTransport, FileCredentialStore, GatewayAddr, LightSetting,
+    PairingError, PairingOptions, Simulator, SimulatorConfig, TradfriGateway, TradfriGatewayError,
+    TransmissionParameters,
+};
+
+const SECURITY_CODE: &str = "SimulatorCode001";
+
+/// Clients always connect to port 5684, so only one simulator can run at a
+/// time.
+static PORT: Mutex<()> = Mutex::new(());
+
+fn simulator(config: SimulatorConfig) -> (MutexGuard<'static, ()>, Simulator) {
+    let port = PORT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let simulator = Simulator::bind("127.0.0.1:5684", config).unwrap();
+
+    (port, simulator)
+}
+
+fn connect() -> TradfriGateway {
+    TradfriGateway::from_gateway_code_and_addr([127, 0, 0, 1], SECURITY_CODE).unwrap()
+}
+
+fn light_on(simulator: &Simulator, id: u32) -> bool {
+    let light = simulator.resource(&format!("15001/{}", id)).unwrap();
+    light["3311"][0]["5850"] == 1
+}
+
+#[test]
+fn pairs_with_security_code() {
+    let (_port, _simulator) = simulator(SimulatorConfig::demo());
+
+    let credentials = TradfriGateway::pair([127, 0, 0, 1], SECURITY_CODE).unwrap();
+    let gateway = TradfriGateway::from_credentials(&credentials).unwrap();
+    assert!(gateway.info().is_ok());
+
+    let malformed = TradfriGateway::pair([127, 0, 0, 1], "WrongSecurity01");
+    assert!(matches!(
+        malformed,
+        Err(TradfriGatewayError::PairingError(
+            PairingError::InvalidSecurityCode
+        ))
+    ));
+
+    // The gateway drops a handshake with the wrong code, so keep the wait short.
+    let options = PairingOptions::new().with_timeout(Duration::from_secs(2));
+    let rejected = TradfriGateway::pair_with([127, 0, 0, 1], "WrongSecurity012", &options);
+    assert!(matches!(
+        rejected,
+        Err(TradfriGatewayError::PairingError(
+            PairingError::WrongSecurityCode
+        ))
+    ));
+}
+
+#[test]
+fn connects_with_stored_credentials() {
+    let config = SimulatorConfig::demo().with_client("paired-client", "0123456789abcdef");
+    let (_port, _simulator) = simulator(config);
+
+    let gateway = TradfriGateway::from_identifier_and_session_key_and_addr(
+        [127, 0, 0, 1],
+        "paired-client",
+        "0123456789abcdef",
+    );
+    assert_eq!(gateway.groups().unwrap().count(), 2);
+}
+
+#[test]
+fn resends_requests_but_posts_after_gateway_restart() {
+    let config = SimulatorConfig::demo().with_client("paired-client", "0123456789abcdef");
+    let (_port, simulator) = simulator(config.clone());
+
+    // Give up on the lost session quickly rather than after the defaults.
+    let parameters = TransmissionParameters {
+        ack_timeout: Duration::from_millis(200),
+        ack_random_factor: 1.0,
+        max_retransmit: 1,
+        request_timeout: Duration::from_secs(20),
+    };
+    let gateway = TradfriGateway::from_transport(
+        DtlsTransport::new([127, 0, 0, 1], "paired-client", "0123456789abcdef")
+            .with_transmission_parameters(parameters),
+    );
+    assert!(gateway.info().is_ok());
+
+    simulator.stop();
+    let simulator = Simulator::bind("127.0.0.1:5684", config.clone()).unwrap();
+    assert!(gateway.info().is_ok());
+
+    // The gateway may have acted on a POST before the session was lost, so it
+    // is not sent again.
+    simulator.stop();
+    let simulator = Simulator::bind("127.0.0.1:5684", config).unwrap();
+    let scenes = simulator.resource("15005/131073");
+    assert!(matches!(
+        gateway.post("15005/131073", &json!({ "9001": "Evening" })),
+        Err(TradfriGatewayError::CoapError(_))
+    ));
+    assert_eq!(simulator.resource("15005/131073"), scenes);
+
+    assert!(gateway
+        .post("15005/131073", &json!({ "9001": "Evening" }))
+        .is_ok());
+    assert_ne!(simulator.resource("15005/131073"), scenes);
+}
+
+#[test]
+fn pairs_once_and_remembers_address() {
+    let (_port, _simulator) = simulator(SimulatorConfig::demo());
+    let path = env::temp_dir().join(format!("tradfri-simulator-{}.json", process::id()));
+    let store = FileCredentialStore::new(&path);
+
+    let gateway =
+        TradfriGateway::from_credential_store_and_addr(&store, [127, 0, 0, 1], SECURITY_CODE);
+    let stored = store.load();
+    // Reconnects with the stored credentials and address, a wrong security
+    // code would fail pairing.
+    let reconnected = TradfriGateway::from_credential_store(&store, "WrongSecurity01")
+        .and_then(|gateway| gateway.info());
+    fs::remove_file(&path).unwrap();
+
+    assert!(gateway.unwrap().info().is_ok());
+    let stored = stored.unwrap().unwrap();
+    assert_eq!(stored.address, Some(GatewayAddr::from([127, 0, 0, 1])));
+    assert!(reconnected.is_ok());
+}
+
+#[test]
+fn lists_devices_and_groups() {
+    let (_port, _simulator) = simulator(SimulatorConfig::demo());
+    let gateway = connect();
+
+    let lights = gateway
+        .devices()
+        .unwrap()
+        .map(|device| device.unwrap())
+        .filter(|device| matches!(device, Device::Light(_)))
+        .count();
+    assert_eq!(lights, 3);
+
+    let mut groups: Vec<u32> = gateway
+        .groups()
+        .unwrap()
+        .map(|group| group.unwrap().id())
+        .collect();
+    groups.sort_unstable();
+    assert_eq!(groups, [131073, 131074]);
+
+    assert!(matches!(
+        gateway.group(1),
+        Err(TradfriGatewayError::NotFound(..))
+    ));
+}
+
+#[test]
+fn switches_lights_and_groups() {
+    let (_port, simulator) = simulator(SimulatorConfig::demo());
+    let gateway = connect();
+
+    let mut light = match gateway.device(65538).unwrap() {
+        Device::Light(light) => light,
+        device => panic!("expected a light, got {:?}", device),
+    };
+    light.on().unwrap();
+    assert!(light.is_on());
+    assert!(light_on(&simulator, 65538));
+
+    let mut kitchen = gateway.group(131073).unwrap();
+    kitchen.off().unwrap();
+    assert!(!light_on(&simulator, 65537));
+    assert!(!light_on(&simulator, 65538));
+
+    kitchen.on().unwrap();
+    assert!(light_on(&simulator, 65537));
+    assert!(light_on(&simulator, 65538));
+    assert!(!light_on(&simulator, 65539));
+}
+
+#[test]
+fn creates_and_activates_scenes() {
+    let (_port, simulator) = simulator(SimulatorConfig::demo());
+    let gateway = connect();
+
+    let scene = gateway
+        .create_scene(
+            131073,
+            "Evening",
+            vec![
+                LightSetting::new(65537, true).with_brightness(77),
+                LightSetting::new(65538, false),
+            ],
+        )
+        .unwrap();
+    assert_eq!(scene.name(), "Evening");
+    assert_eq!(scene.group_id(), 131073);
+    assert_eq!(gateway.scenes(131073).unwrap().count(), 2);
+
+    gateway.group(131073).unwrap().off().unwrap();
+    scene.activate().unwrap();
+
+    let light = simulator.resource("15001/65537").unwrap();
+    assert_eq!(light["3311"][0]["5850"], 1);
+    assert_eq!(light["3311"][0]["5851"], 77);
+    assert!(!light_on(&simulator, 65538));
+}
+
+/// Pairs and talks to the gateway over the non-blocking DTLS transport.
+#[cfg(feature = "async")]
+#[test]
+fn async_gateway_pairs_and_switches_lights() {
+    use tradfri_gateway::{AsyncDevice, AsyncTradfriGateway};
+
+    let (_port, simulator) = simulator(SimulatorConfig::demo());
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    runtime.block_on(async {
+        let gateway =
+            AsyncTradfriGateway::from_gateway_code_and_addr([127, 0, 0, 1], SECURITY_CODE)
+                .await
+                .unwrap();
+        assert_eq!(gateway.groups().await.unwrap().len(), 2);
+
+        let mut light = match gateway.device(65538).await.unwrap() {
+            AsyncDevice::Light(light) => light,
+            device => panic!("expected a light, got {:?}", device),
+        };
+        light.on().await.unwrap();
+        assert!(light.is_on());
+        assert!(light_on(&simulator, 65538));
+
+        let mut kitchen = gateway.group(131073).await.unwrap();
+        kitchen.off().await.unwrap();
+        assert!(!light_on(&simulator, 65537));
+        assert!(!light_on(&simulator, 65538));
+    });
+}